@@ -0,0 +1,75 @@
+// Allocations and time per line when parsing a full `Message` versus only its
+// borrowed `Envelope`. Run with `cargo bench --bench parse`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use maelstrom_client::message::{Envelope, Message};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ITERATIONS: usize = 100_000;
+
+const LINES: &[(&str, &str)] = &[
+    (
+        "broadcast",
+        r#"{"src":"c12","dest":"n3","body":{"type":"broadcast","msg_id":1842,"message":4711}}"#,
+    ),
+    (
+        "broadcast_many",
+        r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","msg_id":77,"messages":[1,2,3,4,5,6,7,8]}}"#,
+    ),
+    (
+        "broadcast_ok",
+        r#"{"src":"n2","dest":"n1","body":{"type":"broadcast_many_ok","in_reply_to":77}}"#,
+    ),
+];
+
+fn measure<F: Fn(&str)>(line: &str, parse: F) -> (f64, f64) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        parse(black_box(line));
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / ITERATIONS as f64;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    (allocations as f64 / ITERATIONS as f64, nanos)
+}
+
+fn main() {
+    println!(
+        "{:<16} {:>14} {:>10} {:>14} {:>10}",
+        "line", "message alloc", "ns", "envelope alloc", "ns"
+    );
+    for (name, line) in LINES {
+        let (full_allocs, full_nanos) = measure(line, |line| {
+            black_box(serde_json::from_str::<Message>(line).unwrap());
+        });
+        let (envelope_allocs, envelope_nanos) = measure(line, |line| {
+            black_box(Envelope::parse(line).unwrap());
+        });
+        println!(
+            "{name:<16} {full_allocs:>14.1} {full_nanos:>10.0} {envelope_allocs:>14.1} {envelope_nanos:>10.0}"
+        );
+    }
+}
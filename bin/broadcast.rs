@@ -0,0 +1,76 @@
+use std::{io, sync::Arc};
+
+use maelstrom_client::{
+    broadcast::{
+        strategy::{
+            AggregatedAcks, Batched, Digest, GossipOrder, Immediate, NewestFirst, OldestFirst,
+            Overlay,
+        },
+        BroadcastApp, Strategy,
+    },
+    checksum::exchange_checksums,
+    maelstrom::{Maelstrom, Startup},
+};
+
+// fanout of the spanning tree used by the `tree` strategy
+const TREE_FANOUT: usize = 4;
+
+// usage: broadcast [--strategy immediate|batched|digest|tree|star|aggregated] [--order newest|oldest]
+fn strategy_from_args() -> io::Result<Box<dyn Strategy>> {
+    let mut strategy = "batched".to_owned();
+    let mut order = "newest".to_owned();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| io::Error::other(format!("missing value for {arg}")))?;
+        match arg.as_str() {
+            "--strategy" => strategy = value,
+            "--order" => order = value,
+            _ => return Err(io::Error::other(format!("unknown argument {arg}"))),
+        }
+    }
+
+    let order: Box<dyn GossipOrder> = match order.as_str() {
+        "newest" => Box::new(NewestFirst),
+        "oldest" => Box::new(OldestFirst),
+        _ => return Err(io::Error::other(format!("unknown order {order}"))),
+    };
+
+    let strategy: Box<dyn Strategy> = match strategy.as_str() {
+        "immediate" => Box::new(Immediate),
+        "batched" => Box::new(Batched::new(Overlay::Topology, order)),
+        "digest" => Box::new(Digest::default()),
+        "tree" => Box::new(Batched::new(
+            Overlay::Tree {
+                fanout: TREE_FANOUT,
+            },
+            order,
+        )),
+        "star" => Box::new(Batched::new(Overlay::Star, order)),
+        "aggregated" => Box::new(AggregatedAcks::new(Overlay::Topology)),
+        _ => return Err(io::Error::other(format!("unknown strategy {strategy}"))),
+    };
+    Ok(strategy)
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let app = Arc::new(BroadcastApp::new(strategy_from_args()?));
+    let maelstrom = Maelstrom::new();
+
+    // periodically broadcast data of the current node, once it knows its neighbours
+    let gossip = app.clone();
+    maelstrom.spawn_after(Startup::Topology, move |maelstrom| {
+        gossip.run_gossip(maelstrom)
+    });
+
+    // compare states with the other nodes whenever they settle
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
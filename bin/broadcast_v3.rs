@@ -0,0 +1,38 @@
+use std::{io, sync::Arc, time::Duration};
+
+use maelstrom_client::{
+    broadcast::{
+        strategy::{Batched, NewestFirst, Overlay},
+        BroadcastApp,
+    },
+    checksum::exchange_checksums,
+    maelstrom::{Maelstrom, Startup},
+};
+
+// Challenge 3d, efficient broadcast: 25 nodes behind 100ms links have to stay
+// under 30 msgs-per-op with a median latency below 400ms and a max below 600ms.
+//
+// Every node batches its new messages to a single hub, which batches them on
+// to everyone else, so a message is two hops from any node: 200ms on the wire
+// plus up to one gossip period of waiting at each hop. Only deltas are sent,
+// one broadcast_many and its ack per peer and tick with anything new.
+const GOSSIP_PERIOD: Duration = Duration::from_millis(90);
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let strategy = Batched::new(Overlay::Star, Box::new(NewestFirst)).with_period(GOSSIP_PERIOD);
+    let app = Arc::new(BroadcastApp::new(Box::new(strategy)));
+    let maelstrom = Maelstrom::new();
+
+    let gossip = app.clone();
+    maelstrom.spawn_after(Startup::Topology, move |maelstrom| {
+        gossip.run_gossip(maelstrom)
+    });
+
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
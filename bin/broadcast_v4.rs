@@ -0,0 +1,37 @@
+use std::{io, sync::Arc, time::Duration};
+
+use maelstrom_client::{
+    broadcast::{
+        strategy::{AggregatedAcks, Overlay},
+        BroadcastApp,
+    },
+    checksum::exchange_checksums,
+    maelstrom::{Maelstrom, Startup},
+};
+
+// Challenge 3e, efficient broadcast part II: at most 20 msgs-per-op with a
+// median latency below 1s and a max below 2s, 25 nodes behind 100ms links.
+//
+// Trades latency for bandwidth compared to broadcast-v3: the same hub and
+// spokes, but batches wait longer and go out one way, with acknowledgements
+// aggregated into the batches flowing back instead of a reply per batch.
+const GOSSIP_PERIOD: Duration = Duration::from_millis(350);
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let strategy = AggregatedAcks::new(Overlay::Star).with_period(GOSSIP_PERIOD);
+    let app = Arc::new(BroadcastApp::new(Box::new(strategy)));
+    let maelstrom = Maelstrom::new();
+
+    let gossip = app.clone();
+    maelstrom.spawn_after(Startup::Topology, move |maelstrom| {
+        gossip.run_gossip(maelstrom)
+    });
+
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
@@ -0,0 +1,76 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    checksum::{exchange_checksums, ChecksumExchange, StateChecksum},
+    gossip_set::GossipSet,
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::*,
+};
+
+struct GSetApp {
+    set: Arc<GossipSet<i64>>,
+    checksums: ChecksumExchange,
+}
+
+#[async_trait]
+impl StateChecksum for GSetApp {
+    async fn state_checksum(&self) -> u64 {
+        self.set.checksum().await
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+#[async_trait]
+impl App for GSetApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        if let Some(ack) = self.set.on_message(&request).await {
+            return Ok(Some(ack));
+        }
+
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Element { element }) => {
+                self.set.insert([*element]).await;
+                MessageBody::with_type(MessageType::AddOk)
+            }
+            MessageType::Read(ReadRequest::Client) => {
+                let mut elements: Vec<i64> = self.set.elements().await.into_iter().collect();
+                elements.sort_unstable();
+                MessageBody::read_ok_value(elements)
+            }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.set.checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                return Ok(None);
+            }
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(Some(body))
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        self.set.flush(&maelstrom).await?;
+        self.checksums.finish(&maelstrom, self.set.checksum().await)
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let set = Arc::new(GossipSet::new("g-set"));
+    let app = Arc::new(GSetApp {
+        set: set.clone(),
+        checksums: Default::default(),
+    });
+    let maelstrom = Maelstrom::new();
+
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| set.run(maelstrom));
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
@@ -1,84 +1,137 @@
 use std::{
     collections::HashMap,
     io,
-    sync::{
-        atomic::{AtomicI64, Ordering},
-        Arc,
-    },
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    checksum::{checksum, exchange_checksums, ChecksumExchange, StateChecksum},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
     message::*,
 };
-use tokio::sync::OnceCell;
 
 #[derive(Default)]
 struct GrowOnlyCounterApp {
-    counters: OnceCell<HashMap<String, AtomicI64>>,
+    // counter of each node in the network, created on its first add or broadcast
+    counters: Mutex<HashMap<NodeId, i64>>,
+    checksums: ChecksumExchange,
+}
+
+impl GrowOnlyCounterApp {
+    fn checksum(&self) -> u64 {
+        checksum(self.counters.lock().unwrap().iter())
+    }
 }
 
 #[async_trait]
-impl App for GrowOnlyCounterApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()> {
-        // get counters for each node in the network
-        let counters = self
-            .counters
-            .get_or_init(|| async {
-                let mut counters = HashMap::new();
-                for node_id in maelstrom.node_ids() {
-                    counters.insert(node_id, AtomicI64::new(0));
-                }
-                counters
-            })
-            .await;
+impl StateChecksum for GrowOnlyCounterApp {
+    async fn state_checksum(&self) -> u64 {
+        self.checksum()
+    }
 
-        match &request.body.msg_type {
-            MessageType::Add { delta } => {
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+#[async_trait]
+impl App for GrowOnlyCounterApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Delta { delta }) if *delta < 0 => {
+                // a max merge would drop the decrement on other nodes, see pn-counter
+                Some(MessageBody::malformed_request(format!(
+                    "grow-only counter can't add negative delta {delta}"
+                )))
+            }
+            MessageType::Add(AddRequest::Delta { delta }) => {
                 // update counter of the current node
-                let old = counters
-                    .get(&request.dest)
-                    .unwrap()
-                    .fetch_add(*delta, Ordering::Relaxed);
-                let message = old + *delta;
+                let message = {
+                    let mut counters = self.counters.lock().unwrap();
+                    let counter = counters.entry(request.dest.to_owned()).or_default();
+                    *counter += *delta;
+                    *counter
+                };
 
-                maelstrom.reply(request, MessageBody::with_type(MessageType::AddOk))?;
+                ctx.reply(MessageBody::with_type(MessageType::AddOk))?;
 
                 // broadcast current node value to other nodes in the network
-                let body = MessageBody::with_type(MessageType::Broadcast { message });
-                for dest in maelstrom.node_ids() {
-                    if dest.ne(maelstrom.node_id()) {
-                        let _ = maelstrom.send(dest.to_owned(), body.clone());
-                    }
+                let body = MessageBody::with_type(MessageType::Broadcast {
+                    message: message.into(),
+                });
+                for dest in ctx.other_node_ids() {
+                    let _ = ctx.send(dest, body.clone());
                 }
+                None
             }
-            #[allow(unused_variables)]
-            MessageType::Read { key } => {
+            MessageType::Read(ReadRequest::Client) => {
                 // read and add counter values of all nodes
-                let value = counters.values().map(|a| a.load(Ordering::Relaxed)).sum();
-                let body = MessageBody::with_type(MessageType::ReadOk {
-                    messages: None,
-                    value: Some(Value::Int(value)),
-                });
-
-                maelstrom.reply(request, body)?;
+                let value: i64 = self.counters.lock().unwrap().values().sum();
+                Some(MessageBody::read_ok_value(value))
             }
             MessageType::Broadcast { message } => {
                 // update counter of the node which sent this broadcast
-                counters
-                    .get(&request.src)
-                    .unwrap()
-                    .fetch_max(*message, Ordering::Relaxed);
+                if let Some(value) = message.as_i64() {
+                    let mut counters = self.counters.lock().unwrap();
+                    let counter = counters.entry(request.src.to_owned()).or_default();
+                    *counter = (*counter).max(value);
+                }
+                None
             }
-            _ => {}
-        }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.checksum();
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                None
+            }
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(body)
+    }
+
+    // nodes that left the network no longer count
+    async fn reinit(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        let node_ids = maelstrom.node_ids();
+        self.counters
+            .lock()
+            .unwrap()
+            .retain(|node_id, _| node_ids.contains(node_id));
         Ok(())
     }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        // flush the final value of the current node to other nodes in the network
+        let message = self
+            .counters
+            .lock()
+            .unwrap()
+            .get(maelstrom.node_id())
+            .copied()
+            .unwrap_or_default();
+        let body = MessageBody::with_type(MessageType::Broadcast {
+            message: message.into(),
+        });
+        for dest in maelstrom.other_node_ids() {
+            maelstrom.send(dest, body.clone())?;
+        }
+
+        self.checksums.finish(&maelstrom, self.checksum())
+    }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     let app = Arc::new(GrowOnlyCounterApp::default());
-    Maelstrom::new().run_with_app(app).await
+    // client retries must not apply the same operation twice
+    let maelstrom = Maelstrom::builder()
+        .dedup_requests(Some(Duration::from_secs(30)))
+        .build();
+
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
 }
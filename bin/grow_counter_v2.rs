@@ -1,87 +1,61 @@
-use std::{io, sync::Arc};
+use std::{io, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    kv::{Kv, KvCounter, KvService},
+    maelstrom::{not_supported, App, Context, Maelstrom},
     message::*,
 };
 use tokio::sync::Mutex;
 
-#[derive(Default)]
 struct GrowOnlyCounterApp {
     lock: Mutex<()>,
+    kv: Kv,
 }
 
-impl GrowOnlyCounterApp {
-    // read from lin-kv store
-    #[allow(unused_variables)]
-    async fn read(&self, maelstrom: &Maelstrom, key: String) -> io::Result<Value> {
-        let body = MessageBody::with_type(MessageType::Read { key: Some(key) });
-        let response = maelstrom.rpc("seq-kv".to_owned(), body, false).await?;
-
-        let value = match response.body.msg_type {
-            MessageType::ReadOk { messages, value } => value.unwrap(),
-            _ => Value::None,
-        };
-        Ok(value)
-    }
-
-    // write to lin-kv store
-    async fn write(&self, maelstrom: &Maelstrom, key: String, value: Value) -> io::Result<()> {
-        let body = MessageBody::with_type(MessageType::Write {
-            key: key.to_owned(),
-            value,
-        });
-        maelstrom.rpc("seq-kv".to_owned(), body, false).await?;
-        Ok(())
-    }
+// seq-kv entry holding the counter of a node
+fn counter_key(node_id: &NodeId) -> KvKey {
+    KvKey(node_id.to_string())
 }
 
 #[async_trait]
 impl App for GrowOnlyCounterApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()> {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
         let _lock_gaurd = self.lock.lock().await;
 
-        match &request.body.msg_type {
-            MessageType::Add { delta } => {
-                let key = maelstrom.node_id();
-                let value = self
-                    .read(&maelstrom, key.to_owned())
-                    .await?
-                    .as_int()
-                    .unwrap_or_default();
-                let _ = self
-                    .write(&maelstrom, key.to_owned(), Value::Int(value + *delta))
-                    .await;
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Delta { delta }) => {
+                // every node adds to its own counter, the adds never contend
+                let counter = KvCounter::new(self.kv.clone(), counter_key(ctx.node_id()));
+                counter.add(*delta).await?;
 
-                maelstrom.reply(request, MessageBody::with_type(MessageType::AddOk))?;
+                Some(MessageBody::with_type(MessageType::AddOk))
             }
-            #[allow(unused_variables)]
-            MessageType::Read { key } => {
+            MessageType::Read(ReadRequest::Client) => {
                 // read and add counter values of all nodes
-                let mut sum = 0;
-                for node_id in maelstrom.node_ids() {
-                    sum += self
-                        .read(&maelstrom, node_id)
-                        .await?
-                        .as_int()
-                        .unwrap_or_default();
-                }
+                let keys = ctx.node_ids().iter().map(counter_key).collect::<Vec<_>>();
+                let sum = KvCounter::sum(&self.kv, keys).await?;
 
-                let body = MessageBody::with_type(MessageType::ReadOk {
-                    messages: None,
-                    value: Some(Value::Int(sum)),
-                });
-                maelstrom.reply(request, body)?;
+                Some(MessageBody::read_ok_value(sum))
             }
-            _ => {}
-        }
-        Ok(())
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(body)
     }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let app = Arc::new(GrowOnlyCounterApp::default());
-    Maelstrom::new().run_with_app(app).await
+    // client retries must not apply the same operation twice
+    let maelstrom = Maelstrom::builder()
+        .dedup_requests(Some(Duration::from_secs(30)))
+        .build();
+    let app = Arc::new(GrowOnlyCounterApp {
+        lock: Default::default(),
+        // each node only writes its own counter, so cached reads are safe
+        kv: Kv::configured(maelstrom.clone(), KvService::Seq)
+            .with_configured_cache()
+            .scoped("counter"),
+    });
+    maelstrom.run_with_app(app).await
 }
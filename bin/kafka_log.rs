@@ -1,163 +1,173 @@
-use std::{collections::HashMap, io, sync::Arc};
+use std::{collections::HashMap, io, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    kv::{Kv, KvService, WriteBatcher},
+    lock::{DistributedLock, LockGuard},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
     message::*,
+    sync::TrackedMutex,
 };
-use tokio::sync::Mutex;
+use serde_json::Value as Json;
 
-#[derive(Default)]
 struct KafkaLogApp {
-    lock: Mutex<()>,
+    lock: TrackedMutex<()>,
+    distributed_lock: DistributedLock,
+    // lin-kv entries holding the messages of each log, keyed by log key
+    logs: Kv,
+    // lin-kv entries holding the committed offset of each log, keyed by log key
+    offsets: Kv,
+    // Commits queued for `offsets` and stored in batches. Appends can't be
+    // queued, their offset comes from the stored log.
+    commits: Arc<WriteBatcher>,
 }
 
 impl KafkaLogApp {
-    async fn distributed_lock(&self, maelstrom: &Maelstrom, lock: bool) -> io::Result<()> {
-        let (from, to) = if lock {
-            (Value::None, Value::String(maelstrom.node_id().to_string()))
-        } else {
-            (Value::String(maelstrom.node_id().to_string()), Value::None)
-        };
-
-        loop {
-            let body = MessageBody::with_type(MessageType::Cas {
-                key: "lock".to_string(),
-                from: from.to_owned(),
-                to: to.to_owned(),
-                create_if_not_exists: Some(true),
-            });
-            let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-            match response.body.msg_type {
-                MessageType::CasOk => break,
-                _ => {}
-            };
-        }
-
-        Ok(())
+    // messages of a log, None before its first send
+    async fn read_log(&self, key: &LogKey) -> io::Result<Option<Vec<Json>>> {
+        Ok(self.logs.read_option(key.to_string()).await?)
     }
+}
 
-    // read from lin-kv store
-    #[allow(unused_variables)]
-    async fn read(&self, maelstrom: &Maelstrom, key: &str) -> io::Result<Value> {
-        let body = MessageBody::with_type(MessageType::Read {
-            key: Some(key.to_owned()),
-        });
-        let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-
-        let value = match response.body.msg_type {
-            MessageType::ReadOk { messages, value } => value.unwrap(),
-            _ => Value::None,
-        };
-        Ok(value)
+// A committed offset only moves forward, so batches merge by max. That makes
+// a store that is retried after a timeout harmless, and lets every node commit
+// without the lock.
+fn max_offset(older: Json, newer: Json) -> Json {
+    match (older.as_u64(), newer.as_u64()) {
+        (Some(older), Some(newer)) if older > newer => older.into(),
+        _ => newer,
     }
+}
 
-    // write to lin-kv store
-    async fn write(&self, maelstrom: &Maelstrom, key: String, value: Value) -> io::Result<()> {
-        let body = MessageBody::with_type(MessageType::Write {
-            key: key.to_owned(),
-            value,
-        });
-        maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-        Ok(())
+// Fails once the lease of the guard was lost, before anything is written under
+// it. The check is local and only advisory, the lease may run out right after
+// it, so the writes themselves are cas'd from what was read under the lock.
+fn fenced(guard: &LockGuard) -> io::Result<()> {
+    if guard.is_held() {
+        return Ok(());
     }
+    Err(io::Error::other(format!(
+        "lock lost (token {}), another node may be writing",
+        guard.token()
+    )))
 }
 
 #[async_trait]
 impl App for KafkaLogApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()> {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
         let _lock = self.lock.lock().await;
 
         // we acquire distributed lock only if we have write to lin-kv store
-        match &request.body.msg_type {
+        let body = match &request.body.msg_type {
             MessageType::Send { key, msg } => {
                 // acquire distributed lock
-                self.distributed_lock(&maelstrom, true).await?;
+                let guard = self.distributed_lock.lock().await?;
 
                 // read data for key from lin-kv, append new msg to key and write back to lin-kv store
                 // offset will be index of new msg in the list
-                let mut data = self
-                    .read(&maelstrom, key)
-                    .await?
-                    .as_vec()
-                    .unwrap_or_default();
-                let offset = data.len() as i64;
-                data.push(*msg as i64);
-                self.write(&maelstrom, key.to_owned(), Value::Vec(data))
+                let current = self.read_log(key).await?;
+                let mut data = current.clone().unwrap_or_default();
+                let offset = Offset(data.len() as u64);
+                data.push(msg.clone());
+
+                // A holder whose lease ran out may still get here, so the log is
+                // swapped from what was read rather than overwritten: an append
+                // of the next holder makes the swap fail instead of being lost.
+                fenced(&guard)?;
+                self.logs
+                    .cas(key.to_string(), &current, &Some(data), true)
                     .await?;
 
+                let metrics = ctx.metrics();
+                metrics.incr_key("kafka.send", key, 1);
+                metrics.max_key("kafka.log_length", key, offset.0 + 1);
+
+                // reply before releasing the lock, the client doesn't need to wait for it
                 let body = MessageBody::with_type(MessageType::SendOk { offset });
-                let _ = maelstrom.reply(request, body);
+                let _ = ctx.reply(body);
 
                 // release distributed lock
-                self.distributed_lock(&maelstrom, false).await?;
+                guard.release().await?;
+                None
             }
             MessageType::Poll { offsets } => {
                 let mut msgs = HashMap::new();
 
-                // read data for each key from lin-kv store and convert the data to required format
+                // read data for each key from lin-kv store concurrently and convert the data to required format
+                let keys = offsets.keys().map(ToString::to_string);
+                let mut logs = self.logs.multi_get::<Vec<Json>>(keys).await?;
                 for (key, offset) in offsets {
-                    if let Some(data) = self.read(&maelstrom, key).await?.as_vec() {
-                        let data: Vec<[i64; 2]> = data
-                            .into_iter()
-                            .enumerate()
-                            .filter(|(idx, _)| *idx as i64 >= *offset)
-                            .map(|(idx, value)| [idx as i64, value])
-                            .collect();
-
-                        msgs.insert(key.to_owned(), data);
-                    }
+                    ctx.metrics().incr_key("kafka.poll", key, 1);
+                    let Some(data) = logs.remove(&KvKey(key.to_string())) else {
+                        continue;
+                    };
+                    let data: Vec<(Offset, Json)> = data
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, value)| (Offset(idx as u64), value))
+                        .filter(|(idx, _)| idx >= offset)
+                        .collect();
+
+                    msgs.insert(key.to_owned(), data);
                 }
 
                 let body = MessageBody::with_type(MessageType::PollOk { msgs });
-                maelstrom.reply(request, body)?;
+                Some(body)
             }
             MessageType::CommitOffsets { offsets } => {
-                // acquire distributed lock
-                self.distributed_lock(&maelstrom, true).await?;
-
-                // read commited offset for each key from lin-kv and update if the new offset is greater
+                // queue the new offsets, they are stored with the next batch
                 for (key, offset) in offsets {
-                    let key = format!("{key}-commited");
-                    let last_comitted_offset =
-                        self.read(&maelstrom, &key).await?.as_int().unwrap_or(-1);
-
-                    if last_comitted_offset < *offset {
-                        self.write(&maelstrom, key.to_owned(), Value::Int(*offset))
-                            .await?;
-                    }
+                    self.commits.write(key.to_string(), &offset.0).await?;
                 }
-
-                maelstrom.reply(
-                    request,
-                    MessageBody::with_type(MessageType::CommitOffsetsOk),
-                )?;
-
-                // release distributed lock
-                self.distributed_lock(&maelstrom, false).await?;
+                Some(MessageBody::with_type(MessageType::CommitOffsetsOk))
             }
             MessageType::ListCommittedOffsets { keys } => {
-                let mut offsets = HashMap::new();
-
-                // read commited offset for each key from lin-kv store
-                for key in keys {
-                    let key = format!("{key}-commited");
-                    if let Some(offset) = self.read(&maelstrom, &key).await?.as_int() {
-                        offsets.insert(key.to_owned(), offset);
-                    }
-                }
+                // store queued commits first, so a node lists its own
+                self.commits.flush().await?;
+
+                // read commited offset for each key from lin-kv store concurrently
+                let committed = self
+                    .offsets
+                    .multi_get::<u64>(keys.iter().map(ToString::to_string))
+                    .await?;
+                let offsets = keys
+                    .iter()
+                    .filter_map(|key| {
+                        let offset = committed.get(&KvKey(key.to_string()))?;
+                        Some((key.to_owned(), Offset(*offset)))
+                    })
+                    .collect();
 
                 let body = MessageBody::with_type(MessageType::ListCommittedOffsetsOk { offsets });
-                maelstrom.reply(request, body)?;
+                Some(body)
             }
-            _ => {}
-        }
-        Ok(())
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(body)
+    }
+
+    async fn shutdown(&self, _maelstrom: Maelstrom) -> io::Result<()> {
+        Ok(self.commits.flush().await?)
     }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let app = Arc::new(KafkaLogApp::default());
-    Maelstrom::new().run_with_app(app).await
+    // client retries must not apply the same operation twice
+    let maelstrom = Maelstrom::builder()
+        .dedup_requests(Some(Duration::from_secs(30)))
+        .build();
+    let kv = Kv::configured(maelstrom.clone(), KvService::Lin).scoped("kafka");
+    let offsets = kv.scoped("offsets");
+    let commits = Arc::new(WriteBatcher::new(offsets.clone()).with_merge(max_offset));
+    let app = Arc::new(KafkaLogApp {
+        lock: Default::default(),
+        distributed_lock: DistributedLock::new(maelstrom.clone(), kv.full_key("lock")),
+        logs: kv.scoped("log"),
+        offsets,
+        commits: commits.clone(),
+    });
+
+    maelstrom.spawn_after(Startup::Init, move |_| commits.run());
+    maelstrom.run_with_app(app).await
 }
@@ -0,0 +1,103 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    checksum::{exchange_checksums, ChecksumExchange, StateChecksum},
+    hlc::HybridClock,
+    lww::{LwwMap, Timestamps},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::*,
+    tso::Tso,
+};
+
+// Map of last-write-wins registers served with the kv protocol: `write` sets
+// the register of a key, `read` returns the newest write this node knows of.
+struct LwwRegisterApp {
+    map: Arc<LwwMap>,
+    checksums: ChecksumExchange,
+}
+
+#[async_trait]
+impl StateChecksum for LwwRegisterApp {
+    async fn state_checksum(&self) -> u64 {
+        self.map.checksum().await
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+#[async_trait]
+impl App for LwwRegisterApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        if let Some(ack) = self.map.on_message(&request).await {
+            return Ok(Some(ack));
+        }
+
+        let body = match &request.body.msg_type {
+            MessageType::Write { key, value } => {
+                self.map
+                    .write(&ctx, key.to_owned(), value.to_owned())
+                    .await?;
+                MessageBody::with_type(MessageType::WriteOk)
+            }
+            MessageType::Read(ReadRequest::Kv { key }) => match self.map.read(key).await {
+                Some(value) => MessageBody::read_ok_value(value),
+                None => MessageBody::key_does_not_exist(format!("key {key} does not exist")),
+            },
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.map.checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                return Ok(None);
+            }
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(Some(body))
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        self.map.flush(&maelstrom).await?;
+        self.checksums.finish(&maelstrom, self.map.checksum().await)
+    }
+}
+
+// usage: lww-register [--clock hlc|tso]
+fn timestamps_from_args(maelstrom: &Maelstrom) -> io::Result<Timestamps> {
+    let mut clock = "hlc".to_owned();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| io::Error::other(format!("missing value for {arg}")))?;
+        match arg.as_str() {
+            "--clock" => clock = value,
+            _ => return Err(io::Error::other(format!("unknown argument {arg}"))),
+        }
+    }
+
+    match clock.as_str() {
+        "hlc" => Ok(Timestamps::Hybrid(HybridClock::new(maelstrom.clock()))),
+        "tso" => Ok(Timestamps::Tso(Tso::new(maelstrom.clone()))),
+        _ => Err(io::Error::other(format!("unknown clock {clock}"))),
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let maelstrom = Maelstrom::new();
+    let map = Arc::new(LwwMap::new(timestamps_from_args(&maelstrom)?));
+    let app = Arc::new(LwwRegisterApp {
+        map: map.clone(),
+        checksums: Default::default(),
+    });
+
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| map.run(maelstrom));
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
@@ -0,0 +1,84 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    checksum::{exchange_checksums, ChecksumExchange, StateChecksum},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::*,
+    or_set::OrSet,
+};
+
+// Set of integers with adds and removes, replicated as an observed-remove set.
+// A remove only takes back the adds its node has seen, an add concurrent with
+// it on another node wins.
+struct OrSetApp {
+    set: Arc<OrSet<i64>>,
+    checksums: ChecksumExchange,
+}
+
+#[async_trait]
+impl StateChecksum for OrSetApp {
+    async fn state_checksum(&self) -> u64 {
+        self.set.checksum().await
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+#[async_trait]
+impl App for OrSetApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        if let Some(ack) = self.set.on_message(&request).await {
+            return Ok(Some(ack));
+        }
+
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Element { element }) => {
+                self.set.add(&ctx, *element).await;
+                MessageBody::with_type(MessageType::AddOk)
+            }
+            // removing an element that isn't there is a no-op
+            MessageType::Remove { element } => {
+                self.set.remove(element).await;
+                MessageBody::with_type(MessageType::RemoveOk)
+            }
+            MessageType::Read(ReadRequest::Client) => {
+                let mut elements: Vec<i64> = self.set.elements().await.into_iter().collect();
+                elements.sort_unstable();
+                MessageBody::read_ok_value(elements)
+            }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.set.checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                return Ok(None);
+            }
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(Some(body))
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        self.set.flush(&maelstrom).await?;
+        self.checksums.finish(&maelstrom, self.set.checksum().await)
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let set = Arc::new(OrSet::new("or-set"));
+    let app = Arc::new(OrSetApp {
+        set: set.clone(),
+        checksums: Default::default(),
+    });
+    let maelstrom = Maelstrom::new();
+
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| set.run(maelstrom));
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    checksum::{checksum, exchange_checksums, ChecksumExchange, StateChecksum},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::*,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+// full state is resent this often, so counts lost on the way catch up
+const GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
+
+// Increments and decrements applied on one node. Both only ever grow, so two
+// copies are merged by taking the max of each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash, Serialize, Deserialize)]
+struct Counts {
+    inc: u64,
+    dec: u64,
+}
+
+impl Counts {
+    fn add(&mut self, delta: i64) {
+        if delta >= 0 {
+            self.inc += delta as u64;
+        } else {
+            self.dec += delta.unsigned_abs();
+        }
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.inc = self.inc.max(other.inc);
+        self.dec = self.dec.max(other.dec);
+    }
+
+    fn value(&self) -> i64 {
+        self.inc as i64 - self.dec as i64
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "pn_counts")]
+struct CountsBody {
+    counts: HashMap<NodeId, Counts>,
+}
+
+impl CountsBody {
+    fn into_body(self) -> MessageBody {
+        // serializing a tagged struct into a body can't fail
+        MessageBody::from_custom(&self).expect("pn counts body")
+    }
+}
+
+#[derive(Default)]
+struct PnCounterApp {
+    counts: Mutex<HashMap<NodeId, Counts>>,
+    checksums: ChecksumExchange,
+}
+
+#[async_trait]
+impl StateChecksum for PnCounterApp {
+    async fn state_checksum(&self) -> u64 {
+        checksum(self.counts.lock().await.iter())
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+impl PnCounterApp {
+    // sends the counts of every node known here to all other nodes
+    async fn gossip(&self, maelstrom: &Maelstrom) -> io::Result<()> {
+        let counts = self.counts.lock().await.clone();
+        if counts.is_empty() {
+            return Ok(());
+        }
+        let body = CountsBody { counts }.into_body();
+        for dest in maelstrom.other_node_ids() {
+            maelstrom.send(dest, body.clone())?;
+        }
+        Ok(())
+    }
+
+    async fn run_gossip(self: Arc<Self>, maelstrom: Maelstrom) {
+        let mut interval = maelstrom.interval(GOSSIP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.gossip(&maelstrom).await {
+                maelstrom.log(format!("Error: gossiping counts failed: {e}"));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl App for PnCounterApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Delta { delta }) => {
+                // update counts of the current node
+                let own = {
+                    let mut counts = self.counts.lock().await;
+                    let own = counts.entry(request.dest.to_owned()).or_default();
+                    own.add(*delta);
+                    *own
+                };
+
+                ctx.reply(MessageBody::with_type(MessageType::AddOk))?;
+
+                // push the counts of the current node right away, the
+                // periodic gossip repairs whatever gets lost
+                let body = CountsBody {
+                    counts: HashMap::from([(request.dest.to_owned(), own)]),
+                }
+                .into_body();
+                for dest in ctx.other_node_ids() {
+                    let _ = ctx.send(dest, body.clone());
+                }
+                None
+            }
+            MessageType::Read(ReadRequest::Client) => {
+                // net sum of the counts of all nodes
+                let value: i64 = self.counts.lock().await.values().map(Counts::value).sum();
+                Some(MessageBody::read_ok_value(value))
+            }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.state_checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                None
+            }
+            _ => {
+                let Ok(theirs) = request.body.to_custom::<CountsBody>() else {
+                    return Err(not_supported(&request.body.msg_type));
+                };
+                let mut counts = self.counts.lock().await;
+                for (node_id, their) in theirs.counts {
+                    counts.entry(node_id).or_default().merge(&their);
+                }
+                None
+            }
+        };
+        Ok(body)
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        // flush the final counts to other nodes in the network
+        self.gossip(&maelstrom).await?;
+        self.checksums
+            .finish(&maelstrom, self.state_checksum().await)
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let app = Arc::new(PnCounterApp::default());
+    // client retries must not apply the same operation twice
+    let maelstrom = Maelstrom::builder()
+        .dedup_requests(Some(Duration::from_secs(30)))
+        .build();
+
+    let gossip = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| gossip.run_gossip(maelstrom));
+    let checksums = app.clone();
+    maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+        exchange_checksums(checksums, maelstrom)
+    });
+
+    maelstrom.run_with_app(app).await
+}
@@ -1,94 +1,99 @@
-use std::{collections::HashMap, io, sync::Arc};
+use std::{io, sync::Arc};
 
 use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    kv::{Kv, KvError, KvService, TxnStore},
+    maelstrom::{not_supported, App, Context, Maelstrom},
     message::*,
+    sync::TrackedMutex,
+    txn_graph::TxnGraph,
 };
-use tokio::sync::Mutex;
 
-#[derive(Default)]
 struct TxnKVStoreApp {
-    lock: Mutex<()>,
+    lock: TrackedMutex<()>,
+    store: TxnStore,
+    // optional dependency graph of processed transactions
+    graph: TxnGraph,
 }
 
 impl TxnKVStoreApp {
-    #[allow(unused_variables)]
     async fn transaction_handler(
         &self,
-        maelstrom: &Maelstrom,
-        mut txn: Vec<Transaction>,
-    ) -> io::Result<Vec<Transaction>> {
+        txn: Vec<ListAppendOp>,
+    ) -> Result<Vec<ListAppendOp>, KvError> {
         let _lock_gaurd = self.lock.lock().await;
 
-        // storing whole database as a value of `root` key in lin-kv store
-        let body = MessageBody::with_type(MessageType::Read {
-            key: Some("root".to_string()),
-        });
-        let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-        let old_data = match response.body.msg_type {
-            MessageType::ReadOk { messages, value } => value.unwrap(),
-            _ => Value::None,
-        };
-        let mut data = match old_data.to_owned() {
-            Value::Map(v) => v,
-            _ => HashMap::new(),
-        };
-
-        for t in txn.iter_mut() {
-            match t {
-                Transaction::Read { key, val } => {
-                    *val = Value::Vec(data.get(&key.to_string()).map(|v| v.to_owned()).unwrap());
-                }
-                Transaction::Append { key, value } => {
-                    let entry = data.entry(key.to_string()).or_insert(vec![]);
-                    entry.push(*value);
+        // one list per key, the transaction runs again on the latest lists when
+        // another node committed first
+        let result = self
+            .store
+            .run(|store| {
+                let mut txn = txn.clone();
+                async move {
+                    for t in txn.iter_mut() {
+                        match t {
+                            ListAppendOp::Read { key, val } => {
+                                *val = store.read(key.to_string()).await?;
+                            }
+                            ListAppendOp::Append { key, value } => {
+                                let mut list: Vec<i64> =
+                                    store.read(key.to_string()).await?.unwrap_or_default();
+                                list.push(*value);
+                                store.write(key.to_string(), &list)?;
+                            }
+                        }
+                    }
+                    Ok(txn)
                 }
-                _ => {}
-            }
+            })
+            .await;
+        match &result {
+            Ok(done) => self.graph.record(done, false),
+            Err(e) if e.is_definite() => self.graph.record(&txn, true),
+            // it may have committed after all, neither a commit nor a conflict
+            Err(_) => {}
         }
-
-        let body = MessageBody::with_type(MessageType::Cas {
-            key: "root".to_string(),
-            from: old_data,
-            to: Value::Map(data),
-            create_if_not_exists: Some(true),
-        });
-        let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-        match response.body.msg_type {
-            MessageType::Error { code, text } => {
-                return Err(io::Error::new(io::ErrorKind::Other, "failed cas"));
-            }
-            _ => {}
-        };
-        Ok(txn)
+        result
     }
 }
 
 #[async_trait]
 impl App for TxnKVStoreApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()> {
-        match &request.body.msg_type {
-            MessageType::Txn { txn } => {
-                let body = match self.transaction_handler(&maelstrom, txn.to_owned()).await {
-                    Ok(txn) => MessageBody::with_type(MessageType::TxnOk { txn }),
-                    Err(_) => MessageBody::with_type(MessageType::Error {
-                        code: 30,
-                        text: "The requested transaction has been aborted because of a conflict."
-                            .to_string(),
-                    }),
-                };
-
-                maelstrom.reply(request, body)?;
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let MessageType::Txn { txn } = &request.body.msg_type else {
+            return Err(not_supported(&request.body.msg_type));
+        };
+        let txn = match txn.iter().cloned().map(ListAppendOp::try_from).collect() {
+            Ok(txn) => txn,
+            Err(e) => return Ok(Some(MessageBody::malformed_request(e.to_string()))),
+        };
+        let body = match self.transaction_handler(txn).await {
+            Ok(txn) => {
+                let txn = txn.into_iter().map(Transaction::from).collect();
+                MessageBody::with_type(MessageType::TxnOk { txn })
             }
-            _ => {}
-        }
-        Ok(())
+            // other nodes kept committing first, the transaction definitely didn't apply
+            Err(e) if e.is_definite() => MessageBody::txn_conflict(
+                "The requested transaction has been aborted because of a conflict.",
+            ),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(body))
     }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let app = Arc::new(TxnKVStoreApp::default());
-    Maelstrom::new().run_with_app(app).await
+    let maelstrom = Maelstrom::new();
+    let app = Arc::new(TxnKVStoreApp {
+        lock: Default::default(),
+        store: TxnStore::new(Kv::configured(maelstrom.clone(), KvService::Lin).scoped("txn")),
+        graph: TxnGraph::from_env(),
+    });
+    maelstrom.run_with_app(app.clone()).await?;
+
+    // export the dependency graph once all requests are processed, which is
+    // only after the runtime waited for in-flight handlers
+    app.graph.export(maelstrom.node_id())
 }
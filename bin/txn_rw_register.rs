@@ -2,106 +2,89 @@ use std::{io, sync::Arc};
 
 use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    kv::{Kv, KvError, KvService, TxnStore},
+    maelstrom::{not_supported, App, Context, Maelstrom},
     message::*,
+    sync::TrackedMutex,
+    txn_graph::TxnGraph,
 };
-use tokio::sync::Mutex;
 
-#[derive(Default)]
 struct KVStoreApp {
-    lock: Mutex<()>,
+    lock: TrackedMutex<()>,
+    store: TxnStore,
+    // optional dependency graph of processed transactions
+    graph: TxnGraph,
 }
 
 impl KVStoreApp {
-    async fn distributed_lock(&self, maelstrom: &Maelstrom, acquire: bool) -> io::Result<()> {
-        let (from, to) = if acquire {
-            (Value::None, Value::String(maelstrom.node_id().to_string()))
-        } else {
-            (Value::String(maelstrom.node_id().to_string()), Value::None)
-        };
-
-        let body = MessageBody::with_type(MessageType::Cas {
-            key: "lock".to_string(),
-            from: from.to_owned(),
-            to: to.to_owned(),
-            create_if_not_exists: Some(true),
-        });
-        loop {
-            let response = maelstrom
-                .rpc("lin-kv".to_owned(), body.to_owned(), false)
-                .await?;
-            match response.body.msg_type {
-                MessageType::CasOk => break,
-                _ => {}
-            };
-        }
-
-        Ok(())
-    }
-
-    #[allow(unused_variables)]
-    async fn transaction_handler(
-        &self,
-        maelstrom: &Maelstrom,
-        mut txn: Vec<Transaction>,
-    ) -> io::Result<Vec<Transaction>> {
-        for t in txn.iter_mut() {
-            match t {
-                Transaction::Read { key, val } => {
-                    let body = MessageBody::with_type(MessageType::Read {
-                        key: Some(key.to_string()),
-                    });
-                    let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-
-                    let value = match response.body.msg_type {
-                        MessageType::ReadOk { messages, value } => value.unwrap(),
-                        _ => Value::None,
-                    };
-
-                    *val = value;
+    // runs again from the start when another transaction committed first
+    async fn transaction_handler(&self, txn: &[RegisterOp]) -> Result<Vec<RegisterOp>, KvError> {
+        self.store
+            .run(|store| {
+                let mut txn = txn.to_vec();
+                async move {
+                    for t in txn.iter_mut() {
+                        match t {
+                            RegisterOp::Read { key, val } => {
+                                // a missing key reads as null
+                                *val = store.read(key.to_string()).await?;
+                            }
+                            RegisterOp::Write { key, value } => {
+                                store.write(key.to_string(), value)?;
+                            }
+                        }
+                    }
+                    Ok(txn)
                 }
-                Transaction::Write { key, value } => {
-                    let body = MessageBody::with_type(MessageType::Write {
-                        key: key.to_string(),
-                        value: Value::Int(*value),
-                    });
-                    let response = maelstrom.rpc("lin-kv".to_owned(), body, false).await?;
-                }
-                _ => {}
-            }
-        }
-
-        Ok(txn)
+            })
+            .await
     }
 }
 
 #[async_trait]
 impl App for KVStoreApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()> {
-        match &request.body.msg_type {
-            MessageType::Txn { txn } => {
-                let _lock_gaurd = self.lock.lock().await;
-
-                // acquire distributed lock
-                self.distributed_lock(&maelstrom, true).await?;
-
-                // process transaction
-                if let Ok(txn) = self.transaction_handler(&maelstrom, txn.to_owned()).await {
-                    let body = MessageBody::with_type(MessageType::TxnOk { txn });
-                    let _ = maelstrom.reply(request, body);
-                }
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let MessageType::Txn { txn } = &request.body.msg_type else {
+            return Err(not_supported(&request.body.msg_type));
+        };
+        let txn: Vec<_> = match txn.iter().cloned().map(RegisterOp::try_from).collect() {
+            Ok(txn) => txn,
+            Err(e) => return Ok(Some(MessageBody::malformed_request(e.to_string()))),
+        };
+        // transactions of this node don't race each other
+        let _lock_gaurd = self.lock.lock().await;
 
-                // release distributed lock
-                self.distributed_lock(&maelstrom, false).await?;
+        let result = self.transaction_handler(&txn).await;
+        let body = match result {
+            Ok(txn) => {
+                self.graph.record(&txn, false);
+                let txn = txn.into_iter().map(Transaction::from).collect();
+                MessageBody::with_type(MessageType::TxnOk { txn })
+            }
+            // other nodes kept committing first, the transaction definitely didn't apply
+            Err(e) if e.is_definite() => {
+                self.graph.record(&txn, true);
+                MessageBody::txn_conflict(
+                    "The requested transaction has been aborted because of a conflict.",
+                )
             }
-            _ => {}
-        }
-        Ok(())
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(body))
     }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let app = Arc::new(KVStoreApp::default());
-    Maelstrom::new().run_with_app(app).await
+    let maelstrom = Maelstrom::new();
+    let app = Arc::new(KVStoreApp {
+        lock: Default::default(),
+        store: TxnStore::new(Kv::configured(maelstrom.clone(), KvService::Lin).scoped("txn")),
+        graph: TxnGraph::from_env(),
+    });
+    maelstrom.run_with_app(app.clone()).await?;
+
+    // export the dependency graph once all requests are processed, which is
+    // only after the runtime waited for in-flight handlers
+    app.graph.export(maelstrom.node_id())
 }
@@ -6,9 +6,9 @@ use std::{
     },
 };
 
-use async_trait::async_trait;
 use maelstrom_client::{
-    maelstrom::{App, Maelstrom},
+    handle::{SyncAdapter, SyncApp},
+    maelstrom::{not_supported, Context, Maelstrom},
     message::*,
 };
 
@@ -17,24 +17,20 @@ struct UniqueIdsApp {
     id: AtomicU64,
 }
 
-#[async_trait]
-impl App for UniqueIdsApp {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> std::io::Result<()> {
-        match &request.body.msg_type {
-            MessageType::Generate => {
-                let id = self.id.fetch_add(1, Ordering::Relaxed);
-                let id = format!("{}-{}", maelstrom.node_id(), id);
-                let body = MessageBody::with_type(MessageType::GenerateOk { id });
-                maelstrom.reply_with_id(request, body)?;
-            }
-            _ => {}
-        }
-        Ok(())
+impl SyncApp for UniqueIdsApp {
+    fn handle(&self, ctx: &Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let MessageType::Generate = request.body.msg_type else {
+            return Err(not_supported(&request.body.msg_type));
+        };
+
+        let id = self.id.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{}-{}", ctx.node_id(), id);
+        Ok(Some(MessageBody::with_type(MessageType::GenerateOk { id })))
     }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let app = Arc::new(UniqueIdsApp::default());
-    Maelstrom::new().run_with_app(app).await
+    let app = SyncAdapter(UniqueIdsApp::default());
+    Maelstrom::new().run_with_app(Arc::new(app)).await
 }
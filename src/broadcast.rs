@@ -0,0 +1,198 @@
+use std::{collections::HashSet, io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{
+    checksum::{checksum, ChecksumExchange, StateChecksum},
+    maelstrom::{not_supported, App, Context, Maelstrom},
+    message::{Message, MessageBody, MessageType, Payload, ReadRequest},
+    sync::TrackedMutex,
+};
+
+pub mod strategy;
+
+// State shared by every dissemination strategy.
+#[derive(Default)]
+pub struct BroadcastState {
+    // holds all messages the node received through broadcast
+    pub messages: TrackedMutex<HashSet<Payload>>,
+}
+
+impl BroadcastState {
+    // add messages to local state, returning the ones not seen before
+    pub async fn insert(&self, messages: impl IntoIterator<Item = Payload>) -> Vec<Payload> {
+        let mut data = self.messages.lock().await;
+        messages
+            .into_iter()
+            .filter(|message| data.insert(message.clone()))
+            .collect()
+    }
+
+    pub async fn checksum(&self) -> u64 {
+        checksum(self.messages.lock().await.iter())
+    }
+}
+
+// How new messages are propagated to the rest of the cluster.
+#[async_trait]
+pub trait Strategy: Send + Sync {
+    // called when gossip starts and again whenever a topology message changes
+    // `maelstrom.neighbours()`
+    async fn on_topology(&self, _maelstrom: &Maelstrom, _state: &BroadcastState) {}
+
+    // `messages` were not seen before and arrived from `src`
+    async fn on_new_messages(&self, maelstrom: &Maelstrom, src: &str, messages: &[Payload]);
+
+    // a message of the strategy's own protocol, false if it isn't one
+    async fn on_message(
+        &self,
+        _maelstrom: &Maelstrom,
+        _state: &BroadcastState,
+        _request: &Message,
+    ) -> bool {
+        false
+    }
+
+    // interval of the background gossip tick, None if the strategy doesn't need one
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    async fn tick(&self, _maelstrom: &Maelstrom, _state: &BroadcastState) {}
+
+    // last chance to push pending messages at shutdown, acks can no longer arrive
+    async fn flush(&self, _maelstrom: &Maelstrom, _state: &BroadcastState) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct BroadcastApp {
+    state: BroadcastState,
+    strategy: Box<dyn Strategy>,
+    checksums: ChecksumExchange,
+}
+
+impl BroadcastApp {
+    pub fn new(strategy: Box<dyn Strategy>) -> Self {
+        Self {
+            state: Default::default(),
+            strategy,
+            checksums: Default::default(),
+        }
+    }
+
+    // Periodically lets the strategy gossip and tells it about topology
+    // changes, runs until the process exits.
+    pub async fn run_gossip(self: Arc<Self>, maelstrom: Maelstrom) {
+        let mut neighbours = maelstrom.watch_neighbours();
+        neighbours.mark_unchanged();
+        self.strategy.on_topology(&maelstrom, &self.state).await;
+
+        let mut interval = self
+            .strategy
+            .tick_interval()
+            .map(|period| maelstrom.interval(period));
+        loop {
+            tokio::select! {
+                changed = neighbours.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    self.strategy.on_topology(&maelstrom, &self.state).await;
+                }
+                Some(_) = async { Some(interval.as_mut()?.tick().await) } => {
+                    self.strategy.tick(&maelstrom, &self.state).await;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StateChecksum for BroadcastApp {
+    async fn state_checksum(&self) -> u64 {
+        self.state.checksum().await
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+// broadcasts and reads of clients are the ops maelstrom's msgs-per-op divides by
+fn count_client_op(maelstrom: &Maelstrom, request: &Message) {
+    if !maelstrom.is_member(&request.src) {
+        maelstrom.metrics().incr("broadcast.client_ops", 1);
+    }
+}
+
+#[async_trait]
+impl App for BroadcastApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let body = match &request.body.msg_type {
+            // the runtime already stored it, `run_gossip` passes changes on
+            MessageType::Topology { .. } => {
+                let body = MessageBody::with_type(MessageType::TopologyOk);
+                Some(body)
+            }
+            MessageType::Broadcast { message } => {
+                count_client_op(&ctx, &request);
+                let new_messages = self.state.insert([message.clone()]).await;
+                if !new_messages.is_empty() {
+                    self.strategy
+                        .on_new_messages(&ctx, &request.src, &new_messages)
+                        .await;
+                }
+
+                let body = MessageBody::with_type(MessageType::BroadcastOk);
+                Some(body)
+            }
+            MessageType::BroadcastMany { messages } => {
+                let new_messages = self.state.insert(messages.iter().cloned()).await;
+                if !new_messages.is_empty() {
+                    self.strategy
+                        .on_new_messages(&ctx, &request.src, &new_messages)
+                        .await;
+                }
+
+                let body = MessageBody::with_type(MessageType::BroadcastManyOk);
+                Some(body)
+            }
+            MessageType::Read(ReadRequest::Client) => {
+                count_client_op(&ctx, &request);
+                let messages = self.state.messages.lock().await.clone();
+                Some(MessageBody::read_ok_messages(messages))
+            }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.state.checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                None
+            }
+            _ if self.strategy.on_message(&ctx, &self.state, &request).await => None,
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(body)
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        self.strategy.flush(&maelstrom, &self.state).await?;
+
+        // this node's share of maelstrom's msgs-per-op, from its own metrics so
+        // nodes sharing a process don't count each other's; the cluster figure
+        // is the sum of the messages of all nodes over the sum of their ops
+        let metrics = maelstrom.metrics();
+        let (messages, ops) = (
+            metrics.counter("net.peer_messages"),
+            metrics.counter("broadcast.client_ops"),
+        );
+        if ops > 0 {
+            maelstrom.log(format!(
+                "sent {messages} messages to peers for {ops} client ops, {:.2} per op",
+                messages as f64 / ops as f64
+            ));
+        }
+
+        self.checksums
+            .finish(&maelstrom, self.state.checksum().await)
+    }
+}
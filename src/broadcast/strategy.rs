@@ -0,0 +1,529 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{BroadcastState, Strategy};
+use crate::{
+    maelstrom::Maelstrom,
+    message::{Message, MessageBody, MessageType, NodeId, Payload},
+    sync::TrackedMutex,
+};
+
+// maximum number of messages sent in a single broadcast_many
+pub const CHUNK_SIZE: usize = 64;
+// default gossip cadence of the periodic strategies
+pub const GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
+
+// Which peers a node gossips with.
+#[derive(Debug, Clone, Copy)]
+pub enum Overlay {
+    // neighbours given by the maelstrom topology message
+    Topology,
+    // spanning tree over the sorted node ids where every node has up to `fanout` children
+    Tree { fanout: usize },
+    // the first of the sorted node ids is the hub that every other node is
+    // connected to, any two nodes are at most two hops apart
+    Star,
+}
+
+impl Overlay {
+    pub fn neighbours(&self, maelstrom: &Maelstrom) -> Vec<NodeId> {
+        match self {
+            Overlay::Topology => maelstrom.neighbours(),
+            Overlay::Tree { fanout } => {
+                let fanout = (*fanout).max(1);
+                let mut node_ids = maelstrom.node_ids();
+                node_ids.sort();
+
+                let Some(idx) = node_ids.iter().position(|id| maelstrom.is_self(id)) else {
+                    return vec![];
+                };
+
+                let mut neighbours = vec![];
+                if idx > 0 {
+                    neighbours.push(node_ids[(idx - 1) / fanout].to_owned());
+                }
+                for child in idx * fanout + 1..=idx * fanout + fanout {
+                    if let Some(id) = node_ids.get(child) {
+                        neighbours.push(id.to_owned());
+                    }
+                }
+                neighbours
+            }
+            Overlay::Star => {
+                let mut node_ids = maelstrom.node_ids();
+                node_ids.sort();
+                let Some(hub) = node_ids.first().cloned() else {
+                    return vec![];
+                };
+                if maelstrom.is_self(&hub) {
+                    node_ids.split_off(1)
+                } else {
+                    vec![hub]
+                }
+            }
+        }
+    }
+}
+
+// decides the order in which pending messages are delivered to a neighbour
+pub trait GossipOrder: Send + Sync {
+    // `pending` is in arrival order, oldest first
+    fn order(&self, pending: Vec<Payload>) -> Vec<Payload>;
+}
+
+// peers catching up after a partition care most about recent messages, as clients are reading them now
+pub struct NewestFirst;
+
+impl GossipOrder for NewestFirst {
+    fn order(&self, mut pending: Vec<Payload>) -> Vec<Payload> {
+        pending.reverse();
+        pending
+    }
+}
+
+pub struct OldestFirst;
+
+impl GossipOrder for OldestFirst {
+    fn order(&self, pending: Vec<Payload>) -> Vec<Payload> {
+        pending
+    }
+}
+
+// Messages are broadcasted to all neighbours immediately upon receipt, with
+// outbox retries until successful delivery.
+#[derive(Default)]
+pub struct Immediate;
+
+#[async_trait]
+impl Strategy for Immediate {
+    async fn on_new_messages(&self, maelstrom: &Maelstrom, src: &str, messages: &[Payload]) {
+        let neighbours = maelstrom.neighbours();
+
+        // broadcast message to all neighbours except src
+        for message in messages {
+            let body = MessageBody::with_type(MessageType::Broadcast {
+                message: message.clone(),
+            });
+            for neighbour in neighbours.iter().filter(|neighbour| neighbour.ne(&src)) {
+                let _ = maelstrom.enqueue(neighbour.to_owned(), body.clone());
+            }
+        }
+    }
+}
+
+// Messages are collected per neighbour and broadcasted periodically in chunked
+// broadcast_many rpcs, in the order picked by `GossipOrder`.
+pub struct Batched {
+    overlay: Overlay,
+    order: Box<dyn GossipOrder>,
+    period: Duration,
+    // pending messages of each current neighbour that need to be broadcasted, in arrival order
+    pending: TrackedMutex<HashMap<NodeId, Vec<Payload>>>,
+}
+
+impl Batched {
+    pub fn new(overlay: Overlay, order: Box<dyn GossipOrder>) -> Self {
+        Self {
+            overlay,
+            order,
+            period: GOSSIP_INTERVAL,
+            pending: Default::default(),
+        }
+    }
+
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    // drain pending messages of a neighbour and split them into chunks in delivery order
+    fn take_pending(&self, pending: &mut Vec<Payload>) -> Vec<HashSet<Payload>> {
+        let messages = std::mem::take(pending);
+
+        self.order
+            .order(messages)
+            .chunks(CHUNK_SIZE)
+            .map(|chunk| chunk.iter().cloned().collect())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Strategy for Batched {
+    // Queues of neighbours that are gone are dropped, the ones kept are left
+    // as is. New neighbours start with every message known so far, as they
+    // missed the gossip before they were connected to us.
+    async fn on_topology(&self, maelstrom: &Maelstrom, state: &BroadcastState) {
+        let neighbours: HashSet<NodeId> = self.overlay.neighbours(maelstrom).into_iter().collect();
+
+        let mut pending = self.pending.lock().await;
+        pending.retain(|neighbour, _| neighbours.contains(neighbour));
+        let missing: Vec<_> = neighbours
+            .into_iter()
+            .filter(|neighbour| !pending.contains_key(neighbour))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let messages: Vec<Payload> = state.messages.lock().await.iter().cloned().collect();
+        for neighbour in missing {
+            pending.insert(neighbour, messages.clone());
+        }
+    }
+
+    async fn on_new_messages(&self, _maelstrom: &Maelstrom, src: &str, messages: &[Payload]) {
+        // add the new messages to pending messages of each neighbour except src
+        for (neighbour, queue) in self.pending.lock().await.iter_mut() {
+            if neighbour.ne(src) {
+                queue.extend_from_slice(messages);
+            }
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.period)
+    }
+
+    async fn tick(&self, maelstrom: &Maelstrom, _state: &BroadcastState) {
+        // chunks are sent in delivery order so the first ones land first; peers
+        // suspected down keep accumulating and catch up once they are back
+        for (dest, queue) in self.pending.lock().await.iter_mut() {
+            if !maelstrom.is_alive(dest) {
+                continue;
+            }
+            for messages in self.take_pending(queue) {
+                let body = MessageBody::with_type(MessageType::BroadcastMany { messages });
+                let _ = maelstrom.enqueue(dest.to_owned(), body);
+            }
+        }
+    }
+
+    async fn flush(&self, maelstrom: &Maelstrom, _state: &BroadcastState) -> io::Result<()> {
+        for (dest, queue) in self.pending.lock().await.iter_mut() {
+            for messages in self.take_pending(queue) {
+                let body = MessageBody::with_type(MessageType::BroadcastMany { messages });
+                maelstrom.send_with_id(dest.to_owned(), body)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Anti-entropy: every tick each neighbour is sent the messages it is not known
+// to hold yet. A neighbour is known to hold a message once it acked it or sent
+// it to us, so lost messages are simply resent on the next tick instead of
+// being retried per rpc.
+pub struct Digest {
+    period: Duration,
+    known: Arc<TrackedMutex<HashMap<NodeId, HashSet<Payload>>>>,
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self {
+            period: GOSSIP_INTERVAL,
+            known: Default::default(),
+        }
+    }
+}
+
+impl Digest {
+    async fn missing(
+        &self,
+        maelstrom: &Maelstrom,
+        state: &BroadcastState,
+    ) -> Vec<(NodeId, HashSet<Payload>)> {
+        let neighbours = maelstrom.neighbours();
+        if neighbours.is_empty() {
+            return vec![];
+        }
+
+        let messages = state.messages.lock().await.clone();
+        let known = self.known.lock().await;
+        neighbours
+            .into_iter()
+            .map(|neighbour| {
+                let missing = match known.get(&neighbour) {
+                    Some(known) => messages.difference(known).cloned().collect(),
+                    None => messages.clone(),
+                };
+                (neighbour, missing)
+            })
+            .filter(|(_, missing): &(NodeId, HashSet<Payload>)| !missing.is_empty())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Strategy for Digest {
+    async fn on_new_messages(&self, maelstrom: &Maelstrom, src: &str, messages: &[Payload]) {
+        let mut known = self.known.lock().await;
+        if let Some(known) = known.get_mut(src) {
+            known.extend(messages.iter().cloned());
+        } else if maelstrom
+            .neighbours()
+            .iter()
+            .any(|neighbour| neighbour == src)
+        {
+            known.insert(src.into(), messages.iter().cloned().collect());
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.period)
+    }
+
+    async fn tick(&self, maelstrom: &Maelstrom, state: &BroadcastState) {
+        for (dest, messages) in self.missing(maelstrom, state).await {
+            let maelstrom = maelstrom.clone();
+            let known = self.known.clone();
+            maelstrom.clone().spawn(async move {
+                let body = MessageBody::with_type(MessageType::BroadcastMany {
+                    messages: messages.clone(),
+                });
+                // no retries, whatever is still missing goes out on the next tick
+                if maelstrom.rpc(dest.to_owned(), body, false).await.is_ok() {
+                    known.lock().await.entry(dest).or_default().extend(messages);
+                }
+            });
+        }
+    }
+
+    async fn flush(&self, maelstrom: &Maelstrom, state: &BroadcastState) -> io::Result<()> {
+        for (dest, messages) in self.missing(maelstrom, state).await {
+            let body = MessageBody::with_type(MessageType::BroadcastMany { messages });
+            maelstrom.send_with_id(dest, body)?;
+        }
+        Ok(())
+    }
+}
+
+// One way batch of `AggregatedAcks`: the sender's messages for the receiver
+// from index `from` up to `upto`, and how much of the receiver's own messages
+// arrived at the sender.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "gossip")]
+struct GossipBody {
+    messages: Vec<Payload>,
+    from: u64,
+    upto: u64,
+    ack: u64,
+}
+
+// Both directions of the exchange with one neighbour.
+struct Link {
+    // messages for the neighbour not acknowledged yet, the first one has index `acked`
+    unacked: Vec<Payload>,
+    acked: u64,
+    // end index and send time of the batches not acknowledged yet, oldest first
+    in_flight: VecDeque<(u64, Instant)>,
+    // every message of the neighbour up to this index arrived
+    received: u64,
+    // `received` is worth telling the neighbour, it wasn't since it last sent
+    ack_owed: bool,
+    ack_sent_at: Instant,
+}
+
+impl Link {
+    fn new(messages: Vec<Payload>, now: Instant) -> Self {
+        Self {
+            unacked: messages,
+            acked: 0,
+            in_flight: VecDeque::new(),
+            received: 0,
+            ack_owed: false,
+            ack_sent_at: now,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.acked + self.unacked.len() as u64
+    }
+
+    fn sent(&self) -> u64 {
+        self.in_flight.back().map_or(self.acked, |(upto, _)| *upto)
+    }
+
+    fn acknowledge(&mut self, upto: u64) {
+        if upto <= self.acked {
+            return;
+        }
+        let upto = upto.min(self.end());
+        self.unacked.drain(..(upto - self.acked) as usize);
+        self.acked = upto;
+        self.in_flight.retain(|(end, _)| *end > upto);
+    }
+
+    // the batch due at `now`, if any: what wasn't sent yet, everything
+    // unacknowledged once the oldest batch is overdue, or just an ack the
+    // neighbour has waited `ack_delay` for
+    fn due(
+        &mut self,
+        now: Instant,
+        ack_delay: Duration,
+        resend_after: Duration,
+    ) -> Option<GossipBody> {
+        let overdue = self
+            .in_flight
+            .front()
+            .is_some_and(|(_, sent_at)| now.duration_since(*sent_at) >= resend_after);
+        let from = if overdue {
+            self.in_flight.clear();
+            self.acked
+        } else {
+            self.sent()
+        };
+        let end = self.end();
+        if from == end && !(self.ack_owed && now.duration_since(self.ack_sent_at) >= ack_delay) {
+            return None;
+        }
+        if from < end {
+            self.in_flight.push_back((end, now));
+        }
+        self.ack_owed = false;
+        self.ack_sent_at = now;
+        Some(GossipBody {
+            messages: self.unacked[(from - self.acked) as usize..].to_vec(),
+            from,
+            upto: end,
+            ack: self.received,
+        })
+    }
+}
+
+// Batches go out one way, without a reply each. Acknowledgements are
+// cumulative indexes into the stream of messages for a neighbour and ride
+// along with the batches of the other direction; a neighbour that has nothing
+// to send acknowledges on its own only every other tick. Batches that stay
+// unacknowledged are resent whole, and the receiver only moves its index on
+// when a batch connects to what it already has.
+pub struct AggregatedAcks {
+    overlay: Overlay,
+    period: Duration,
+    links: TrackedMutex<HashMap<NodeId, Link>>,
+}
+
+impl AggregatedAcks {
+    pub fn new(overlay: Overlay) -> Self {
+        Self {
+            overlay,
+            period: GOSSIP_INTERVAL,
+            links: Default::default(),
+        }
+    }
+
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    fn ack_delay(&self) -> Duration {
+        self.period * 2
+    }
+
+    // long enough for a lazy ack to come back
+    fn resend_after(&self) -> Duration {
+        self.period * 4
+    }
+}
+
+fn gossip(body: GossipBody) -> MessageBody {
+    // serializing a tagged struct into a body can't fail
+    MessageBody::from_custom(&body).expect("gossip body")
+}
+
+#[async_trait]
+impl Strategy for AggregatedAcks {
+    // like `Batched`, links of neighbours that are gone are dropped and new
+    // neighbours start with every message known so far
+    async fn on_topology(&self, maelstrom: &Maelstrom, state: &BroadcastState) {
+        let neighbours: HashSet<NodeId> = self.overlay.neighbours(maelstrom).into_iter().collect();
+
+        let mut links = self.links.lock().await;
+        links.retain(|neighbour, _| neighbours.contains(neighbour));
+        let missing: Vec<_> = neighbours
+            .into_iter()
+            .filter(|neighbour| !links.contains_key(neighbour))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let messages: Vec<Payload> = state.messages.lock().await.iter().cloned().collect();
+        let now = maelstrom.now();
+        for neighbour in missing {
+            links.insert(neighbour, Link::new(messages.clone(), now));
+        }
+    }
+
+    async fn on_new_messages(&self, _maelstrom: &Maelstrom, src: &str, messages: &[Payload]) {
+        for (neighbour, link) in self.links.lock().await.iter_mut() {
+            if neighbour.ne(src) {
+                link.unacked.extend_from_slice(messages);
+            }
+        }
+    }
+
+    async fn on_message(
+        &self,
+        maelstrom: &Maelstrom,
+        state: &BroadcastState,
+        request: &Message,
+    ) -> bool {
+        let Ok(body) = request.body.to_custom::<GossipBody>() else {
+            return false;
+        };
+        let new_messages = state.insert(body.messages.iter().cloned()).await;
+        if !new_messages.is_empty() {
+            self.on_new_messages(maelstrom, &request.src, &new_messages)
+                .await;
+        }
+
+        if let Some(link) = self.links.lock().await.get_mut(&request.src) {
+            link.acknowledge(body.ack);
+            // a batch past a gap is kept, the index waits for the resend
+            if body.from <= link.received && body.upto > link.received {
+                link.received = body.upto;
+            }
+            if !body.messages.is_empty() {
+                link.ack_owed = true;
+            }
+        }
+        true
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.period)
+    }
+
+    async fn tick(&self, maelstrom: &Maelstrom, _state: &BroadcastState) {
+        let now = maelstrom.now();
+        let (ack_delay, resend_after) = (self.ack_delay(), self.resend_after());
+        for (dest, link) in self.links.lock().await.iter_mut() {
+            if !maelstrom.is_alive(dest) {
+                continue;
+            }
+            // resent batches and lone acks repeat earlier lines on purpose
+            if let Some(body) = link.due(now, ack_delay, resend_after) {
+                let _ = maelstrom.resend(dest.to_owned(), gossip(body));
+            }
+        }
+    }
+
+    async fn flush(&self, maelstrom: &Maelstrom, _state: &BroadcastState) -> io::Result<()> {
+        for (dest, link) in self.links.lock().await.iter_mut() {
+            if let Some(body) = link.due(maelstrom.now(), Duration::ZERO, Duration::ZERO) {
+                maelstrom.resend(dest.to_owned(), gossip(body))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::{Serialize, Serializer};
+
+// Canonical serialization of outgoing bodies, so the same state always gives
+// the same line in logs, traces and goldens. Object keys are sorted anyway, as
+// bodies go through serde_json's `Value` whose maps are ordered; sets are
+// written sorted by the serialization of their members once this is enabled.
+//
+// The mode is process wide: serde impls have no access to the runtime, and
+// nodes sharing a process (`Cluster`, `Simulator`) share one output format.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// `serialize_with` for set fields
+pub fn serialize_set<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    if !is_enabled() {
+        return serializer.collect_seq(set);
+    }
+
+    let mut members: Vec<(String, &T)> = set
+        .iter()
+        .map(|member| (serde_json::to_string(member).unwrap_or_default(), member))
+        .collect();
+    members.sort_by(|(a, _), (b, _)| a.cmp(b));
+    serializer.collect_seq(members.into_iter().map(|(_, member)| member))
+}
+
+// a body as text with sorted keys, also when it doesn't pass through `Value`
+pub fn to_string<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    if is_enabled() {
+        Ok(serde_json::to_value(value)?.to_string())
+    } else {
+        serde_json::to_string(value)
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    maelstrom::Maelstrom,
+    message::{MessageBody, MessageType, NodeId},
+};
+
+// how often the local checksum is looked at while the node runs
+pub const CHECKSUM_INTERVAL: Duration = Duration::from_secs(1);
+
+// Order independent checksum of a collection, so sets and maps hash the same
+// regardless of iteration order.
+pub fn checksum<T: Hash>(items: impl IntoIterator<Item = T>) -> u64 {
+    items.into_iter().fold(0u64, |acc, item| {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        acc.wrapping_add(hasher.finish())
+    })
+}
+
+// An app whose replicated state should end up the same on every node.
+#[async_trait]
+pub trait StateChecksum: Send + Sync {
+    async fn state_checksum(&self) -> u64;
+
+    // where the comparisons with peers are kept
+    fn checksums(&self) -> &ChecksumExchange;
+}
+
+// Sends the local checksum to every other node whenever the state has been
+// quiet for a whole `CHECKSUM_INTERVAL` and changed since the last send, so a
+// busy or idle node adds no traffic. Runs until the process exits.
+pub async fn exchange_checksums(state: Arc<impl StateChecksum>, maelstrom: Maelstrom) {
+    let mut interval = maelstrom.interval(CHECKSUM_INTERVAL);
+    let (mut previous, mut sent) = (None, None);
+    loop {
+        interval.tick().await;
+        let ours = state.state_checksum().await;
+        state.checksums().caught_up(ours);
+
+        if previous == Some(ours) && sent != Some(ours) {
+            if let Err(e) = send_checksum(&maelstrom, ours) {
+                maelstrom.log(format!("Error: sending state checksum failed: {e}"));
+            }
+            sent = Some(ours);
+        }
+        previous = Some(ours);
+    }
+}
+
+// Compares state checksums with the other nodes during the run, see
+// `exchange_checksums`, not just at shutdown. The latest disagreement with
+// each peer is kept and logged at exit.
+#[derive(Default)]
+pub struct ChecksumExchange {
+    // (ours, theirs) of the latest comparison with each peer that disagreed
+    mismatches: Mutex<BTreeMap<NodeId, (u64, u64)>>,
+}
+
+impl ChecksumExchange {
+    // compare a checksum received from a peer against the local one
+    pub fn compare(&self, maelstrom: &Maelstrom, src: &str, theirs: u64, ours: u64) {
+        let mut mismatches = self.mismatches.lock().unwrap();
+        if theirs == ours {
+            mismatches.remove(src);
+        } else {
+            maelstrom.metrics().incr("checksum.mismatches", 1);
+            mismatches.insert(src.into(), (ours, theirs));
+        }
+    }
+
+    // peers whose latest checksum differed from the local one
+    pub fn mismatched(&self) -> Vec<NodeId> {
+        self.mismatches.lock().unwrap().keys().cloned().collect()
+    }
+
+    // forgets peers whose last checksum this node has caught up with since
+    fn caught_up(&self, ours: u64) {
+        self.mismatches
+            .lock()
+            .unwrap()
+            .retain(|_, (_, theirs)| *theirs != ours);
+    }
+
+    // log the final state checksum and the latest mismatches, and send the
+    // checksum to every other node
+    pub fn finish(&self, maelstrom: &Maelstrom, checksum: u64) -> io::Result<()> {
+        maelstrom.log(format!("final state checksum {checksum:016x}"));
+
+        self.caught_up(checksum);
+        for (src, (ours, theirs)) in self.mismatches.lock().unwrap().iter() {
+            maelstrom.log(format!(
+                "state checksum MISMATCH with {src}: ours {ours:016x}, theirs {theirs:016x}"
+            ));
+        }
+
+        send_checksum(maelstrom, checksum)
+    }
+}
+
+fn send_checksum(maelstrom: &Maelstrom, checksum: u64) -> io::Result<()> {
+    let body = MessageBody::with_type(MessageType::StateChecksum { checksum });
+    for dest in maelstrom.other_node_ids() {
+        maelstrom.send(dest, body.clone())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+
+// Source of time for rpc retries, timers and periodic loops. The runtime uses
+// the system clock unless another one is set through the builder.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    async fn sleep(&self, duration: Duration);
+}
+
+// tokio time, which `tokio::time::pause` can also freeze
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+// Manually driven clock for tests: time stands still until `advance` is
+// called, which wakes every sleeper whose deadline has passed.
+#[derive(Debug)]
+pub struct VirtualClock {
+    state: Mutex<VirtualState>,
+}
+
+#[derive(Debug)]
+struct VirtualState {
+    now: Instant,
+    next_sleeper: u64,
+    sleepers: BTreeMap<(Instant, u64), oneshot::Sender<()>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(VirtualState {
+                now: Instant::now(),
+                next_sleeper: 0,
+                sleepers: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration;
+
+        let now = state.now;
+        let pending = state.sleepers.split_off(&(now, u64::MAX));
+        let due = std::mem::replace(&mut state.sleepers, pending);
+        for (_, waker) in due {
+            let _ = waker.send(());
+        }
+    }
+
+    // when the earliest pending sleep ends, None if nothing sleeps
+    pub fn next_wake(&self) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        state.sleepers.retain(|_, waker| !waker.is_closed());
+        state.sleepers.keys().next().map(|(deadline, _)| *deadline)
+    }
+
+    // number of sleeps waiting for time to advance
+    pub fn sleepers(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.sleepers.retain(|_, waker| !waker.is_closed());
+        state.sleepers.len()
+    }
+}
+
+#[async_trait]
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        if duration.is_zero() {
+            return;
+        }
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            let (sender, receiver) = oneshot::channel();
+            let key = (state.now + duration, state.next_sleeper);
+            state.next_sleeper += 1;
+            state.sleepers.insert(key, sender);
+            receiver
+        };
+        let _ = receiver.await;
+    }
+}
+
+// Periodic ticks on top of a `Clock`. Like `tokio::time::interval` the first
+// tick completes immediately and missed ticks fire right away; `tick` is
+// cancel safe.
+pub struct Interval {
+    clock: Arc<dyn Clock>,
+    period: Duration,
+    next: Instant,
+}
+
+impl Interval {
+    pub fn new(clock: Arc<dyn Clock>, period: Duration) -> Self {
+        let next = clock.now();
+        Self {
+            clock,
+            period,
+            next,
+        }
+    }
+
+    pub async fn tick(&mut self) -> Instant {
+        let now = self.clock.now();
+        if now < self.next {
+            self.clock.sleep(self.next - now).await;
+        }
+        let tick = self.next;
+        self.next += self.period;
+        tick
+    }
+}
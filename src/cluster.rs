@@ -0,0 +1,125 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    config::MaelstromBuilder,
+    maelstrom::{App, Maelstrom},
+    message::Envelope,
+    transport::{ChannelTransport, StdioTransport, Transport},
+};
+
+// Runs nodes n1..nN in one process behind a single transport, stdin / stdout
+// by default. Lines from outside go to the node named in `dest`, messages
+// between the nodes never leave the process and everything else is written to
+// the transport. Each node still needs its own init.
+pub struct Cluster {
+    nodes: usize,
+    transport: Arc<dyn Transport>,
+    builder: Box<dyn Fn() -> MaelstromBuilder + Send + Sync>,
+}
+
+impl Cluster {
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            nodes,
+            transport: Arc::new(StdioTransport::default()),
+            builder: Box::new(Maelstrom::builder),
+        }
+    }
+
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    // configuration of every node, the transport is set by the cluster
+    pub fn builder(
+        mut self,
+        builder: impl Fn() -> MaelstromBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.builder = Box::new(builder);
+        self
+    }
+
+    pub fn node_ids(&self) -> Vec<String> {
+        (1..=self.nodes).map(|i| format!("n{i}")).collect()
+    }
+
+    // Boots the nodes with the app built by `app` and routes messages until the
+    // input of the transport is closed, then waits for every node to shut down.
+    pub async fn run<F>(self, app: F) -> io::Result<()>
+    where
+        F: Fn(&Maelstrom) -> Arc<dyn App> + Send,
+    {
+        let mut inboxes = HashMap::new();
+        let mut outputs = vec![];
+        let mut nodes: Vec<JoinHandle<io::Result<()>>> = vec![];
+        for node_id in self.node_ids() {
+            let (transport, handle) = ChannelTransport::new();
+            let maelstrom = (self.builder)().transport(Arc::new(transport)).build();
+            let app = app(&maelstrom);
+            nodes.push(tokio::spawn(
+                async move { maelstrom.run_with_app(app).await },
+            ));
+            inboxes.insert(node_id, handle.inbound);
+            outputs.push(handle.outbound);
+        }
+
+        let node_ids: Arc<HashSet<String>> = Arc::new(self.node_ids().into_iter().collect());
+        let inboxes = Arc::new(Mutex::new(inboxes));
+        for output in outputs {
+            tokio::spawn(route(
+                output,
+                node_ids.clone(),
+                inboxes.clone(),
+                self.transport.clone(),
+            ));
+        }
+
+        while let Some(line) = self.transport.recv().await? {
+            let dest = dest_of(&line);
+            match inboxes.lock().unwrap().get(dest.as_ref()) {
+                Some(inbox) => {
+                    let _ = inbox.send(line);
+                }
+                None => eprintln!("warning: no node {dest} in the cluster: {line}"),
+            }
+        }
+
+        inboxes.lock().unwrap().clear();
+        for node in nodes {
+            node.await.map_err(io::Error::other)??;
+        }
+        Ok(())
+    }
+}
+
+fn dest_of(line: &str) -> Cow<'_, str> {
+    Envelope::parse(line)
+        .map(|envelope| envelope.dest)
+        .unwrap_or_default()
+}
+
+// Delivers what a node writes to the other nodes directly, the rest goes out.
+// Messages to nodes that already shut down are dropped.
+async fn route(
+    mut output: mpsc::UnboundedReceiver<String>,
+    node_ids: Arc<HashSet<String>>,
+    inboxes: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>,
+    transport: Arc<dyn Transport>,
+) {
+    while let Some(line) = output.recv().await {
+        let dest = dest_of(&line);
+        if !node_ids.contains(dest.as_ref()) {
+            transport.send(line);
+        } else if let Some(inbox) = inboxes.lock().unwrap().get(dest.as_ref()) {
+            let _ = inbox.send(line);
+        }
+    }
+}
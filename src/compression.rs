@@ -0,0 +1,101 @@
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+    sync::Mutex,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    canonical,
+    message::{MessageBody, MessageType},
+    metrics::Metrics,
+};
+
+// the only encoding understood so far, advertised in `capabilities`
+pub const GZIP: &str = "gzip";
+
+// gzip the text and encode it as base64 so it fits in a JSON string
+pub fn compress(text: &str) -> io::Result<String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(STANDARD.encode(encoder.finish()?))
+}
+
+pub fn decompress(payload: &str) -> io::Result<String> {
+    let bytes = STANDARD.decode(payload).map_err(io::Error::other)?;
+    let mut text = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+// Compresses bodies sent between nodes once they exceed `threshold` bytes, but
+// only towards peers that advertised they can decompress them.
+pub struct Compressor {
+    threshold: usize,
+    peers: Mutex<HashSet<String>>,
+}
+
+impl Compressor {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            peers: Default::default(),
+        }
+    }
+
+    pub fn add_peer(&self, node_id: &str, encodings: &[String]) {
+        if encodings.iter().any(|encoding| encoding == GZIP) {
+            self.peers.lock().unwrap().insert(node_id.to_owned());
+        }
+    }
+
+    pub fn capabilities() -> MessageBody {
+        MessageBody::with_type(MessageType::Capabilities {
+            encodings: vec![GZIP.to_owned()],
+        })
+    }
+
+    // wraps the body into a `compressed` message if that is worth it for `dest`
+    pub fn maybe_compress(
+        &self,
+        dest: &str,
+        body: MessageBody,
+        metrics: &Metrics,
+    ) -> io::Result<MessageBody> {
+        if !self.peers.lock().unwrap().contains(dest) {
+            return Ok(body);
+        }
+
+        let text = canonical::to_string(&body)?;
+        if text.len() < self.threshold {
+            return Ok(body);
+        }
+
+        let payload = compress(&text)?;
+        if payload.len() >= text.len() {
+            return Ok(body);
+        }
+
+        metrics.incr("compression.messages", 1);
+        metrics.incr("compression.bytes_before", text.len() as u64);
+        metrics.incr("compression.bytes_after", payload.len() as u64);
+
+        Ok(MessageBody::with_type(MessageType::Compressed {
+            encoding: GZIP.to_owned(),
+            payload,
+        }))
+    }
+}
+
+// unwraps a `compressed` body, other bodies are returned unchanged
+pub fn decompress_body(body: MessageBody) -> io::Result<MessageBody> {
+    let MessageType::Compressed { encoding, payload } = &body.msg_type else {
+        return Ok(body);
+    };
+    if encoding != GZIP {
+        return Err(io::Error::other(format!("unknown encoding {encoding}")));
+    }
+    Ok(serde_json::from_str(&decompress(payload)?)?)
+}
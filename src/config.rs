@@ -0,0 +1,340 @@
+use std::{collections::HashMap, env, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{
+    clock::Clock, extensions::Extensions, kv::KvService, maelstrom::Maelstrom,
+    rate_limit::RateLimit, transport::Transport,
+};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // how long an rpc without retries waits for its reply
+    pub rpc_timeout: Duration,
+    // resend cadence of rpcs with retries
+    pub rpc_retry_interval: Duration,
+    // log every received and sent message to stderr
+    pub log_messages: bool,
+    // answer retried requests (same src and msg_id) with the cached reply
+    pub dedup_requests: bool,
+    // how long replies are kept for replay, forever when unset
+    pub dedup_window: Option<Duration>,
+    // also answer lines that aren't a known request with a malformed-request error when src
+    // and msg_id are readable, requests of a known type with a bad field always are
+    pub reply_to_malformed: bool,
+    // upper bound on concurrently running request handlers, further requests wait in FIFO order,
+    // 0 counts as 1
+    pub max_concurrent_handlers: Option<usize>,
+    // cap on all outbound messages combined
+    pub global_rate_limit: Option<RateLimit>,
+    // cap applied to each destination, unless overridden in `dest_rate_limits`
+    pub dest_rate_limit: Option<RateLimit>,
+    pub dest_rate_limits: HashMap<String, RateLimit>,
+    // drop byte-identical messages sent again within this window, retransmissions
+    // and heartbeats excepted
+    pub outbound_dedup_window: Option<Duration>,
+    // gzip bodies of at least this many bytes sent to peers that support it
+    pub compress_threshold: Option<usize>,
+    // how long clients are expected to wait for a reply, sets the deadline of request contexts
+    pub request_timeout: Option<Duration>,
+    // exit loudly on protocol violations that are otherwise ignored
+    pub strict: bool,
+    // answer requests declined by the app with a not-supported error
+    pub reply_not_supported: bool,
+    // upper bound on concurrent rpcs of a single `rpc_all` call
+    pub rpc_concurrency: Option<usize>,
+    // upper bound on outstanding rpcs to a single destination, further rpcs wait in FIFO order,
+    // 0 counts as 1
+    pub rpc_inflight_per_dest: Option<usize>,
+    // send heartbeats to every peer at this cadence and run the failure detector
+    pub heartbeat_interval: Option<Duration>,
+    // silence after which a peer is suspected down
+    pub heartbeat_timeout: Duration,
+    // stamp messages between nodes with a lamport timestamp
+    pub lamport: bool,
+    // record every message to `<prefix>-<node_id>.jsonl`
+    pub trace_prefix: Option<String>,
+    // write sets sorted, see `canonical`; once on, it stays on for the whole process
+    pub canonical: bool,
+    // backend of `Kv::configured` clients, overriding the one chosen by the app
+    pub kv_service: Option<KvService>,
+    // serve reads of clients that opt in with `Kv::with_configured_cache` from a
+    // write-through cache, entries expire after this age
+    pub kv_cache: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rpc_timeout: Duration::from_millis(500),
+            rpc_retry_interval: Duration::from_millis(500),
+            log_messages: true,
+            dedup_requests: false,
+            dedup_window: None,
+            reply_to_malformed: false,
+            max_concurrent_handlers: None,
+            global_rate_limit: None,
+            dest_rate_limit: None,
+            dest_rate_limits: HashMap::new(),
+            outbound_dedup_window: None,
+            compress_threshold: None,
+            request_timeout: None,
+            strict: false,
+            reply_not_supported: true,
+            rpc_concurrency: None,
+            rpc_inflight_per_dest: None,
+            heartbeat_interval: None,
+            heartbeat_timeout: Duration::from_millis(1500),
+            lamport: false,
+            trace_prefix: None,
+            canonical: false,
+            kv_service: None,
+            kv_cache: None,
+        }
+    }
+}
+
+fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok()?.parse().ok()
+}
+
+fn env_millis(name: &str) -> Option<Duration> {
+    env_var(name).map(Duration::from_millis)
+}
+
+fn env_bool(name: &str) -> Option<bool> {
+    env::var(name)
+        .ok()
+        .map(|value| matches!(value.as_str(), "1" | "true" | "yes"))
+}
+
+// rate limits are given as `<per_second>` or `<per_second>:<burst>`, a rate
+// that isn't positive is ignored like any other unparsable value
+fn env_rate_limit(name: &str) -> Option<RateLimit> {
+    let value = env::var(name).ok()?;
+    let (per_second, burst) = match value.split_once(':') {
+        Some((per_second, burst)) => (per_second.parse().ok()?, burst.parse().ok()?),
+        None => (value.parse().ok()?, 1),
+    };
+    Some(RateLimit { per_second, burst }).filter(RateLimit::is_valid)
+}
+
+impl Config {
+    // Overrides settings from MAELSTROM_* environment variables, so runs can be
+    // tuned without code edits.
+    pub fn apply_env(&mut self) {
+        if let Some(timeout) = env_millis("MAELSTROM_RPC_TIMEOUT_MS") {
+            self.rpc_timeout = timeout;
+        }
+        if let Some(interval) = env_millis("MAELSTROM_RPC_RETRY_MS") {
+            self.rpc_retry_interval = interval;
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_LOG_MESSAGES") {
+            self.log_messages = enabled;
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_DEDUP_REQUESTS") {
+            self.dedup_requests = enabled;
+        }
+        if let Some(window) = env_millis("MAELSTROM_DEDUP_WINDOW_MS") {
+            self.dedup_window = Some(window);
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_REPLY_TO_MALFORMED") {
+            self.reply_to_malformed = enabled;
+        }
+        if let Some(limit) = env_var("MAELSTROM_MAX_HANDLERS") {
+            self.max_concurrent_handlers = Some(limit);
+        }
+        if let Some(limit) = env_rate_limit("MAELSTROM_RATE_LIMIT") {
+            self.global_rate_limit = Some(limit);
+        }
+        if let Some(limit) = env_rate_limit("MAELSTROM_DEST_RATE_LIMIT") {
+            self.dest_rate_limit = Some(limit);
+        }
+        if let Some(window) = env_millis("MAELSTROM_OUTBOUND_DEDUP_MS") {
+            self.outbound_dedup_window = Some(window);
+        }
+        if let Some(threshold) = env_var("MAELSTROM_COMPRESS_THRESHOLD") {
+            self.compress_threshold = Some(threshold);
+        }
+        if let Some(timeout) = env_millis("MAELSTROM_REQUEST_TIMEOUT_MS") {
+            self.request_timeout = Some(timeout);
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_STRICT") {
+            self.strict = enabled;
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_REPLY_NOT_SUPPORTED") {
+            self.reply_not_supported = enabled;
+        }
+        if let Some(limit) = env_var("MAELSTROM_RPC_CONCURRENCY") {
+            self.rpc_concurrency = Some(limit);
+        }
+        if let Some(limit) = env_var("MAELSTROM_RPC_INFLIGHT") {
+            self.rpc_inflight_per_dest = Some(limit);
+        }
+        if let Some(interval) = env_millis("MAELSTROM_HEARTBEAT_MS") {
+            self.heartbeat_interval = Some(interval);
+        }
+        if let Some(timeout) = env_millis("MAELSTROM_HEARTBEAT_TIMEOUT_MS") {
+            self.heartbeat_timeout = timeout;
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_LAMPORT") {
+            self.lamport = enabled;
+        }
+        if let Ok(prefix) = env::var("MAELSTROM_TRACE") {
+            self.trace_prefix = Some(prefix);
+        }
+        if let Some(enabled) = env_bool("MAELSTROM_CANONICAL") {
+            self.canonical = enabled;
+        }
+        if let Some(service) = env_var("MAELSTROM_KV_SERVICE") {
+            self.kv_service = Some(service);
+        }
+        if let Some(max_age) = env_millis("MAELSTROM_KV_CACHE_MS") {
+            self.kv_cache = Some(max_age);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MaelstromBuilder {
+    config: Config,
+    extensions: Extensions,
+    clock: Option<Arc<dyn Clock>>,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl MaelstromBuilder {
+    pub fn rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.config.rpc_timeout = timeout;
+        self
+    }
+
+    pub fn rpc_retry_interval(mut self, interval: Duration) -> Self {
+        self.config.rpc_retry_interval = interval;
+        self
+    }
+
+    pub fn log_messages(mut self, enabled: bool) -> Self {
+        self.config.log_messages = enabled;
+        self
+    }
+
+    pub fn dedup_requests(mut self, window: Option<Duration>) -> Self {
+        self.config.dedup_requests = true;
+        self.config.dedup_window = window;
+        self
+    }
+
+    pub fn reply_to_malformed(mut self, enabled: bool) -> Self {
+        self.config.reply_to_malformed = enabled;
+        self
+    }
+
+    pub fn max_concurrent_handlers(mut self, limit: usize) -> Self {
+        self.config.max_concurrent_handlers = Some(limit);
+        self
+    }
+
+    pub fn global_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.global_rate_limit = Some(limit);
+        self
+    }
+
+    pub fn dest_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.dest_rate_limit = Some(limit);
+        self
+    }
+
+    pub fn rate_limit_for(mut self, dest: impl Into<String>, limit: RateLimit) -> Self {
+        self.config.dest_rate_limits.insert(dest.into(), limit);
+        self
+    }
+
+    pub fn outbound_dedup_window(mut self, window: Duration) -> Self {
+        self.config.outbound_dedup_window = Some(window);
+        self
+    }
+
+    pub fn compress_threshold(mut self, bytes: usize) -> Self {
+        self.config.compress_threshold = Some(bytes);
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.config.strict = enabled;
+        self
+    }
+
+    pub fn reply_not_supported(mut self, enabled: bool) -> Self {
+        self.config.reply_not_supported = enabled;
+        self
+    }
+
+    pub fn rpc_concurrency(mut self, limit: usize) -> Self {
+        self.config.rpc_concurrency = Some(limit);
+        self
+    }
+
+    pub fn rpc_inflight_per_dest(mut self, limit: usize) -> Self {
+        self.config.rpc_inflight_per_dest = Some(limit);
+        self
+    }
+
+    pub fn heartbeat(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.config.heartbeat_interval = Some(interval);
+        self.config.heartbeat_timeout = timeout;
+        self
+    }
+
+    pub fn lamport(mut self, enabled: bool) -> Self {
+        self.config.lamport = enabled;
+        self
+    }
+
+    pub fn trace(mut self, prefix: impl Into<String>) -> Self {
+        self.config.trace_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn canonical(mut self, enabled: bool) -> Self {
+        self.config.canonical = enabled;
+        self
+    }
+
+    pub fn kv_service(mut self, service: KvService) -> Self {
+        self.config.kv_service = Some(service);
+        self
+    }
+
+    pub fn kv_cache(mut self, max_age: Duration) -> Self {
+        self.config.kv_cache = Some(max_age);
+        self
+    }
+
+    // time source of retries, timers and periodic loops, e.g. a `VirtualClock` in tests
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    // where messages are read from and written to, stdin / stdout by default
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    // shared state available to handlers and helpers through `maelstrom.state::<T>()`
+    pub fn state<T: Send + Sync + 'static>(self, value: T) -> Self {
+        self.extensions.insert(value);
+        self
+    }
+
+    // environment variables take precedence over values set in code
+    pub fn build(mut self) -> Maelstrom {
+        self.config.apply_env();
+        Maelstrom::with_parts(self.config, self.extensions, self.clock, self.transport)
+    }
+}
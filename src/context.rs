@@ -0,0 +1,128 @@
+use std::{
+    future::Future,
+    io,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use crate::{
+    maelstrom::Maelstrom,
+    message::{ErrorCode, Message, MessageBody, MsgId, NodeId},
+};
+
+// Per-request handle given to handlers. Replies sent through it always carry
+// the `in_reply_to` of the request, the rest of the runtime is reachable
+// through deref.
+#[derive(Clone)]
+pub struct Context {
+    maelstrom: Maelstrom,
+    pub src: NodeId,
+    pub msg_id: Option<MsgId>,
+    // Point in time after which the client has likely given up on the request.
+    // The handler is aborted once it passes and rpcs made on its behalf give up
+    // early.
+    pub deadline: Option<Instant>,
+    // lamport time right after the request was received, None unless the clock is enabled
+    pub timestamp: Option<u64>,
+    // trace id of the client request being served, carried along to peers
+    pub trace_id: Option<String>,
+    replied: Arc<AtomicBool>,
+    // msg_id of the latest part sent with `reply_part`
+    last_part: Arc<Mutex<Option<MsgId>>>,
+}
+
+impl Context {
+    pub fn new(
+        maelstrom: Maelstrom,
+        src: NodeId,
+        msg_id: Option<MsgId>,
+        deadline: Option<Instant>,
+        timestamp: Option<u64>,
+        trace_id: Option<String>,
+    ) -> Self {
+        Self {
+            maelstrom,
+            src,
+            msg_id,
+            deadline,
+            timestamp,
+            trace_id,
+            replied: Default::default(),
+            last_part: Default::default(),
+        }
+    }
+
+    pub fn maelstrom(&self) -> &Maelstrom {
+        &self.maelstrom
+    }
+
+    // the final reply, it also ends a stream of parts if any were sent
+    pub fn reply(&self, mut body: MessageBody) -> io::Result<()> {
+        self.replied.store(true, Ordering::Relaxed);
+        body.prev = self.last_part.lock().unwrap().clone();
+        self.maelstrom
+            .reply_to(&self.src, self.msg_id.clone(), body)
+    }
+
+    // Sends one part of a streamed reply. Parts get a msg_id of their own and
+    // name the part before them, so `rpc_stream` can put them back in order.
+    pub fn reply_part(&self, mut body: MessageBody) -> io::Result<()> {
+        let mut last_part = self.last_part.lock().unwrap();
+        let msg_id = MsgId::from(self.maelstrom.next_msg_id());
+        body.msg_id = Some(msg_id.clone());
+        body.prev = last_part.clone();
+        body.more = Some(true);
+        self.maelstrom
+            .reply_to(&self.src, self.msg_id.clone(), body)?;
+        *last_part = Some(msg_id);
+        Ok(())
+    }
+
+    // ends a stream started with `reply_part`, same as `reply`
+    pub fn reply_final(&self, body: MessageBody) -> io::Result<()> {
+        self.reply(body)
+    }
+
+    pub fn replied(&self) -> bool {
+        self.replied.load(Ordering::Relaxed)
+    }
+
+    // like `Maelstrom::forward`, counts as the reply to this request
+    pub async fn forward(&self, request: &Message, owner: &str) -> io::Result<()> {
+        let body = self.maelstrom.relay(request, owner).await?;
+        self.reply(body)
+    }
+
+    pub fn reply_error(&self, code: ErrorCode, text: impl Into<String>) -> io::Result<()> {
+        self.reply(MessageBody::error(code, text))
+    }
+}
+
+impl Deref for Context {
+    type Target = Maelstrom;
+
+    fn deref(&self) -> &Self::Target {
+        &self.maelstrom
+    }
+}
+
+tokio::task_local! {
+    // deadline of the request the current task works for
+    static DEADLINE: Option<Instant>;
+}
+
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(Clone::clone).ok().flatten()
+}
+
+// runs the future with `deadline` as the current deadline
+pub fn with_deadline<F: Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    DEADLINE.scope(deadline, future)
+}
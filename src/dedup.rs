@@ -0,0 +1,159 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::message::MsgId;
+
+pub enum Lookup {
+    // first time we see this request, the handler should run
+    New,
+    // a handler for this request is still running
+    InFlight,
+    // the request was already answered, replay the serialized reply
+    Replied(String),
+}
+
+enum Entry {
+    InFlight,
+    Replied(String),
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<(String, MsgId), Entry>,
+    // replies in the order they were cached, used to expire them
+    expiry: VecDeque<(Instant, (String, MsgId))>,
+}
+
+// Caches replies keyed by (src, msg_id) so retried requests are answered with the
+// previous reply instead of re-running the handler. Replies are kept for `window`,
+// or for the lifetime of the node when no window is set.
+#[derive(Default)]
+pub struct RequestCache {
+    window: Option<Duration>,
+    state: Mutex<CacheState>,
+}
+
+impl RequestCache {
+    pub fn new(window: Option<Duration>) -> Self {
+        Self {
+            window,
+            state: Default::default(),
+        }
+    }
+
+    pub fn begin(&self, src: &str, msg_id: MsgId, now: Instant) -> Lookup {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, now);
+
+        let key = (src.to_owned(), msg_id);
+        match state.entries.get(&key) {
+            Some(Entry::InFlight) => Lookup::InFlight,
+            Some(Entry::Replied(line)) => Lookup::Replied(line.to_owned()),
+            None => {
+                state.entries.insert(key, Entry::InFlight);
+                Lookup::New
+            }
+        }
+    }
+
+    // the cached reply of an answered request, without marking it in flight otherwise
+    pub fn replied(&self, src: &str, msg_id: MsgId, now: Instant) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state, now);
+        match state.entries.get(&(src.to_owned(), msg_id)) {
+            Some(Entry::Replied(line)) => Some(line.to_owned()),
+            _ => None,
+        }
+    }
+
+    pub fn complete(&self, src: &str, msg_id: MsgId, line: String, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let key = (src.to_owned(), msg_id);
+        state.entries.insert(key.to_owned(), Entry::Replied(line));
+        if self.window.is_some() {
+            state.expiry.push_back((now, key));
+        }
+    }
+
+    // forget a request whose handler finished without replying, so a retry runs it again
+    pub fn abandon(&self, src: &str, msg_id: MsgId) {
+        let mut state = self.state.lock().unwrap();
+        let key = (src.to_owned(), msg_id);
+        if let Some(Entry::InFlight) = state.entries.get(&key) {
+            state.entries.remove(&key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn expire(&self, state: &mut CacheState, now: Instant) {
+        let Some(window) = self.window else {
+            return;
+        };
+
+        while let Some((cached_at, _)) = state.expiry.front() {
+            if now.duration_since(*cached_at) < window {
+                break;
+            }
+            let (_, key) = state.expiry.pop_front().unwrap();
+            if let Some(Entry::Replied(_)) = state.entries.get(&key) {
+                state.entries.remove(&key);
+            }
+        }
+    }
+}
+
+// Drops byte-identical outbound messages sent again within `window`, such as
+// the same gossip produced twice. Lines are compared whole, so distinct
+// messages are never mistaken for each other. Retransmissions and heartbeats
+// repeat lines on purpose and bypass this, see `Maelstrom::resend`.
+pub struct OutboundDedup {
+    window: Duration,
+    state: Mutex<OutboundState>,
+}
+
+#[derive(Default)]
+struct OutboundState {
+    seen: HashMap<Arc<str>, Instant>,
+    expiry: VecDeque<(Instant, Arc<str>)>,
+}
+
+impl OutboundDedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Default::default(),
+        }
+    }
+
+    // returns false if the same line was already sent within the window
+    pub fn admit(&self, line: &str, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while let Some((sent_at, _)) = state.expiry.front() {
+            if now.duration_since(*sent_at) < self.window {
+                break;
+            }
+            let (sent_at, expired) = state.expiry.pop_front().unwrap();
+            if state.seen.get(&expired) == Some(&sent_at) {
+                state.seen.remove(&expired);
+            }
+        }
+
+        if state.seen.contains_key(line) {
+            return false;
+        }
+        let line: Arc<str> = line.into();
+        state.seen.insert(line.clone(), now);
+        state.expiry.push_back((now, line));
+        true
+    }
+}
@@ -0,0 +1,37 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+// Type keyed map of shared values, one per type.
+#[derive(Default)]
+pub struct Extensions {
+    values: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl Extensions {
+    // replaces the value of the same type, if any
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self.values.lock().unwrap().get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+
+    pub fn get_or_init<T: Send + Sync + 'static>(&self, init: impl FnOnce() -> T) -> Arc<T> {
+        let value = self
+            .values
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(init()))
+            .clone();
+        value.downcast().unwrap()
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::broadcast;
+
+// capacity of the event channel, slow subscribers miss older events
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    Up(String),
+    Down(String),
+}
+
+// Timeout based failure detector. Any message from a peer, heartbeats
+// included, counts as a sign of life; a peer silent for longer than `timeout`
+// is suspected down until it is heard from again. Times come from the
+// runtime's clock, so a virtual clock drives it in tests.
+pub struct FailureDetector {
+    timeout: Duration,
+    started: Instant,
+    last_seen: Mutex<HashMap<String, Instant>>,
+    down: Mutex<HashSet<String>>,
+    events: broadcast::Sender<PeerEvent>,
+}
+
+impl FailureDetector {
+    pub fn new(timeout: Duration, started: Instant) -> Self {
+        Self {
+            timeout,
+            started,
+            last_seen: Default::default(),
+            down: Default::default(),
+            events: broadcast::Sender::new(EVENT_CAPACITY),
+        }
+    }
+
+    pub fn observe(&self, peer: &str, now: Instant) {
+        self.last_seen.lock().unwrap().insert(peer.to_owned(), now);
+
+        if self.down.lock().unwrap().remove(peer) {
+            let _ = self.events.send(PeerEvent::Up(peer.to_owned()));
+        }
+    }
+
+    // marks peers silent for longer than the timeout as down
+    pub fn check<'a>(&self, peers: impl IntoIterator<Item = &'a str>, now: Instant) {
+        let last_seen = self.last_seen.lock().unwrap();
+        let mut down = self.down.lock().unwrap();
+        for peer in peers {
+            // peers never heard from get the same grace period from startup
+            let seen = last_seen.get(peer).copied().unwrap_or(self.started);
+            if now.duration_since(seen) > self.timeout && down.insert(peer.to_owned()) {
+                let _ = self.events.send(PeerEvent::Down(peer.to_owned()));
+            }
+        }
+    }
+
+    pub fn is_alive(&self, peer: &str) -> bool {
+        !self.down.lock().unwrap().contains(peer)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    io,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    checksum::checksum,
+    maelstrom::Maelstrom,
+    message::{Message, MessageBody, NodeId},
+    sync::TrackedMutex,
+};
+
+// default anti-entropy cadence
+pub const GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
+
+// anything a `GossipSet` can hold
+pub trait Element:
+    Clone + Eq + Hash + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+}
+
+impl<T: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + Sync + 'static> Element for T {}
+
+// Elements of the set `set` the sender holds and the receiver isn't known to.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "set_gossip")]
+struct SetGossip<T> {
+    set: String,
+    elements: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "set_gossip_ok")]
+struct SetGossipOk {}
+
+// Grow-only set replicated to every other node by anti-entropy, for any
+// serde element type rather than the broadcast payloads of `BroadcastApp`.
+//
+// Every interval each peer is sent the elements it isn't known to hold yet; a
+// peer is known to hold an element once it acked a gossip carrying it or sent
+// it to us. Lost gossip is simply resent on the next tick, so the set of every
+// node converges once the network heals. A node may replicate several sets,
+// their names tell them apart on the wire.
+pub struct GossipSet<T> {
+    name: String,
+    period: Duration,
+    elements: TrackedMutex<HashSet<T>>,
+    known: Arc<TrackedMutex<HashMap<NodeId, HashSet<T>>>>,
+}
+
+impl<T: Element> GossipSet<T> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            period: GOSSIP_INTERVAL,
+            elements: Default::default(),
+            known: Default::default(),
+        }
+    }
+
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    // adds elements to the local replica, returning the ones not seen before
+    pub async fn insert(&self, elements: impl IntoIterator<Item = T>) -> Vec<T> {
+        let mut data = self.elements.lock().await;
+        elements
+            .into_iter()
+            .filter(|element| data.insert(element.clone()))
+            .collect()
+    }
+
+    pub async fn contains(&self, element: &T) -> bool {
+        self.elements.lock().await.contains(element)
+    }
+
+    pub async fn elements(&self) -> HashSet<T> {
+        self.elements.lock().await.clone()
+    }
+
+    pub async fn checksum(&self) -> u64 {
+        checksum(self.elements.lock().await.iter())
+    }
+
+    // Merges gossip of this set and returns the ack to reply with, None if
+    // the request isn't gossip of this set.
+    pub async fn on_message(&self, request: &Message) -> Option<MessageBody> {
+        let gossip = request.body.to_custom::<SetGossip<T>>().ok()?;
+        if gossip.set != self.name {
+            return None;
+        }
+        self.insert(gossip.elements.iter().cloned()).await;
+        self.known
+            .lock()
+            .await
+            .entry(request.src.to_owned())
+            .or_default()
+            .extend(gossip.elements);
+        // serializing a tagged struct into a body can't fail
+        Some(MessageBody::from_custom(&SetGossipOk {}).expect("set gossip ack"))
+    }
+
+    async fn missing(&self, maelstrom: &Maelstrom) -> Vec<(NodeId, Vec<T>)> {
+        let elements = self.elements.lock().await;
+        let known = self.known.lock().await;
+        maelstrom
+            .other_node_ids()
+            .into_iter()
+            .map(|peer| {
+                let missing = match known.get(&peer) {
+                    Some(known) => elements.difference(known).cloned().collect(),
+                    None => elements.iter().cloned().collect(),
+                };
+                (peer, missing)
+            })
+            .filter(|(_, missing): &(NodeId, Vec<T>)| !missing.is_empty())
+            .collect()
+    }
+
+    fn gossip(&self, elements: Vec<T>) -> io::Result<MessageBody> {
+        MessageBody::from_custom(&SetGossip {
+            set: self.name.to_owned(),
+            elements,
+        })
+    }
+
+    // Sends each peer what it is missing every interval, runs until the
+    // process exits. Peers suspected down are skipped until they are back.
+    pub async fn run(self: Arc<Self>, maelstrom: Maelstrom) {
+        let mut interval = maelstrom.interval(self.period);
+        loop {
+            interval.tick().await;
+            for (dest, elements) in self.missing(&maelstrom).await {
+                if !maelstrom.is_alive(&dest) {
+                    continue;
+                }
+                let body = match self.gossip(elements.clone()) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        maelstrom.log(format!("Error: encoding {} gossip failed: {e}", self.name));
+                        continue;
+                    }
+                };
+                let (maelstrom, known) = (maelstrom.clone(), self.known.clone());
+                maelstrom.clone().spawn(async move {
+                    // no retries, whatever is still missing goes out on the next tick
+                    if maelstrom.rpc(dest.to_owned(), body, false).await.is_ok() {
+                        known.lock().await.entry(dest).or_default().extend(elements);
+                    }
+                });
+            }
+        }
+    }
+
+    // last chance to push missing elements at shutdown, acks can no longer arrive
+    pub async fn flush(&self, maelstrom: &Maelstrom) -> io::Result<()> {
+        for (dest, elements) in self.missing(maelstrom).await {
+            maelstrom.send_with_id(dest, self.gossip(elements)?)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::{future::Future, io, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    context::Context,
+    maelstrom::{not_supported, App, Maelstrom},
+    message::{Message, MessageBody, MessageType},
+    request::Request,
+};
+
+// Typed handler for a single request type, the returned body is sent as the reply.
+#[async_trait]
+pub trait Handle<T: Request>: Send + Sync {
+    async fn handle(&self, ctx: &Context, request: T) -> io::Result<MessageBody>;
+}
+
+type Reply = Pin<Box<dyn Future<Output = io::Result<Option<MessageBody>>> + Send>>;
+
+struct Route<A> {
+    matches: fn(&MessageType) -> bool,
+    call: Box<dyn Fn(Arc<A>, Context, MessageType) -> Reply + Send + Sync>,
+}
+
+// Routes every request to the `Handle` impl of its type. Requests without a
+// route are declined as not supported.
+pub struct Router<A> {
+    app: Arc<A>,
+    routes: Vec<Route<A>>,
+}
+
+impl<A: Send + Sync + 'static> Router<A> {
+    pub fn new(app: A) -> Self {
+        Self {
+            app: Arc::new(app),
+            routes: vec![],
+        }
+    }
+
+    pub fn route<T>(mut self) -> Self
+    where
+        T: Request + Send + 'static,
+        A: Handle<T>,
+    {
+        self.routes.push(Route {
+            matches: T::matches,
+            call: Box::new(|app, ctx, msg_type| {
+                Box::pin(async move {
+                    let Some(request) = T::from_type(msg_type) else {
+                        return Ok(None);
+                    };
+                    app.handle(&ctx, request).await.map(Some)
+                })
+            }),
+        });
+        self
+    }
+}
+
+#[async_trait]
+impl<A: Send + Sync + 'static> App for Router<A> {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let msg_type = request.body.msg_type;
+        match self.routes.iter().find(|route| (route.matches)(&msg_type)) {
+            Some(route) => (route.call)(self.app.clone(), ctx, msg_type).await,
+            None => Err(not_supported(&msg_type)),
+        }
+    }
+}
+
+// Handler for workloads that never wait on anything, a plain function or
+// closure works as well. Wrap it in `SyncAdapter` to run it.
+pub trait SyncApp: Send + Sync {
+    fn handle(&self, ctx: &Context, request: Message) -> io::Result<Option<MessageBody>>;
+}
+
+impl<F> SyncApp for F
+where
+    F: Fn(&Context, Message) -> io::Result<Option<MessageBody>> + Send + Sync,
+{
+    fn handle(&self, ctx: &Context, request: Message) -> io::Result<Option<MessageBody>> {
+        self(ctx, request)
+    }
+}
+
+// Runs a `SyncApp` as an `App`, the handler is called inline on the request task.
+pub struct SyncAdapter<A>(pub A);
+
+#[async_trait]
+impl<A: SyncApp> App for SyncAdapter<A> {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        self.0.handle(&ctx, request)
+    }
+}
+
+// App speaking a protocol of its own, so a new workload doesn't need variants
+// in `MessageType`. `Body` is any serde type tagged with `type`, see
+// `MessageBody::from_custom`; init, errors and the other runtime messages are
+// still handled by the runtime. Wrap it in `ProtocolAdapter` to run it.
+#[async_trait]
+pub trait ProtocolApp: Send + Sync {
+    type Body: Serialize + DeserializeOwned + Send;
+
+    async fn handle(&self, ctx: Context, request: Self::Body) -> io::Result<Option<Self::Body>>;
+
+    async fn shutdown(&self, _maelstrom: Maelstrom) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs a `ProtocolApp` as an `App`. Requests that don't parse as its body type
+// are declined as not supported.
+pub struct ProtocolAdapter<A>(pub A);
+
+#[async_trait]
+impl<A: ProtocolApp> App for ProtocolAdapter<A> {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let Ok(body) = request.body.to_custom::<A::Body>() else {
+            return Err(not_supported(&request.body.msg_type));
+        };
+        match self.0.handle(ctx, body).await? {
+            Some(reply) => MessageBody::from_custom(&reply).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn shutdown(&self, maelstrom: Maelstrom) -> io::Result<()> {
+        self.0.shutdown(maelstrom).await
+    }
+}
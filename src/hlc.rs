@@ -0,0 +1,72 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{clock::Clock, kv::now_millis};
+
+// low bits of a timestamp that count events within the same millisecond
+pub const LOGICAL_BITS: u32 = 16;
+
+// Hybrid logical clock: timestamps are wall clock milliseconds shifted left by
+// `LOGICAL_BITS`, with a logical counter in the low bits. They stay close to
+// the wall clock, yet strictly increase on every node and order after every
+// timestamp the node observed from others, even when clocks drift apart.
+//
+// The wall clock is read once at creation and then moves with the runtime
+// clock, so a virtual clock drives it in tests.
+pub struct HybridClock {
+    clock: Arc<dyn Clock>,
+    origin_millis: i64,
+    origin: Instant,
+    last: AtomicU64,
+}
+
+impl HybridClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let origin = clock.now();
+        Self {
+            clock,
+            origin_millis: now_millis(),
+            origin,
+            last: AtomicU64::new(0),
+        }
+    }
+
+    fn wall(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.origin);
+        let millis = self.origin_millis + elapsed.as_millis() as i64;
+        (millis.max(0) as u64) << LOGICAL_BITS
+    }
+
+    // a timestamp for a local event or send
+    pub fn now(&self) -> u64 {
+        self.advance(0)
+    }
+
+    // merges the timestamp of a received message and returns the new time
+    pub fn observe(&self, remote: u64) -> u64 {
+        self.advance(remote)
+    }
+
+    fn advance(&self, remote: u64) -> u64 {
+        let wall = self.wall();
+        let next = |last: u64| {
+            wall.max(last.saturating_add(1))
+                .max(remote.saturating_add(1))
+        };
+        let previous = self
+            .last
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last)))
+            .unwrap_or_default();
+        next(previous)
+    }
+
+    // wall clock milliseconds of a timestamp
+    pub fn millis(ts: u64) -> i64 {
+        (ts >> LOGICAL_BITS) as i64
+    }
+}
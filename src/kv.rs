@@ -0,0 +1,732 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    error, fmt,
+    future::Future,
+    hash::BuildHasher,
+    io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as Json;
+
+use crate::{
+    maelstrom::Maelstrom,
+    message::{ErrorCode, KvKey, Message, MessageBody, MessageType, ReadReply, ReadRequest, Value},
+};
+
+mod batch;
+mod cache;
+mod content;
+mod counter;
+mod expiring;
+mod txn;
+mod versioned;
+
+pub use batch::{Merge, WriteBatcher};
+pub use cache::ReadCache;
+pub use content::ContentStore;
+pub use counter::KvCounter;
+pub use expiring::{now_millis, Expiring};
+pub use txn::{Txn, TxnStore};
+pub use versioned::Versioned;
+
+// Failure of a kv service request, so callers can tell a missing key from a
+// lost cas race or a request that never got an answer.
+#[derive(Debug)]
+pub enum KvError {
+    KeyDoesNotExist,
+    // the current value didn't match the `from` of a cas
+    PreconditionFailed(String),
+    // any other error reply of the service
+    Service(ErrorCode, String),
+    // no reply, e.g. the rpc timed out
+    Io(io::Error),
+    // a value that doesn't fit the type it is read as or written from
+    Serde(serde_json::Error),
+}
+
+impl KvError {
+    // the request definitely did not take effect
+    pub fn is_definite(&self) -> bool {
+        match self {
+            KvError::KeyDoesNotExist | KvError::PreconditionFailed(_) => true,
+            KvError::Service(code, _) => code.is_definite(),
+            KvError::Io(_) => false,
+            KvError::Serde(_) => true,
+        }
+    }
+
+    // worth sending again: it failed for a passing reason and definitely
+    // didn't apply, or it is a read and can't have changed anything
+    fn is_retryable(&self, read: bool) -> bool {
+        match self {
+            KvError::Service(ErrorCode::TemporarilyUnavailable, _) => true,
+            e => read && !e.is_definite(),
+        }
+    }
+
+    // None when the body is not an error reply
+    pub fn from_body(body: &MessageBody) -> Option<Self> {
+        let (code, text) = body.as_error()?;
+        Some(match code {
+            ErrorCode::KeyDoesNotExist => KvError::KeyDoesNotExist,
+            ErrorCode::PreconditionFailed => KvError::PreconditionFailed(text.to_owned()),
+            code => KvError::Service(code, text.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KvError::KeyDoesNotExist => write!(f, "key does not exist"),
+            KvError::PreconditionFailed(text) => write!(f, "precondition failed: {text}"),
+            KvError::Service(code, text) => write!(f, "kv request failed ({code}): {text}"),
+            KvError::Io(e) => write!(f, "kv request failed: {e}"),
+            KvError::Serde(e) => write!(f, "kv value has an unexpected shape: {e}"),
+        }
+    }
+}
+
+impl error::Error for KvError {}
+
+impl From<io::Error> for KvError {
+    fn from(e: io::Error) -> Self {
+        KvError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for KvError {
+    fn from(e: serde_json::Error) -> Self {
+        KvError::Serde(e)
+    }
+}
+
+// rpc errors keep their kind, so a timeout is still answered as one
+impl From<KvError> for io::Error {
+    fn from(e: KvError) -> Self {
+        match e {
+            KvError::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+async fn request(
+    maelstrom: &Maelstrom,
+    service: &str,
+    msg_type: MessageType,
+) -> Result<Message, KvError> {
+    let body = MessageBody::with_type(msg_type);
+    let response = maelstrom.rpc(service, body, false).await?;
+    match KvError::from_body(&response.body) {
+        Some(e) => Err(e),
+        None => Ok(response),
+    }
+}
+
+pub fn read_body(key: impl Into<KvKey>) -> MessageBody {
+    MessageBody::with_type(MessageType::Read(ReadRequest::Kv { key: key.into() }))
+}
+
+// value carried by the reply to a `read_body` request, for reads sent through
+// `rpc_all` and the like
+pub fn read_reply(response: Message) -> Result<Value, KvError> {
+    if let Some(e) = KvError::from_body(&response.body) {
+        return Err(e);
+    }
+    match response.body.msg_type {
+        MessageType::ReadOk(ReadReply::Value { value }) => Ok(value),
+        other => Err(KvError::Io(io::Error::other(format!(
+            "unexpected reply {} to kv read",
+            other.name()
+        )))),
+    }
+}
+
+// value of `key` in the kv `service`, e.g. "lin-kv"
+pub async fn read(
+    maelstrom: &Maelstrom,
+    service: &str,
+    key: impl Into<KvKey>,
+) -> Result<Value, KvError> {
+    let response = maelstrom.rpc(service, read_body(key), false).await?;
+    read_reply(response)
+}
+
+pub async fn write(
+    maelstrom: &Maelstrom,
+    service: &str,
+    key: impl Into<KvKey>,
+    value: Value,
+) -> Result<(), KvError> {
+    let msg_type = MessageType::Write {
+        key: key.into(),
+        value,
+    };
+    request(maelstrom, service, msg_type).await?;
+    Ok(())
+}
+
+// sets `key` to `to` if it currently holds `from`, or doesn't exist yet and
+// `create_if_not_exists` is set
+pub async fn cas(
+    maelstrom: &Maelstrom,
+    service: &str,
+    key: impl Into<KvKey>,
+    from: Value,
+    to: Value,
+    create_if_not_exists: bool,
+) -> Result<(), KvError> {
+    let msg_type = MessageType::Cas {
+        key: key.into(),
+        from,
+        to,
+        create_if_not_exists: Some(create_if_not_exists),
+    };
+    request(maelstrom, service, msg_type).await?;
+    Ok(())
+}
+
+// How often a kv request that failed for a passing reason is sent again.
+// Reads are retried after timeouts as well, writes and cas only when the
+// service reported that they didn't apply.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // attempts in total, the first one included
+    pub attempts: u32,
+    // pause before the first retry, doubled before each further one
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub const NONE: Self = Self {
+        attempts: 1,
+        backoff: Duration::ZERO,
+    };
+
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            backoff,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(50))
+    }
+}
+
+// how often `Kv::update` starts over after losing a race with another writer
+pub const DEFAULT_CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    attempts: 10,
+    backoff: Duration::from_millis(5),
+};
+
+// Pauses between the reads of `Kv::wait_until`, doubling from `initial` up to
+// `max`. Each pause is shortened by a random share of up to `jitter`, 0 to 1,
+// so nodes waiting for the same key don't read in lockstep.
+#[derive(Debug, Clone, Copy)]
+pub struct PollBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+}
+
+impl PollBackoff {
+    fn pause(&self, pause: Duration) -> Duration {
+        // a randomly seeded hasher makes do as a random number
+        let random = RandomState::new().hash_one(()) as f64 / u64::MAX as f64;
+        pause.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random)
+    }
+}
+
+impl Default for PollBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(5),
+            max: Duration::from_millis(200),
+            jitter: 0.5,
+        }
+    }
+}
+
+// reads of a `read_all` or `multi_get` in flight at once, unless lowered by
+// `rpc_concurrency`
+pub const DEFAULT_READ_CONCURRENCY: usize = 16;
+
+// a read result with a missing key as None, any other error stays an error
+pub fn optional<T>(result: Result<T, KvError>) -> Result<Option<T>, KvError> {
+    match result {
+        Err(KvError::KeyDoesNotExist) => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<Value, KvError> {
+    Ok(serde_json::to_value(value)?.into())
+}
+
+fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, KvError> {
+    Ok(serde_json::from_value(Json::from(value))?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    Linearizable,
+    // a node sees its own writes, writes of others may show up late
+    Sequential,
+    // totally available, concurrent writes are resolved by timestamp
+    LastWriteWins,
+}
+
+// Maelstrom's kv services, picked per app or by `MAELSTROM_KV_SERVICE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvService {
+    Lin,
+    Seq,
+    Lww,
+}
+
+impl KvService {
+    pub fn name(self) -> &'static str {
+        match self {
+            KvService::Lin => "lin-kv",
+            KvService::Seq => "seq-kv",
+            KvService::Lww => "lww-kv",
+        }
+    }
+
+    pub fn consistency(self) -> Consistency {
+        match self {
+            KvService::Lin => Consistency::Linearizable,
+            KvService::Seq => Consistency::Sequential,
+            KvService::Lww => Consistency::LastWriteWins,
+        }
+    }
+}
+
+impl fmt::Display for KvService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KvService {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "lin-kv" => Ok(KvService::Lin),
+            "seq-kv" => Ok(KvService::Seq),
+            "lww-kv" => Ok(KvService::Lww),
+            other => Err(format!("unknown kv service {other}")),
+        }
+    }
+}
+
+// Client of one of Maelstrom's kv services, reading and writing any serde type.
+//
+// On seq-kv reads may return stale values: a node always sees its own writes,
+// but not necessarily the latest writes of other nodes. `sync` writes a unique
+// value to a key all nodes share, which orders everything read after it behind
+// the writes that completed before; `with_sync_reads` does so before every
+// read. On lww-kv every node reads and writes its own replica, so reads may be
+// stale, concurrent writes can be lost and a cas compares against the local
+// replica only.
+//
+// `scoped` gives a client whose keys all live under a prefix, so apps and
+// parts of an app sharing a service can't collide.
+//
+// `with_cache` serves reads from the values this client last read or wrote,
+// for at most the given age. Cached reads skip `sync` and may miss writes of
+// other nodes within that age; a failed write or cas drops the key, so an
+// `update` that lost a race starts over from a fresh read. That makes it
+// unsafe for a plain read followed by a write of a key other nodes write too,
+// such as a kafka log or a txn root: the write is based on a stale read and
+// silently undoes theirs. Only keys a single node writes, or ones only ever
+// changed through cas, are safe to cache.
+#[derive(Clone)]
+pub struct Kv {
+    maelstrom: Maelstrom,
+    service: KvService,
+    retry: RetryPolicy,
+    conflict_retry: RetryPolicy,
+    poll_backoff: PollBackoff,
+    sync_reads: bool,
+    read_concurrency: usize,
+    syncs: Arc<AtomicU64>,
+    // prepended to every key, empty or ending in '/'
+    prefix: String,
+    // shared by clones and scopes, keyed by full key
+    cache: Option<Arc<ReadCache>>,
+}
+
+impl Kv {
+    pub const SYNC_KEY: &'static str = "__sync";
+
+    pub fn new(maelstrom: Maelstrom, service: KvService) -> Self {
+        Self {
+            maelstrom,
+            service,
+            retry: RetryPolicy::default(),
+            conflict_retry: DEFAULT_CONFLICT_RETRY,
+            poll_backoff: PollBackoff::default(),
+            sync_reads: false,
+            read_concurrency: DEFAULT_READ_CONCURRENCY,
+            syncs: Default::default(),
+            prefix: String::new(),
+            cache: None,
+        }
+    }
+
+    // `service` unless the config names another one
+    pub fn configured(maelstrom: Maelstrom, service: KvService) -> Self {
+        let service = maelstrom.config().kv_service.unwrap_or(service);
+        Self::new(maelstrom, service)
+    }
+
+    pub fn lin(maelstrom: Maelstrom) -> Self {
+        Self::new(maelstrom, KvService::Lin)
+    }
+
+    pub fn seq(maelstrom: Maelstrom) -> Self {
+        Self::new(maelstrom, KvService::Seq)
+    }
+
+    pub fn lww(maelstrom: Maelstrom) -> Self {
+        Self::new(maelstrom, KvService::Lww)
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_conflict_retry(mut self, retry: RetryPolicy) -> Self {
+        self.conflict_retry = retry;
+        self
+    }
+
+    pub fn with_poll_backoff(mut self, backoff: PollBackoff) -> Self {
+        self.poll_backoff = backoff;
+        self
+    }
+
+    pub fn with_sync_reads(mut self, enabled: bool) -> Self {
+        self.sync_reads = enabled;
+        self
+    }
+
+    pub fn with_read_concurrency(mut self, limit: usize) -> Self {
+        self.read_concurrency = limit.max(1);
+        self
+    }
+
+    // a fresh cache, not shared with the clients this one was cloned from;
+    // see above for which keys it is unsafe with
+    pub fn with_cache(mut self, max_age: Duration) -> Self {
+        self.cache = Some(Arc::new(ReadCache::new(max_age)));
+        self
+    }
+
+    // `with_cache` with the age the config sets, if any, for apps whose keys
+    // are all safe to cache
+    pub fn with_configured_cache(self) -> Self {
+        match self.maelstrom.config().kv_cache {
+            Some(max_age) => self.with_cache(max_age),
+            None => self,
+        }
+    }
+
+    pub fn service(&self) -> KvService {
+        self.service
+    }
+
+    // the same client with keys under `<scope>/`, nested scopes add up
+    pub fn scoped(&self, scope: &str) -> Self {
+        let mut kv = self.clone();
+        kv.prefix = format!("{}{}/", self.prefix, scope.trim_matches('/'));
+        kv
+    }
+
+    // the key as stored in the service
+    pub fn full_key(&self, key: impl Into<KvKey>) -> KvKey {
+        let key = key.into();
+        if self.prefix.is_empty() {
+            key
+        } else {
+            KvKey(format!("{}{key}", self.prefix))
+        }
+    }
+
+    async fn with_retries<T, F, Fut>(&self, read: bool, request: F) -> Result<T, KvError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, KvError>>,
+    {
+        let mut backoff = self.retry.backoff;
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.retry.attempts && e.is_retryable(read) => {
+                    self.maelstrom.sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Makes the following reads of this node observe every write that completed
+    // before. Only seq-kv needs it: lin-kv reads are always fresh, and lww-kv
+    // replicas give no such guarantee.
+    pub async fn sync(&self) -> Result<(), KvError> {
+        if self.service.consistency() != Consistency::Sequential {
+            return Ok(());
+        }
+        let n = self.syncs.fetch_add(1, Ordering::Relaxed);
+        let token = format!("{}/{n}", self.maelstrom.node_id());
+        // shared by all scopes, the write only has to be ordered
+        self.write_value(&Self::SYNC_KEY.into(), token.into()).await
+    }
+
+    async fn sync_before_read(&self) -> Result<(), KvError> {
+        if self.sync_reads {
+            self.sync().await?;
+        }
+        Ok(())
+    }
+
+    fn cached(&self, key: &KvKey) -> Option<Value> {
+        let value = self.cache.as_ref()?.get(key, self.maelstrom.now());
+        let metric = match value {
+            Some(_) => "kv.cache.hit",
+            None => "kv.cache.miss",
+        };
+        self.maelstrom.metrics().incr(metric, 1);
+        value
+    }
+
+    // keeps `result` as the latest value of `key`, or forgets the key when
+    // it failed
+    fn remember(&self, key: &KvKey, result: Result<&Value, &KvError>) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        match result {
+            Ok(value) => cache.insert(key.to_owned(), value.to_owned(), self.maelstrom.now()),
+            Err(_) => cache.invalidate(key),
+        }
+    }
+
+    fn forget(&self, key: &KvKey) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(key);
+        }
+    }
+
+    async fn read_value(&self, key: &KvKey) -> Result<Value, KvError> {
+        if let Some(value) = self.cached(key) {
+            return Ok(value);
+        }
+        self.sync_before_read().await?;
+        let result = self
+            .with_retries(true, || read(&self.maelstrom, self.service.name(), key))
+            .await;
+        self.remember(key, result.as_ref());
+        result
+    }
+
+    pub async fn read<T: DeserializeOwned>(&self, key: impl Into<KvKey>) -> Result<T, KvError> {
+        from_value(self.read_value(&self.full_key(key)).await?)
+    }
+
+    // None while the key doesn't exist, a value of another type is an error
+    pub async fn read_option<T: DeserializeOwned>(
+        &self,
+        key: impl Into<KvKey>,
+    ) -> Result<Option<T>, KvError> {
+        optional(self.read(key).await)
+    }
+
+    pub async fn read_or<T: DeserializeOwned>(
+        &self,
+        key: impl Into<KvKey>,
+        default: T,
+    ) -> Result<T, KvError> {
+        Ok(self.read_option(key).await?.unwrap_or(default))
+    }
+
+    // reads sent concurrently, at most `read_concurrency` at a time, results in
+    // key order; one sync for all keys when sync reads are enabled
+    pub async fn read_all<T: DeserializeOwned>(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<KvKey>>,
+    ) -> Result<Vec<Result<T, KvError>>, KvError> {
+        let keys: Vec<KvKey> = keys.into_iter().map(|key| self.full_key(key)).collect();
+        let mut values: Vec<Option<Result<Value, KvError>>> =
+            keys.iter().map(|key| self.cached(key).map(Ok)).collect();
+        let misses: Vec<usize> = (0..keys.len()).filter(|&i| values[i].is_none()).collect();
+        if !misses.is_empty() {
+            self.sync_before_read().await?;
+            let calls = misses
+                .iter()
+                .map(|&i| (self.service.name(), read_body(keys[i].to_owned())));
+            let responses = self
+                .maelstrom
+                .rpc_all_limited(calls, false, Some(self.read_concurrency))
+                .await;
+            for (i, response) in misses.into_iter().zip(responses) {
+                let result = response.map_err(KvError::from).and_then(read_reply);
+                self.remember(&keys[i], result.as_ref());
+                values[i] = Some(result);
+            }
+        }
+        Ok(values
+            .into_iter()
+            .flatten()
+            .map(|value| from_value(value?))
+            .collect())
+    }
+
+    // Values of the keys that exist, read concurrently like `read_all`. Any
+    // error other than a missing key fails the whole call.
+    pub async fn multi_get<T: DeserializeOwned>(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<KvKey>>,
+    ) -> Result<HashMap<KvKey, T>, KvError> {
+        let keys: Vec<KvKey> = keys.into_iter().map(Into::into).collect();
+        let values = self.read_all(keys.iter().cloned()).await?;
+        let mut found = HashMap::with_capacity(keys.len());
+        for (key, value) in keys.into_iter().zip(values) {
+            if let Some(value) = optional(value)? {
+                found.insert(key, value);
+            }
+        }
+        Ok(found)
+    }
+
+    pub async fn write<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        value: &T,
+    ) -> Result<(), KvError> {
+        self.write_value(&self.full_key(key), to_value(value)?)
+            .await
+    }
+
+    async fn write_value(&self, key: &KvKey, value: Value) -> Result<(), KvError> {
+        let result = self
+            .with_retries(false, || {
+                write(&self.maelstrom, self.service.name(), key, value.to_owned())
+            })
+            .await;
+        self.remember(key, result.as_ref().map(|()| &value));
+        result
+    }
+
+    // sets `key` to `to` if it currently holds `from`, or doesn't exist yet and
+    // `create_if_not_exists` is set
+    pub async fn cas<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        from: &T,
+        to: &T,
+        create_if_not_exists: bool,
+    ) -> Result<(), KvError> {
+        let (from, to) = (to_value(from)?, to_value(to)?);
+        self.cas_value(&self.full_key(key), from, to, create_if_not_exists)
+            .await
+    }
+
+    async fn cas_value(
+        &self,
+        key: &KvKey,
+        from: Value,
+        to: Value,
+        create_if_not_exists: bool,
+    ) -> Result<(), KvError> {
+        let result = self
+            .with_retries(false, || {
+                cas(
+                    &self.maelstrom,
+                    self.service.name(),
+                    key,
+                    from.to_owned(),
+                    to.to_owned(),
+                    create_if_not_exists,
+                )
+            })
+            .await;
+        self.remember(key, result.as_ref().map(|()| &to));
+        result
+    }
+
+    // Read-modify-write: applies `f` to the current value, None while the key
+    // doesn't exist, and swaps the result in. When another writer got in
+    // between, it backs off and starts over from a fresh read, so `f` may run
+    // more than once. Returns the value written.
+    pub async fn update<T, F>(&self, key: impl Into<KvKey>, mut f: F) -> Result<T, KvError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(Option<T>) -> T,
+    {
+        let key = self.full_key(key);
+        let mut backoff = self.conflict_retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let current = optional(self.read_value(&key).await)?;
+            let updated = f(current.clone().map(from_value).transpose()?);
+
+            // a missing key is swapped from null and created
+            let from = current.unwrap_or(Value::None);
+            match self.cas_value(&key, from, to_value(&updated)?, true).await {
+                Ok(()) => return Ok(updated),
+                Err(KvError::PreconditionFailed(_)) if attempt < self.conflict_retry.attempts => {
+                    self.maelstrom.sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Reads `key` until `predicate` holds for its value, None while the key
+    // doesn't exist, and returns that value. The pauses between reads grow as
+    // set by `with_poll_backoff`; the reads bypass the cache. Waits for as long
+    // as it takes, callers that need a bound wrap it in a timeout.
+    pub async fn wait_until<T, P>(
+        &self,
+        key: impl Into<KvKey>,
+        mut predicate: P,
+    ) -> Result<Option<T>, KvError>
+    where
+        T: DeserializeOwned,
+        P: FnMut(Option<&T>) -> bool,
+    {
+        let key = self.full_key(key);
+        let mut pause = self.poll_backoff.initial;
+        loop {
+            self.forget(&key);
+            let value = optional(self.read_value(&key).await)?
+                .map(from_value)
+                .transpose()?;
+            if predicate(value.as_ref()) {
+                return Ok(value);
+            }
+            self.maelstrom.sleep(self.poll_backoff.pause(pause)).await;
+            pause = (pause * 2).min(self.poll_backoff.max);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value as Json;
+
+use crate::message::KvKey;
+
+use super::{Kv, KvError};
+
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+// queued keys that trigger a flush right away
+pub const DEFAULT_MAX_PENDING: usize = 64;
+
+// folds a newer write into an older one
+pub type Merge = Arc<dyn Fn(Json, Json) -> Json + Send + Sync>;
+
+// Queues writes per key and stores them in batches, for workloads that write
+// the same keys far more often than anyone reads them.
+//
+// Writes to a queued key are coalesced: the last one wins, or with `with_merge`
+// they are folded together and a flush folds the batch into the stored value
+// with a cas loop, e.g. set unions or counter adds. A flush runs every
+// interval, see `run`, and as soon as `max_pending` keys are queued. A queued
+// write is lost if the node crashes before its flush, and apps call `flush`
+// from `App::shutdown` so nothing is left behind on a clean exit.
+pub struct WriteBatcher {
+    kv: Kv,
+    merge: Option<Merge>,
+    interval: Duration,
+    max_pending: usize,
+    pending: Mutex<HashMap<KvKey, Json>>,
+    // one flush at a time, so the batches of a key are stored in order
+    flushing: tokio::sync::Mutex<()>,
+}
+
+impl WriteBatcher {
+    pub fn new(kv: Kv) -> Self {
+        Self {
+            kv,
+            merge: None,
+            interval: DEFAULT_FLUSH_INTERVAL,
+            max_pending: DEFAULT_MAX_PENDING,
+            pending: Default::default(),
+            flushing: Default::default(),
+        }
+    }
+
+    pub fn with_merge(
+        mut self,
+        merge: impl Fn(Json, Json) -> Json + Send + Sync + 'static,
+    ) -> Self {
+        self.merge = Some(Arc::new(merge));
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_max_pending(mut self, limit: usize) -> Self {
+        self.max_pending = limit.max(1);
+        self
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    // queues the write, and flushes right away once too many keys are queued
+    pub async fn write<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        value: &T,
+    ) -> Result<(), KvError> {
+        let (key, value) = (key.into(), serde_json::to_value(value)?);
+        let full = {
+            let mut pending = self.pending.lock().unwrap();
+            let value = match pending.remove(&key) {
+                Some(queued) => self.coalesce(queued, value),
+                None => value,
+            };
+            pending.insert(key, value);
+            pending.len() >= self.max_pending
+        };
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn coalesce(&self, older: Json, newer: Json) -> Json {
+        match &self.merge {
+            Some(merge) => merge(older, newer),
+            None => newer,
+        }
+    }
+
+    // Stores everything queued so far. Writes that failed are queued again
+    // behind anything written since, and the first error is returned. A write
+    // that timed out may have applied already, so a merge that must not apply
+    // twice, like an add, is better replaced by an idempotent one, like a max
+    // of per-node totals.
+    pub async fn flush(&self) -> Result<(), KvError> {
+        let _flushing = self.flushing.lock().await;
+        let batch = mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let metrics = self.kv.maelstrom.metrics();
+        metrics.incr("kv.batch.flushes", 1);
+        metrics.incr("kv.batch.writes", batch.len() as u64);
+
+        let stores: Vec<_> = batch
+            .into_iter()
+            .map(|(key, value)| {
+                let (kv, merge) = (self.kv.clone(), self.merge.clone());
+                self.kv.maelstrom.spawn(async move {
+                    let result = store(&kv, merge, &key, value.to_owned()).await;
+                    (key, value, result)
+                })
+            })
+            .collect();
+
+        let mut first_error = None;
+        for store in stores {
+            let Ok((key, value, result)) = store.await else {
+                continue;
+            };
+            if let Err(e) = result {
+                let mut pending = self.pending.lock().unwrap();
+                let value = match pending.remove(&key) {
+                    Some(newer) => self.coalesce(value, newer),
+                    None => value,
+                };
+                pending.insert(key, value);
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Flushes every interval, runs until the process exits.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = self.kv.maelstrom.interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.flush().await {
+                let maelstrom = &self.kv.maelstrom;
+                maelstrom.log(format!("Error: flushing queued kv writes failed: {e}"));
+            }
+        }
+    }
+}
+
+async fn store(kv: &Kv, merge: Option<Merge>, key: &KvKey, value: Json) -> Result<(), KvError> {
+    let Some(merge) = merge else {
+        return kv.write(key.to_owned(), &value).await;
+    };
+    kv.update(key.to_owned(), |current: Option<Json>| match current {
+        Some(current) => merge(current, value.to_owned()),
+        None => value.to_owned(),
+    })
+    .await?;
+    Ok(())
+}
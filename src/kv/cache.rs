@@ -0,0 +1,46 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::message::{KvKey, Value};
+
+// Values a client last read or wrote, by full key. Entries older than
+// `max_age` count as missing, so a cached read is at most that stale.
+pub struct ReadCache {
+    max_age: Duration,
+    entries: Mutex<HashMap<KvKey, (Value, Instant)>>,
+}
+
+impl ReadCache {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            entries: Default::default(),
+        }
+    }
+
+    // None when the key isn't cached or its entry is too old
+    pub fn get(&self, key: &KvKey, now: Instant) -> Option<Value> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, since)) if now.duration_since(*since) < self.max_age => {
+                Some(value.to_owned())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: KvKey, value: Value, now: Instant) {
+        self.entries.lock().unwrap().insert(key, (value, now));
+    }
+
+    pub fn invalidate(&self, key: &KvKey) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::message::KvKey;
+
+use super::{Kv, KvError, RetryPolicy, DEFAULT_CONFLICT_RETRY};
+
+// Content key of a serialized value: 128 bits of two differently seeded
+// hashers, the same on every node running the same binary.
+fn content_key(bytes: &str) -> KvKey {
+    let halves = [0u8, 1].map(|seed| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        bytes.hash(&mut hasher);
+        hasher.finish()
+    });
+    KvKey(format!("{:016x}{:016x}", halves[0], halves[1]))
+}
+
+// Immutable values stored under the hash of their content, plus small named
+// root pointers to them, in the manner of Datomic.
+//
+// A value is never changed once written, so `put` is a plain write that any
+// number of nodes may repeat. State moves forward by writing the new values
+// first and then swapping the root from the old content key to the new one,
+// the only cas involved, and a tiny one however large the values are. Values
+// that no root points to anymore are left behind.
+#[derive(Clone)]
+pub struct ContentStore {
+    blobs: Kv,
+    roots: Kv,
+    conflict_retry: RetryPolicy,
+}
+
+impl ContentStore {
+    pub fn new(kv: Kv) -> Self {
+        Self {
+            blobs: kv.scoped("blob"),
+            roots: kv.scoped("root"),
+            conflict_retry: DEFAULT_CONFLICT_RETRY,
+        }
+    }
+
+    pub fn with_conflict_retry(mut self, retry: RetryPolicy) -> Self {
+        self.conflict_retry = retry;
+        self
+    }
+
+    // stores `value` and returns its content key
+    pub async fn put<T: Serialize>(&self, value: &T) -> Result<KvKey, KvError> {
+        // through serde_json's sorted maps, so equal values hash alike
+        let value = serde_json::to_value(value)?;
+        let key = content_key(&value.to_string());
+        self.blobs.write(key.to_owned(), &value).await?;
+        Ok(key)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, key: &KvKey) -> Result<T, KvError> {
+        self.blobs.read(key.to_owned()).await
+    }
+
+    // content key the root points to, None before the first swap
+    pub async fn root(&self, name: &str) -> Result<Option<KvKey>, KvError> {
+        self.roots.read_option(name).await
+    }
+
+    // points the root at `to` if it still points at `from`
+    pub async fn swap_root(
+        &self,
+        name: &str,
+        from: Option<&KvKey>,
+        to: &KvKey,
+    ) -> Result<(), KvError> {
+        self.roots.cas(name, &from, &Some(to), from.is_none()).await
+    }
+
+    // The value the root points to, None before the first swap.
+    pub async fn current<T: DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> Result<Option<(KvKey, T)>, KvError> {
+        match self.root(name).await? {
+            Some(key) => Ok(Some((key.to_owned(), self.get(&key).await?))),
+            None => Ok(None),
+        }
+    }
+
+    // Stores the value `f` derives from the current one and swaps the root to
+    // it, starting over with backoff when another node swapped it first. `f`
+    // may store values of its own with `put`, e.g. the parts of a bigger value
+    // that changed. Returns the new content key.
+    pub async fn update<T, F, Fut>(&self, name: &str, mut f: F) -> Result<KvKey, KvError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(Option<T>) -> Fut,
+        Fut: Future<Output = Result<T, KvError>>,
+    {
+        let mut backoff = self.conflict_retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let current = self.current::<T>(name).await?;
+            let (from, value) = match current {
+                Some((key, value)) => (Some(key), Some(value)),
+                None => (None, None),
+            };
+            let to = self.put(&f(value).await?).await?;
+            if from.as_ref() == Some(&to) {
+                return Ok(to);
+            }
+            match self.swap_root(name, from.as_ref(), &to).await {
+                Err(KvError::PreconditionFailed(_)) if attempt < self.conflict_retry.attempts => {
+                    self.roots.maelstrom.sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result.map(|()| to),
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::message::KvKey;
+
+use super::{Kv, KvError};
+
+// Integer counter stored under a single kv key. Adds go through a cas loop, so
+// concurrent adds from several nodes are never lost; counters that take many
+// adds are better split into one key per node and summed with `sum`.
+#[derive(Clone)]
+pub struct KvCounter {
+    kv: Kv,
+    key: KvKey,
+}
+
+impl KvCounter {
+    pub fn new(kv: Kv, key: impl Into<KvKey>) -> Self {
+        Self {
+            kv,
+            key: key.into(),
+        }
+    }
+
+    pub fn key(&self) -> &KvKey {
+        &self.key
+    }
+
+    // zero until the first add
+    pub async fn get(&self) -> Result<i64, KvError> {
+        self.kv.read_or(self.key.to_owned(), 0).await
+    }
+
+    // the counter after the add, e.g. the end of a block of ids handed out
+    pub async fn add(&self, delta: i64) -> Result<i64, KvError> {
+        self.kv
+            .update(self.key.to_owned(), |value: Option<i64>| {
+                value.unwrap_or_default() + delta
+            })
+            .await
+    }
+
+    // Total of the counters under `keys`, read concurrently after a sync so
+    // the latest adds of other nodes are included.
+    pub async fn sum(
+        kv: &Kv,
+        keys: impl IntoIterator<Item = impl Into<KvKey>>,
+    ) -> Result<i64, KvError> {
+        kv.sync().await?;
+        Ok(kv.multi_get::<i64>(keys).await?.values().sum())
+    }
+}
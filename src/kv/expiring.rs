@@ -0,0 +1,84 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::message::{KvKey, Value};
+
+use super::{from_value, optional, to_value, Kv, KvError};
+
+// Wall clock time in milliseconds, which all maelstrom nodes of a run share.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+// A value stored with the wall clock time it expires at. Expired entries stay
+// in the service until overwritten, reads treat them as absent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expiring<T> {
+    pub expires_at: i64,
+    pub value: T,
+}
+
+impl<T> Expiring<T> {
+    pub fn new(value: T, ttl: Duration) -> Self {
+        Self {
+            expires_at: now_millis() + ttl.as_millis() as i64,
+            value,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= now_millis()
+    }
+}
+
+impl Kv {
+    // stores `value` until `ttl` from now
+    pub async fn write_expiring<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<(), KvError> {
+        self.write(key, &Expiring::new(value, ttl)).await
+    }
+
+    // None while the key doesn't exist or its entry expired
+    pub async fn read_unexpired<T: DeserializeOwned>(
+        &self,
+        key: impl Into<KvKey>,
+    ) -> Result<Option<T>, KvError> {
+        let entry = self.read_option::<Expiring<T>>(key).await?;
+        Ok(entry
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.value))
+    }
+
+    // Stores `value` until `ttl` from now unless the key holds an entry that
+    // hasn't expired yet, e.g. to take a lease. False when the key was taken,
+    // also when another node claimed it concurrently.
+    pub async fn claim<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<bool, KvError> {
+        let key = self.full_key(key);
+        let current = optional(self.read_value(&key).await)?.unwrap_or_default();
+        if !current.is_none() {
+            let entry: Expiring<Value> = from_value(current.to_owned())?;
+            if !entry.is_expired() {
+                return Ok(false);
+            }
+        }
+        let claimed = to_value(&Expiring::new(value, ttl))?;
+        match self.cas_value(&key, current, claimed, true).await {
+            Ok(()) => Ok(true),
+            Err(KvError::PreconditionFailed(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::message::KvKey;
+
+use super::{Kv, KvError, RetryPolicy, DEFAULT_CONFLICT_RETRY};
+
+// key of the commit record, next to the `data` scope
+const COMMIT_KEY: &str = "commit";
+
+// Version of the last committed transaction. Its writes are kept in the record
+// until they are copied to their keys, so whichever node finds them first can
+// finish the copy, also after the committing node crashed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct CommitRecord {
+    version: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    writes: BTreeMap<KvKey, Json>,
+}
+
+// a value together with the version of the transaction that wrote it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    version: u64,
+    value: Json,
+}
+
+fn conflict() -> KvError {
+    KvError::PreconditionFailed("transaction conflict".to_owned())
+}
+
+// Optimistic multi-key transactions over a kv service, lin-kv in practice.
+//
+// A transaction reads at the version it began with and buffers its writes. It
+// commits by swapping the commit record from that version to the next one, so
+// it fails if anything committed in between. Values are stored with the version
+// that wrote them: a read that finds a value newer than its snapshot has lost a
+// race, and the transaction aborts before reading an inconsistent state.
+// Read-only transactions never touch the record.
+#[derive(Clone)]
+pub struct TxnStore {
+    kv: Kv,
+    data: Kv,
+    conflict_retry: RetryPolicy,
+}
+
+impl TxnStore {
+    pub fn new(kv: Kv) -> Self {
+        Self {
+            data: kv.scoped("data"),
+            kv,
+            conflict_retry: DEFAULT_CONFLICT_RETRY,
+        }
+    }
+
+    pub fn with_conflict_retry(mut self, retry: RetryPolicy) -> Self {
+        self.conflict_retry = retry;
+        self
+    }
+
+    pub async fn begin(&self) -> Result<Txn, KvError> {
+        loop {
+            let record = self.kv.read_option::<CommitRecord>(COMMIT_KEY).await?;
+            match record {
+                Some(record) if !record.writes.is_empty() => self.finish(record).await?,
+                record => {
+                    return Ok(Txn {
+                        store: self.clone(),
+                        version: record.as_ref().map_or(0, |record| record.version),
+                        base: record,
+                        state: Default::default(),
+                    })
+                }
+            }
+        }
+    }
+
+    // Runs `body` in a transaction and commits it, starting over in a fresh
+    // transaction with backoff on conflicts. A conflict is returned once the
+    // attempts of the conflict retry policy are used up.
+    pub async fn run<R, F, Fut>(&self, mut body: F) -> Result<R, KvError>
+    where
+        F: FnMut(Txn) -> Fut,
+        Fut: Future<Output = Result<R, KvError>>,
+    {
+        let mut backoff = self.conflict_retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let txn = self.begin().await?;
+            let result = match body(txn.clone()).await {
+                Ok(result) => txn.commit().await.map(|()| result),
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(KvError::PreconditionFailed(_)) if attempt < self.conflict_retry.attempts => {
+                    self.kv.maelstrom.sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // copies the writes of a commit to their keys and clears them from the record
+    async fn finish(&self, record: CommitRecord) -> Result<(), KvError> {
+        for (key, value) in &record.writes {
+            // a late copy must not overwrite the value of a later commit
+            self.data
+                .update(key.to_owned(), |entry: Option<Entry>| match entry {
+                    Some(entry) if entry.version >= record.version => entry,
+                    _ => Entry {
+                        version: record.version,
+                        value: value.to_owned(),
+                    },
+                })
+                .await?;
+        }
+
+        let finished = CommitRecord {
+            version: record.version,
+            writes: BTreeMap::new(),
+        };
+        match self.kv.cas(COMMIT_KEY, &record, &finished, false).await {
+            // another node finished it first
+            Err(KvError::PreconditionFailed(_)) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+// Handle of a running transaction, clones share its reads and writes.
+#[derive(Clone)]
+pub struct Txn {
+    store: TxnStore,
+    version: u64,
+    // the commit record the transaction began with, None before the first commit
+    base: Option<CommitRecord>,
+    state: Arc<Mutex<TxnState>>,
+}
+
+#[derive(Default)]
+struct TxnState {
+    reads: HashMap<KvKey, Option<Json>>,
+    writes: BTreeMap<KvKey, Json>,
+}
+
+impl Txn {
+    // snapshot version the transaction reads at
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // None while the key doesn't exist; sees the transaction's own writes, and
+    // reads the same value when a key is read twice
+    pub async fn read<T: DeserializeOwned>(
+        &self,
+        key: impl Into<KvKey>,
+    ) -> Result<Option<T>, KvError> {
+        let key = key.into();
+        let known = {
+            let state = self.state.lock().unwrap();
+            match state.writes.get(&key) {
+                Some(value) => Some(Some(value.to_owned())),
+                None => state.reads.get(&key).cloned(),
+            }
+        };
+        let value = match known {
+            Some(value) => value,
+            None => self.read_snapshot(key).await?,
+        };
+        Ok(value.map(serde_json::from_value).transpose()?)
+    }
+
+    async fn read_snapshot(&self, key: KvKey) -> Result<Option<Json>, KvError> {
+        let value = match self.store.data.read_option::<Entry>(key.to_owned()).await? {
+            Some(entry) if entry.version > self.version => return Err(conflict()),
+            entry => entry.map(|entry| entry.value),
+        };
+        let mut state = self.state.lock().unwrap();
+        Ok(state.reads.entry(key).or_insert(value).to_owned())
+    }
+
+    // buffered until the commit
+    pub fn write<T: Serialize>(&self, key: impl Into<KvKey>, value: &T) -> Result<(), KvError> {
+        let value = serde_json::to_value(value)?;
+        self.state.lock().unwrap().writes.insert(key.into(), value);
+        Ok(())
+    }
+
+    // A conflict, i.e. a definite `PreconditionFailed`, when another
+    // transaction committed since this one began.
+    pub async fn commit(self) -> Result<(), KvError> {
+        let writes = std::mem::take(&mut self.state.lock().unwrap().writes);
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        let record = CommitRecord {
+            version: self.version + 1,
+            writes,
+        };
+        self.store
+            .kv
+            .cas(COMMIT_KEY, &self.base, &Some(record.to_owned()), true)
+            .await?;
+
+        // committed at this point, the next transaction to begin finishes the copy
+        // if this one fails
+        if let Err(e) = self.store.finish(record).await {
+            let maelstrom = &self.store.kv.maelstrom;
+            maelstrom.log(format!("copying the writes of a commit failed: {e}"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::message::KvKey;
+
+use super::{Kv, KvError};
+
+// A value together with the version that every `cas_versioned` bumps. The
+// version number lives alone under the key and each version's value under
+// `<key>@<version>`, written once and never changed. Writers only ever cas the
+// small version number, never the value, and a value that changed and changed
+// back still counts as changed. Values of old versions are left behind, kv
+// services can't delete keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub version: u64,
+    pub value: T,
+}
+
+fn value_key(key: &KvKey, version: u64) -> KvKey {
+    KvKey(format!("{key}@{version}"))
+}
+
+impl Kv {
+    // None while the key doesn't exist, i.e. at version 0
+    pub async fn read_versioned<T: DeserializeOwned>(
+        &self,
+        key: impl Into<KvKey>,
+    ) -> Result<Option<Versioned<T>>, KvError> {
+        let key = key.into();
+        let Some(version) = self.read_option::<u64>(key.to_owned()).await? else {
+            return Ok(None);
+        };
+        let value = self.read(value_key(&key, version)).await?;
+        Ok(Some(Versioned { version, value }))
+    }
+
+    // Replaces the value of `key` if it is still at `expected_version`, 0 for a
+    // key that doesn't exist yet, and returns the new version. Fails with
+    // `PreconditionFailed` when the key moved on.
+    //
+    // The new value is written under its version's key first, created only if
+    // no other writer claimed that version, and then the version number is
+    // swapped. A writer that finds the next version claimed but the number not
+    // bumped yet, e.g. because its writer crashed, bumps it on its behalf.
+    pub async fn cas_versioned<T: Serialize>(
+        &self,
+        key: impl Into<KvKey>,
+        expected_version: u64,
+        value: &T,
+    ) -> Result<u64, KvError> {
+        let key = key.into();
+        let version = expected_version + 1;
+        let current = Some(expected_version).filter(|version| *version > 0);
+
+        let claimed = self
+            .cas(value_key(&key, version), &None, &Some(value), true)
+            .await;
+        if let Err(KvError::PreconditionFailed(_)) = claimed {
+            let _ = self
+                .cas(key.to_owned(), &current, &Some(version), current.is_none())
+                .await;
+            // a cached version may be the stale one, the caller reads again
+            self.forget(&self.full_key(key.to_owned()));
+            return Err(KvError::PreconditionFailed(format!(
+                "{key} moved on from version {expected_version}"
+            )));
+        }
+        claimed?;
+
+        match self
+            .cas(key.to_owned(), &current, &Some(version), current.is_none())
+            .await
+        {
+            // another writer found the claim and bumped the number for us
+            Err(KvError::PreconditionFailed(_))
+                if self.read_option::<u64>(key).await? >= Some(version) =>
+            {
+                Ok(version)
+            }
+            result => result.map(|()| version),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Lamport logical clock. Every stamped send ticks it and every stamped receive
+// moves it past the sender's time, so causally related events are ordered.
+#[derive(Debug, Default)]
+pub struct LamportClock {
+    time: AtomicU64,
+}
+
+impl LamportClock {
+    pub fn now(&self) -> u64 {
+        self.time.load(Ordering::SeqCst)
+    }
+
+    // advances the clock for a local event or send and returns the new time
+    pub fn tick(&self) -> u64 {
+        self.time.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    // merges the timestamp of a received message and returns the new time
+    pub fn observe(&self, remote: u64) -> u64 {
+        let previous = self
+            .time
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |local| {
+                Some(local.max(remote) + 1)
+            })
+            .unwrap_or_default();
+        previous.max(remote) + 1
+    }
+}
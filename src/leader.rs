@@ -0,0 +1,90 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+use crate::{maelstrom::Maelstrom, message::NodeId};
+
+// how often the leader is recomputed when no peer events arrive
+const ELECTION_INTERVAL: Duration = Duration::from_millis(250);
+
+// Deterministic bully election: the smallest node id among the nodes not
+// suspected down leads. Without heartbeats every node is considered alive,
+// so the smallest node id of the cluster leads for the whole run.
+pub struct LeaderElection {
+    maelstrom: Maelstrom,
+    leader: watch::Sender<Option<NodeId>>,
+}
+
+impl LeaderElection {
+    pub fn new(maelstrom: Maelstrom) -> Self {
+        Self {
+            maelstrom,
+            leader: watch::Sender::new(None),
+        }
+    }
+
+    // None until the node is initialized
+    pub fn leader(&self) -> Option<NodeId> {
+        if self.maelstrom.node_id().is_empty() {
+            return None;
+        }
+
+        let mut candidates = self.maelstrom.alive_peers();
+        candidates.push(self.maelstrom.node_id().to_owned());
+        candidates.into_iter().min()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader()
+            .is_some_and(|leader| self.maelstrom.is_self(&leader))
+    }
+
+    // notified with the new leader whenever leadership changes
+    pub fn subscribe(&self) -> watch::Receiver<Option<NodeId>> {
+        self.leader.subscribe()
+    }
+
+    // calls `callback` with the new leader on every leadership change; the task
+    // is not tracked, so it does not hold up shutdown
+    pub fn on_change(&self, callback: impl Fn(Option<NodeId>) + Send + 'static) {
+        let mut leader = self.subscribe();
+        tokio::spawn(async move {
+            while leader.changed().await.is_ok() {
+                let current = leader.borrow_and_update().to_owned();
+                callback(current);
+            }
+        });
+    }
+
+    // keeps `subscribe` and `on_change` up to date, runs until the process exits
+    pub async fn run(self: Arc<Self>) {
+        let mut events = self.maelstrom.watch_peers();
+        let mut interval = self.maelstrom.interval(ELECTION_INTERVAL);
+        loop {
+            match &mut events {
+                Some(events) => {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = events.recv() => {}
+                    }
+                }
+                None => {
+                    interval.tick().await;
+                }
+            }
+            self.update();
+        }
+    }
+
+    fn update(&self) {
+        let leader = self.leader();
+        self.leader.send_if_modified(|current| {
+            if *current == leader {
+                return false;
+            }
+            self.maelstrom.log(format!("leader is now {leader:?}"));
+            *current = leader;
+            true
+        });
+    }
+}
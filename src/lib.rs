@@ -1,2 +1,43 @@
+#[cfg(feature = "broadcast")]
+pub mod broadcast;
+pub mod canonical;
+pub mod checksum;
+pub mod clock;
+pub mod cluster;
+pub mod compression;
+pub mod config;
+pub mod context;
+pub mod dedup;
+pub mod extensions;
+pub mod failure_detector;
+pub mod gossip_set;
+pub mod handle;
+pub mod hlc;
+pub mod kv;
+pub mod lamport;
+pub mod leader;
+pub mod lock;
+pub mod lww;
 pub mod maelstrom;
 pub mod message;
+pub mod metrics;
+pub mod or_set;
+pub mod ordered;
+pub mod outbox;
+pub mod peer_health;
+pub mod rate_limit;
+pub mod request;
+pub mod schema;
+pub mod self_test;
+pub mod simulator;
+pub mod stream;
+pub mod sync;
+pub mod timer;
+pub mod trace;
+pub mod transport;
+pub mod tso;
+pub mod two_phase;
+#[cfg(feature = "txn")]
+pub mod txn_graph;
+pub mod version_vector;
+pub mod write_queue;
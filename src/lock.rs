@@ -0,0 +1,274 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Mutex, OwnedMutexGuard},
+    task::JoinHandle,
+};
+
+use crate::{
+    kv::{self, now_millis, Kv, KvError, PollBackoff},
+    maelstrom::{Maelstrom, STRICT_CAS_ATTEMPTS},
+    message::{NodeId, Value},
+};
+
+const LIN_KV: &str = "lin-kv";
+pub const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(2);
+
+// Lock lease as stored in lin-kv, encoded as `<holder>/<token>/<expires_ms>`.
+// Expiry uses wall clock time, which all maelstrom nodes of a run share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lease {
+    holder: NodeId,
+    token: u64,
+    expires_at: i64,
+}
+
+impl Lease {
+    fn parse(value: &Value) -> Option<Self> {
+        let mut parts = value.as_str()?.rsplitn(3, '/');
+        let expires_at = parts.next()?.parse().ok()?;
+        let token = parts.next()?.parse().ok()?;
+        let holder = parts.next()?.into();
+        Some(Self {
+            holder,
+            token,
+            expires_at,
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        format!("{}/{}/{}", self.holder, self.token, self.expires_at).into()
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at < now_millis()
+    }
+}
+
+// Mutual exclusion across nodes through a lease key in lin-kv. Leases expire
+// after `ttl` unless renewed, so a crashed holder cannot deadlock the cluster,
+// and every acquisition hands out a larger fencing token. Tasks of the same
+// node queue on a local mutex first, so at most one of them holds the lease.
+pub struct DistributedLock {
+    maelstrom: Maelstrom,
+    // reads the lease while it is held elsewhere
+    kv: Kv,
+    key: String,
+    ttl: Duration,
+    local: Arc<Mutex<()>>,
+}
+
+impl DistributedLock {
+    pub fn new(maelstrom: Maelstrom, key: impl Into<String>) -> Self {
+        Self {
+            kv: Kv::lin(maelstrom.clone()),
+            maelstrom,
+            key: key.into(),
+            ttl: DEFAULT_LOCK_TTL,
+            local: Default::default(),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_poll_backoff(mut self, backoff: PollBackoff) -> Self {
+        self.kv = self.kv.with_poll_backoff(backoff);
+        self
+    }
+
+    // waits until the lease is acquired, it is renewed in the background until released
+    pub async fn lock(&self) -> io::Result<LockGuard> {
+        let local = self.local.clone().lock_owned().await;
+        for attempt in 1.. {
+            if attempt > STRICT_CAS_ATTEMPTS {
+                self.maelstrom.protocol_violation(format!(
+                    "lock {} retried {STRICT_CAS_ATTEMPTS} times",
+                    self.key
+                ));
+            }
+
+            // Waits out the lease of its holder, reading less often the longer
+            // it takes. A lease of this node is waited out too: it is one still
+            // being released, or one of an earlier run that lost track of it.
+            let current = self
+                .kv
+                .wait_until(self.key.as_str(), |value: Option<&Value>| {
+                    value
+                        .and_then(Lease::parse)
+                        .is_none_or(|lease| lease.is_expired())
+                })
+                .await?
+                .unwrap_or_default();
+            let token = Lease::parse(&current).map_or(1, |lease| lease.token + 1);
+
+            let acquired = Lease {
+                holder: self.maelstrom.node_id().to_owned(),
+                token,
+                expires_at: now_millis() + self.ttl.as_millis() as i64,
+            };
+            if cas(&self.maelstrom, &self.key, current, acquired.to_value()).await? {
+                return Ok(LockGuard::new(self, acquired, local));
+            }
+        }
+        unreachable!()
+    }
+}
+
+// true when the value was swapped, false when `from` was stale
+async fn cas(maelstrom: &Maelstrom, key: &str, from: Value, to: Value) -> io::Result<bool> {
+    match kv::cas(maelstrom, LIN_KV, key, from, to, true).await {
+        Ok(()) => Ok(true),
+        Err(KvError::PreconditionFailed(_)) => Ok(false),
+        Err(KvError::Io(e)) => Err(e),
+        // the swap may or may not have happened, callers read the lock again
+        Err(e) if !e.is_definite() => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Held lease, renewed every third of the ttl. Dropping the guard releases the
+// lease in the background; `release` does the same but waits for it.
+pub struct LockGuard {
+    maelstrom: Maelstrom,
+    key: String,
+    token: u64,
+    lease: Arc<Mutex<Lease>>,
+    lost: Arc<AtomicBool>,
+    renewal: JoinHandle<()>,
+    released: bool,
+    // lets the next local waiter in once the guard is gone
+    _local: OwnedMutexGuard<()>,
+}
+
+impl LockGuard {
+    fn new(lock: &DistributedLock, lease: Lease, local: OwnedMutexGuard<()>) -> Self {
+        let token = lease.token;
+        let lease = Arc::new(Mutex::new(lease));
+        let lost = Arc::new(AtomicBool::new(false));
+        let renewal = tokio::spawn(renew(
+            lock.maelstrom.clone(),
+            lock.key.to_owned(),
+            lock.ttl,
+            lease.clone(),
+            lost.clone(),
+        ));
+
+        Self {
+            maelstrom: lock.maelstrom.clone(),
+            key: lock.key.to_owned(),
+            token,
+            lease,
+            lost,
+            renewal,
+            released: false,
+            _local: local,
+        }
+    }
+
+    // Fencing token, strictly increasing across acquisitions of the same key.
+    // Advisory: it isn't stored with the writes made under the lock, so the kv
+    // can't reject a stale holder's writes, see `is_held`.
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    // false once a renewal failed, another node may hold the lock by now
+    pub fn is_held(&self) -> bool {
+        !self.lost.load(Ordering::Relaxed)
+    }
+
+    pub async fn release(mut self) -> io::Result<()> {
+        self.released = true;
+        self.renewal.abort();
+        release(&self.maelstrom, &self.key, &self.lease).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.renewal.abort();
+
+        let maelstrom = self.maelstrom.clone();
+        let key = self.key.to_owned();
+        let lease = self.lease.clone();
+        self.maelstrom.spawn(async move {
+            if let Err(e) = release(&maelstrom, &key, &lease).await {
+                maelstrom.log(format!("Error: releasing lock {key} failed: {e}"));
+            }
+        });
+    }
+}
+
+async fn renew(
+    maelstrom: Maelstrom,
+    key: String,
+    ttl: Duration,
+    lease: Arc<Mutex<Lease>>,
+    lost: Arc<AtomicBool>,
+) {
+    let mut interval = maelstrom.interval(ttl / 3);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        let mut lease = lease.lock().await;
+        let renewed = Lease {
+            expires_at: now_millis() + ttl.as_millis() as i64,
+            ..lease.to_owned()
+        };
+        match swapped(&maelstrom, &key, &lease, &renewed).await {
+            Some(true) => *lease = renewed,
+            Some(false) => {
+                maelstrom.log(format!("lock {key} lost, lease was taken over"));
+                lost.store(true, Ordering::Relaxed);
+                return;
+            }
+            // the renewal may or may not have been applied, try again next tick
+            None => {}
+        }
+    }
+}
+
+// Whether `from` was swapped for `to`, None when that can't be told yet. `cas`
+// reports a stale lease and a swap that may or may not have happened alike,
+// the lease as stored tells them apart.
+async fn swapped(maelstrom: &Maelstrom, key: &str, from: &Lease, to: &Lease) -> Option<bool> {
+    match cas(maelstrom, key, from.to_value(), to.to_value()).await {
+        Ok(true) => Some(true),
+        Ok(false) => match kv::read(maelstrom, LIN_KV, key).await {
+            Ok(current) => match Lease::parse(&current) {
+                Some(current) if current == *to => Some(true),
+                Some(current) if current == *from => None,
+                _ => Some(false),
+            },
+            Err(KvError::KeyDoesNotExist) => Some(false),
+            Err(_) => None,
+        },
+        Err(_) => None,
+    }
+}
+
+// expires the lease rather than deleting it so the next token stays larger,
+// releasing a lease that was taken over is a no-op
+async fn release(maelstrom: &Maelstrom, key: &str, lease: &Mutex<Lease>) -> io::Result<()> {
+    let lease = lease.lock().await;
+    let expired = Lease {
+        expires_at: 0,
+        ..lease.to_owned()
+    };
+    cas(maelstrom, key, lease.to_value(), expired.to_value()).await?;
+    Ok(())
+}
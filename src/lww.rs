@@ -0,0 +1,217 @@
+use std::{collections::HashMap, io, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::checksum,
+    hlc::HybridClock,
+    maelstrom::Maelstrom,
+    message::{KvKey, Message, MessageBody, NodeId, Value},
+    sync::TrackedMutex,
+    tso::Tso,
+};
+
+// default anti-entropy cadence
+pub const GOSSIP_INTERVAL: Duration = Duration::from_millis(500);
+
+// Where an `LwwMap` takes the timestamps of its writes from.
+pub enum Timestamps {
+    // Maelstrom's lin-tso, an rpc per write: writes are ordered as by a
+    // single clock, a write always wins over every write acked before it
+    Tso(Tso),
+    // local hybrid logical clock, no rpcs: a write wins over every write its
+    // node knew of, concurrent ones are ordered by wall clock time
+    Hybrid(HybridClock),
+}
+
+// Timestamp of a write, the node breaks ties between equal timestamps.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub ts: u64,
+    pub node: NodeId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    key: KvKey,
+    stamp: Stamp,
+    value: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "lww_gossip")]
+struct LwwGossip {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "lww_gossip_ok")]
+struct LwwGossipOk {}
+
+#[derive(Default)]
+struct State {
+    // latest write of each key and the change number it got on this node
+    entries: HashMap<KvKey, (Entry, u64)>,
+    changes: u64,
+    // every change up to this number reached the peer
+    acked: HashMap<NodeId, u64>,
+}
+
+impl State {
+    // keeps `entry` if it is newer than the stored write of its key
+    fn merge(&mut self, entry: Entry) -> bool {
+        if let Some((current, _)) = self.entries.get(&entry.key) {
+            if current.stamp >= entry.stamp {
+                return false;
+            }
+        }
+        self.changes += 1;
+        self.entries
+            .insert(entry.key.to_owned(), (entry, self.changes));
+        true
+    }
+
+    fn changed_since(&self, change: u64) -> Vec<Entry> {
+        self.entries
+            .values()
+            .filter(|(_, changed)| *changed > change)
+            .map(|(entry, _)| entry.to_owned())
+            .collect()
+    }
+}
+
+// Map of last-write-wins registers replicated to every other node, a single
+// register is a map with one key.
+//
+// Writes apply locally and win over any write of the key with a smaller
+// `Stamp`, so all nodes keep the same value once they exchanged their writes.
+// Anti-entropy sends each peer every interval the keys changed since its last
+// ack, lost gossip is resent on the next tick.
+pub struct LwwMap {
+    timestamps: Timestamps,
+    period: Duration,
+    state: Arc<TrackedMutex<State>>,
+}
+
+impl LwwMap {
+    pub fn new(timestamps: Timestamps) -> Self {
+        Self {
+            timestamps,
+            period: GOSSIP_INTERVAL,
+            state: Default::default(),
+        }
+    }
+
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub async fn write(
+        &self,
+        maelstrom: &Maelstrom,
+        key: impl Into<KvKey>,
+        value: Value,
+    ) -> io::Result<Stamp> {
+        let ts = match &self.timestamps {
+            Timestamps::Tso(tso) => tso.ts().await?,
+            Timestamps::Hybrid(clock) => clock.now(),
+        };
+        let stamp = Stamp {
+            ts,
+            node: maelstrom.node_id().to_owned(),
+        };
+        self.state.lock().await.merge(Entry {
+            key: key.into(),
+            stamp: stamp.clone(),
+            value,
+        });
+        Ok(stamp)
+    }
+
+    pub async fn read(&self, key: &KvKey) -> Option<Value> {
+        let state = self.state.lock().await;
+        state.entries.get(key).map(|(entry, _)| entry.value.clone())
+    }
+
+    pub async fn checksum(&self) -> u64 {
+        let state = self.state.lock().await;
+        checksum(state.entries.values().map(|(entry, _)| {
+            let value = serde_json::to_string(&entry.value).unwrap_or_default();
+            (&entry.key, &entry.stamp, value)
+        }))
+    }
+
+    // Merges gossip and returns the ack to reply with, None if the request
+    // isn't gossip. Hybrid clocks move past every timestamp they see.
+    pub async fn on_message(&self, request: &Message) -> Option<MessageBody> {
+        let gossip = request.body.to_custom::<LwwGossip>().ok()?;
+        if let Timestamps::Hybrid(clock) = &self.timestamps {
+            if let Some(latest) = gossip.entries.iter().map(|entry| entry.stamp.ts).max() {
+                clock.observe(latest);
+            }
+        }
+        let mut state = self.state.lock().await;
+        for entry in gossip.entries {
+            state.merge(entry);
+        }
+        // serializing a tagged struct into a body can't fail
+        Some(MessageBody::from_custom(&LwwGossipOk {}).expect("lww gossip ack"))
+    }
+
+    // peers with changes they haven't acked, and the last change sent
+    async fn pending(&self, maelstrom: &Maelstrom) -> Vec<(NodeId, Vec<Entry>, u64)> {
+        let state = self.state.lock().await;
+        maelstrom
+            .other_node_ids()
+            .into_iter()
+            .map(|peer| {
+                let acked = state.acked.get(&peer).copied().unwrap_or_default();
+                (peer, state.changed_since(acked), state.changes)
+            })
+            .filter(|(_, entries, _)| !entries.is_empty())
+            .collect()
+    }
+
+    fn gossip(entries: Vec<Entry>) -> io::Result<MessageBody> {
+        MessageBody::from_custom(&LwwGossip { entries })
+    }
+
+    // Sends each peer the keys changed since its last ack every interval,
+    // runs until the process exits. Peers suspected down are skipped.
+    pub async fn run(self: Arc<Self>, maelstrom: Maelstrom) {
+        let mut interval = maelstrom.interval(self.period);
+        loop {
+            interval.tick().await;
+            for (dest, entries, upto) in self.pending(&maelstrom).await {
+                if !maelstrom.is_alive(&dest) {
+                    continue;
+                }
+                let body = match Self::gossip(entries) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        maelstrom.log(format!("Error: encoding lww gossip failed: {e}"));
+                        continue;
+                    }
+                };
+                let (maelstrom, state) = (maelstrom.clone(), self.state.clone());
+                maelstrom.clone().spawn(async move {
+                    // no retries, whatever isn't acked goes out on the next tick
+                    if maelstrom.rpc(dest.to_owned(), body, false).await.is_ok() {
+                        let mut state = state.lock().await;
+                        let acked = state.acked.entry(dest).or_default();
+                        *acked = (*acked).max(upto);
+                    }
+                });
+            }
+        }
+    }
+
+    // last chance to push unacked changes at shutdown, acks can no longer arrive
+    pub async fn flush(&self, maelstrom: &Maelstrom) -> io::Result<()> {
+        for (dest, entries, _) in self.pending(maelstrom).await {
+            maelstrom.send_with_id(dest, Self::gossip(entries)?)?;
+        }
+        Ok(())
+    }
+}
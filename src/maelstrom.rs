@@ -1,26 +1,67 @@
 use std::{
     collections::HashMap,
+    fmt,
     future::Future,
-    io::{self, BufRead, Error},
+    io::{self, Error},
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
+use serde_json::Value as Json;
 use tokio::{
     sync::{
+        broadcast, mpsc,
         oneshot::{self, Sender},
-        Mutex, OnceCell,
+        watch, Mutex, Semaphore,
     },
     task::JoinHandle,
-    time::interval,
 };
-use tokio_util::task::TaskTracker;
+use tokio_util::{
+    sync::CancellationToken,
+    task::{AbortOnDropHandle, TaskTracker},
+};
+
+use crate::{
+    canonical,
+    clock::{Clock, Interval, SystemClock},
+    compression::{decompress_body, Compressor},
+    context::{current_deadline, with_deadline},
+    dedup::{Lookup, OutboundDedup, RequestCache},
+    extensions::Extensions,
+    failure_detector::{FailureDetector, PeerEvent},
+    lamport::LamportClock,
+    message::{Envelope, ErrorCode, Message, MessageBody, MessageType, MsgId, NodeId},
+    metrics::{with_metrics, Metrics},
+    ordered::OrderedQueues,
+    outbox::Outbox,
+    peer_health::{HealthTracker, PeerHealth},
+    rate_limit::{Priority, RateLimiter},
+    schema,
+    self_test::self_test,
+    stream::StreamAssembler,
+    timer::Timer,
+    trace::{current_trace_id, new_trace_id, with_trace_id, Direction, Tracer},
+    transport::{StdioTransport, Transport},
+    write_queue::WriteQueue,
+};
+pub use crate::{
+    config::{Config, MaelstromBuilder},
+    context::Context,
+};
+
+// CAS retry loops spinning longer than this are reported in strict mode
+pub const STRICT_CAS_ATTEMPTS: u32 = 1000;
 
-use crate::message::{Message, MessageBody, MessageType};
+// steps of node startup that background tasks can wait for, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Startup {
+    Init,
+    Topology,
+}
 
 #[derive(Clone)]
 pub struct Maelstrom {
@@ -28,192 +69,1382 @@ pub struct Maelstrom {
 }
 
 pub struct MaelstromInner {
-    node: OnceCell<NodeMeta>,
-    rpc: Mutex<HashMap<u64, Sender<Message>>>,
+    config: Config,
+    node_id: std::sync::OnceLock<NodeId>,
+    // membership from the latest init message
+    node_ids: watch::Sender<Vec<NodeId>>,
+    rpc: std::sync::Mutex<HashMap<u64, Sender<Message>>>,
+    // streamed replies being received, keyed by the msg_id of their request
+    streams: std::sync::Mutex<HashMap<u64, mpsc::UnboundedSender<Message>>>,
     next_msg_id: AtomicU64,
     task_tracker: TaskTracker,
+    requests: RequestCache,
+    handler_permits: Option<Arc<Semaphore>>,
+    // outstanding rpc slots per destination, see `rpc_inflight_per_dest`
+    rpc_permits: std::sync::Mutex<HashMap<String, Arc<Semaphore>>>,
+    health: HealthTracker,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    outbound_dedup: Option<OutboundDedup>,
+    compressor: Option<Compressor>,
+    // neighbours of this node from the latest topology message
+    neighbours: watch::Sender<Vec<NodeId>>,
+    extensions: Extensions,
+    outbox: Outbox,
+    ordered: OrderedQueues,
+    outbox_task: Mutex<Option<JoinHandle<()>>>,
+    failure_detector: Option<FailureDetector>,
+    heartbeat_task: Mutex<Option<JoinHandle<()>>>,
+    lamport: Option<LamportClock>,
+    // cancelled at shutdown so pending timers don't hold it up
+    timers: CancellationToken,
+    clock: Arc<dyn Clock>,
+    transport: Arc<dyn Transport>,
+    tracer: Option<Tracer>,
+    // lines on their way to the transport, written by the writer task
+    write_queue: Arc<WriteQueue>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+    limiter_task: Mutex<Option<JoinHandle<()>>>,
+    // furthest startup step processed so far
+    startup: watch::Sender<Option<Startup>>,
+    background: std::sync::Mutex<Vec<JoinHandle<()>>>,
+    // stops tasks of `spawn_cancellable`, replaced by a fresh token on `cancel_all`
+    cancellable: std::sync::Mutex<CancellationToken>,
+    // also the current metrics of the node's tasks, see `with_metrics`
+    metrics: Arc<Metrics>,
 }
 
 #[derive(Debug)]
 pub struct NodeMeta {
-    node_id: String,
-    node_ids: Vec<String>,
+    node_id: NodeId,
+    node_ids: Vec<NodeId>,
+}
+
+impl Default for Maelstrom {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Maelstrom {
+    // default configuration with MAELSTROM_* environment overrides applied
     pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> MaelstromBuilder {
+        MaelstromBuilder::default()
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Self::with_extensions(config, Extensions::default())
+    }
+
+    pub fn with_extensions(config: Config, extensions: Extensions) -> Self {
+        Self::with_parts(config, extensions, None, None)
+    }
+
+    // What the builder assembles, the system clock and stdin / stdout unless
+    // `clock` and `transport` are given.
+    pub fn with_parts(
+        config: Config,
+        extensions: Extensions,
+        clock: Option<Arc<dyn Clock>>,
+        transport: Option<Arc<dyn Transport>>,
+    ) -> Self {
+        let handler_permits = config
+            .max_concurrent_handlers
+            .map(|limit| Arc::new(Semaphore::new(limit.max(1))));
+        let rate_limiter = RateLimiter::new(
+            config.global_rate_limit,
+            config.dest_rate_limit,
+            config.dest_rate_limits.to_owned(),
+        )
+        .map(Arc::new);
+        let outbound_dedup = config.outbound_dedup_window.map(OutboundDedup::new);
+        let compressor = config.compress_threshold.map(Compressor::new);
+        let lamport = config.lamport.then(LamportClock::default);
+        let clock = clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let failure_detector = config
+            .heartbeat_interval
+            .map(|_| FailureDetector::new(config.heartbeat_timeout, clock.now()));
+        let tracer = config.trace_prefix.as_ref().map(Tracer::new);
+        if config.canonical {
+            canonical::enable();
+        }
+        let transport = transport.unwrap_or_else(|| Arc::new(StdioTransport::default()));
+
         Self {
             inner: Arc::new(MaelstromInner {
-                node: Default::default(),
+                requests: RequestCache::new(config.dedup_window),
+                config,
+                node_id: Default::default(),
+                node_ids: watch::Sender::new(vec![]),
                 rpc: Default::default(),
+                streams: Default::default(),
                 next_msg_id: AtomicU64::new(0),
                 task_tracker: TaskTracker::new(),
+                handler_permits,
+                rpc_permits: Default::default(),
+                health: Default::default(),
+                rate_limiter,
+                outbound_dedup,
+                compressor,
+                neighbours: watch::Sender::new(vec![]),
+                extensions,
+                outbox: Default::default(),
+                ordered: Default::default(),
+                outbox_task: Default::default(),
+                failure_detector,
+                heartbeat_task: Default::default(),
+                lamport,
+                timers: CancellationToken::new(),
+                clock,
+                transport,
+                tracer,
+                write_queue: Default::default(),
+                writer: Default::default(),
+                limiter_task: Default::default(),
+                startup: watch::Sender::new(None),
+                background: Default::default(),
+                cancellable: Default::default(),
+                metrics: Default::default(),
             }),
         }
     }
 
+    pub fn config(&self) -> &Config {
+        &self.inner.config
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.inner.clock.clone()
+    }
+
+    pub fn now(&self) -> Instant {
+        self.inner.clock.now()
+    }
+
+    pub async fn sleep(&self, duration: Duration) {
+        self.inner.clock.sleep(duration).await
+    }
+
+    // periodic ticks driven by the runtime clock, the first one completes immediately
+    pub fn interval(&self, period: Duration) -> Interval {
+        Interval::new(self.clock(), period)
+    }
+
+    // this node's own, nodes sharing a process each have their own
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+
+    // shared state of type T, created with its default on first use unless
+    // provided through the builder
+    pub fn state<T: Default + Send + Sync + 'static>(&self) -> Arc<T> {
+        self.inner.extensions.get_or_init(T::default)
+    }
+
+    pub fn try_state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.inner.extensions.get()
+    }
+
+    // prefixed with the trace id of the current request, if any
     pub fn log(&self, message: String) {
-        eprintln!("{message}");
+        match current_trace_id() {
+            Some(trace_id) => eprintln!("[{trace_id}] {message}"),
+            None => eprintln!("{message}"),
+        }
+    }
+
+    // Sets the identity from an init message and returns whether the membership
+    // changed. The node id is fixed once set, a repeated init may only bring a
+    // new set of nodes.
+    pub fn set_node_meta(&self, node: NodeMeta) -> io::Result<bool> {
+        let node_id = self.inner.node_id.get_or_init(|| node.node_id.to_owned());
+        if *node_id != node.node_id {
+            return Err(Error::other(format!(
+                "node {node_id} can't be re-initialized as {}",
+                node.node_id
+            )));
+        }
+        Ok(self.inner.node_ids.send_if_modified(|current| {
+            if *current == node.node_ids {
+                return false;
+            }
+            *current = node.node_ids;
+            true
+        }))
+    }
+
+    // empty until init
+    pub fn node_id(&self) -> &NodeId {
+        static UNSET: NodeId = NodeId(String::new());
+        self.inner.node_id.get().unwrap_or(&UNSET)
+    }
+
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.inner.node_ids.borrow().to_owned()
     }
 
-    pub fn set_node_meta(&self, node: NodeMeta) -> io::Result<()> {
+    // every node in the cluster except the current one
+    pub fn other_node_ids(&self) -> Vec<NodeId> {
         self.inner
-            .node
-            .set(node)
-            .map_err(|e| Error::new(io::ErrorKind::Other, e))
+            .node_ids
+            .borrow()
+            .iter()
+            .filter(|node_id| !self.is_self(node_id))
+            .cloned()
+            .collect()
     }
 
-    pub fn node_id(&self) -> &str {
-        if let Some(node) = self.inner.node.get() {
-            return node.node_id.as_str();
+    // changes whenever an init message brings a new membership
+    pub fn watch_node_ids(&self) -> watch::Receiver<Vec<NodeId>> {
+        self.inner.node_ids.subscribe()
+    }
+
+    // Looks the node up in place: this runs for every line sent and received,
+    // so the membership is borrowed rather than cloned like `node_ids` does.
+    pub fn is_member(&self, node_id: &str) -> bool {
+        self.inner
+            .node_ids
+            .borrow()
+            .iter()
+            .any(|node| node == node_id)
+    }
+
+    pub fn is_self(&self, node_id: &str) -> bool {
+        node_id == self.node_id()
+    }
+
+    // maelstrom names clients c1, c2, ... and nodes n1, n2, ...
+    pub fn is_client(&self, node_id: &str) -> bool {
+        node_id.starts_with('c')
+    }
+
+    // In strict mode a violation is fatal so development runs surface bugs that
+    // are silently tolerated otherwise.
+    pub fn protocol_violation(&self, what: impl AsRef<str>) {
+        if self.inner.config.strict {
+            self.log(format!("strict mode: {}", what.as_ref()));
+            std::process::exit(1);
         }
-        ""
     }
 
-    pub fn node_ids(&self) -> Vec<String> {
-        if let Some(node) = self.inner.node.get() {
-            return node.node_ids.to_owned();
+    // peers not suspected down, all peers when heartbeats are disabled
+    pub fn alive_peers(&self) -> Vec<NodeId> {
+        self.other_node_ids()
+            .into_iter()
+            .filter(|peer| self.is_alive(peer))
+            .collect()
+    }
+
+    pub fn is_alive(&self, peer: &str) -> bool {
+        match &self.inner.failure_detector {
+            Some(detector) => detector.is_alive(peer),
+            None => true,
         }
-        vec![]
     }
 
-    fn next_msg_id(&self) -> u64 {
+    // up/down events of peers, None when heartbeats are disabled
+    pub fn watch_peers(&self) -> Option<broadcast::Receiver<PeerEvent>> {
+        self.inner
+            .failure_detector
+            .as_ref()
+            .map(|detector| detector.subscribe())
+    }
+
+    // What the rpcs to `dest` went like so far, None before the first one. Apps
+    // can skip gossiping to unhealthy peers and catch them up once they answer
+    // again.
+    pub fn peer_health(&self, dest: &str) -> Option<PeerHealth> {
+        self.inner.health.get(dest)
+    }
+
+    // down when a destination turns unhealthy, up when it answers again
+    pub fn watch_peer_health(&self) -> broadcast::Receiver<PeerEvent> {
+        self.inner.health.subscribe()
+    }
+
+    async fn run_heartbeats(self, period: Duration) {
+        let mut interval = self.interval(period);
+        loop {
+            interval.tick().await;
+            let Some(detector) = &self.inner.failure_detector else {
+                return;
+            };
+
+            let body = MessageBody::with_type(MessageType::Heartbeat);
+            let peers = self.other_node_ids();
+            for peer in &peers {
+                let _ = self.resend(peer.to_owned(), body.clone());
+            }
+            detector.check(peers.iter().map(|peer| &**peer), self.now());
+        }
+    }
+
+    // None unless the lamport clock is enabled
+    pub fn lamport(&self) -> Option<&LamportClock> {
+        self.inner.lamport.as_ref()
+    }
+
+    // Runs a background task once the node reached the startup step, e.g. a
+    // gossip loop that needs the node id and neighbours. It runs until it
+    // returns or the node shuts down.
+    pub fn spawn_after<F, Fut>(&self, step: Startup, task: F)
+    where
+        F: FnOnce(Maelstrom) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let maelstrom = self.clone();
+        let mut startup = self.inner.startup.subscribe();
+        let metrics = self.inner.metrics.clone();
+        let handle = tokio::spawn(with_metrics(metrics, async move {
+            if startup
+                .wait_for(|reached| *reached >= Some(step))
+                .await
+                .is_ok()
+            {
+                task(maelstrom).await;
+            }
+        }));
+        self.inner.background.lock().unwrap().push(handle);
+    }
+
+    fn reach_startup(&self, step: Startup) {
+        self.inner.startup.send_if_modified(|reached| {
+            if *reached >= Some(step) {
+                return false;
+            }
+            *reached = Some(step);
+            true
+        });
+    }
+
+    pub fn neighbours(&self) -> Vec<NodeId> {
+        self.inner.neighbours.borrow().to_owned()
+    }
+
+    // notified whenever a topology message changes the neighbours of this node
+    pub fn watch_neighbours(&self) -> watch::Receiver<Vec<NodeId>> {
+        self.inner.neighbours.subscribe()
+    }
+
+    pub fn set_topology(&self, topology: &HashMap<NodeId, Vec<NodeId>>) {
+        let neighbours = topology.get(self.node_id()).cloned().unwrap_or_default();
+        self.inner.neighbours.send_if_modified(|current| {
+            if *current == neighbours {
+                return false;
+            }
+            *current = neighbours;
+            true
+        });
+    }
+
+    fn trace(&self, direction: Direction, line: &str) {
+        if let Some(tracer) = &self.inner.tracer {
+            if let Err(e) = tracer.record(self.node_id(), direction, line) {
+                self.log(format!("Error: tracing message failed: {e}"));
+            }
+        }
+    }
+
+    pub fn next_msg_id(&self) -> u64 {
         self.inner.next_msg_id.fetch_add(1, Ordering::Relaxed)
     }
 
-    pub fn send(&self, dest: String, body: MessageBody) -> io::Result<()> {
+    // the line, and its summary when messages are logged, taken from the message
+    // rather than parsed back from the line
+    fn encode(&self, dest: &str, mut body: MessageBody) -> io::Result<(String, Option<String>)> {
+        // clients and services don't know these fields, only peers are stamped
+        if self.is_peer(dest) {
+            if let Some(clock) = &self.inner.lamport {
+                body.lamport = Some(clock.tick());
+            }
+            if body.trace_id.is_none() {
+                body.trace_id = current_trace_id();
+            }
+        }
+        let body = match &self.inner.compressor {
+            Some(compressor) => compressor.maybe_compress(dest, body, self.metrics())?,
+            None => body,
+        };
         let message = Message {
             src: self.node_id().to_owned(),
-            dest,
+            dest: dest.into(),
             body,
         };
-        let message = serde_json::to_value(message)?;
+        let summary = self.inner.config.log_messages.then(|| message.to_string());
+        Ok((serde_json::to_string(&message)?, summary))
+    }
 
-        println!("{message}");
-        self.log(format!("sent {}", message.to_string()));
+    pub fn send(&self, dest: impl Into<NodeId>, body: MessageBody) -> io::Result<()> {
+        self.send_line(dest.into(), body, true)
+    }
 
+    // Like `send`, but never dropped by the outbound dedup. For retransmissions
+    // and heartbeats, which repeat an earlier line on purpose.
+    pub fn resend(&self, dest: impl Into<NodeId>, body: MessageBody) -> io::Result<()> {
+        self.send_line(dest.into(), body, false)
+    }
+
+    fn send_line(&self, dest: NodeId, body: MessageBody, dedup: bool) -> io::Result<()> {
+        let priority = self.priority(&dest, &body);
+        let (line, summary) = self.encode(&dest, body)?;
+        if let (true, Some(outbound)) = (dedup, &self.inner.outbound_dedup) {
+            if !outbound.admit(&line, self.now()) {
+                self.metrics().incr("outbound.deduplicated", 1);
+                return Ok(());
+            }
+        }
+        self.write_line(&dest, priority, line, summary);
         Ok(())
     }
 
-    pub fn send_with_id(&self, dest: String, mut body: MessageBody) -> io::Result<()> {
-        body.msg_id = Some(self.inner.next_msg_id.fetch_add(1, Ordering::Relaxed));
+    // Requests and one way messages between nodes are background traffic,
+    // replies and anything for clients or services is latency sensitive.
+    fn priority(&self, dest: &str, body: &MessageBody) -> Priority {
+        if self.is_peer(dest) && body.in_reply_to.is_none() {
+            Priority::Low
+        } else {
+            Priority::High
+        }
+    }
+
+    // another node of the cluster, as opposed to clients and services
+    fn is_peer(&self, dest: &str) -> bool {
+        !self.is_self(dest) && self.is_member(dest)
+    }
+
+    // `summary` is None for replayed replies, which are logged as the line they are
+    fn write_line(&self, dest: &str, priority: Priority, line: String, summary: Option<String>) {
+        // what maelstrom's msgs-per-op counts, requests and replies alike
+        if self.is_peer(dest) {
+            self.metrics().incr("net.peer_messages", 1);
+        }
+        if self.inner.config.log_messages {
+            match summary {
+                Some(summary) => self.log(format!("sent {summary}")),
+                None => self.log(format!("sent {line}")),
+            }
+        }
+        self.trace(Direction::Sent, &line);
+
+        let line = match &self.inner.rate_limiter {
+            Some(limiter) => limiter.submit(dest, priority, line),
+            None => Some(line),
+        };
+        if let Some(line) = line {
+            self.inner.write_queue.push(priority, line);
+        }
+    }
+
+    pub fn send_with_id(&self, dest: impl Into<NodeId>, mut body: MessageBody) -> io::Result<()> {
+        body.msg_id = Some(
+            self.inner
+                .next_msg_id
+                .fetch_add(1, Ordering::Relaxed)
+                .into(),
+        );
         self.send(dest, body)
     }
 
-    pub fn reply(&self, request: Message, mut body: MessageBody) -> io::Result<()> {
-        body.in_reply_to = request.body.msg_id;
-        self.send(request.src, body)
+    pub fn reply(&self, request: Message, body: MessageBody) -> io::Result<()> {
+        self.reply_to(&request.src, request.body.msg_id.clone(), body)
+    }
+
+    pub fn reply_to(
+        &self,
+        src: &str,
+        msg_id: Option<MsgId>,
+        mut body: MessageBody,
+    ) -> io::Result<()> {
+        if msg_id.is_none() {
+            self.protocol_violation(format!("reply to {src} for a request without msg_id"));
+        }
+        body.in_reply_to = msg_id.clone();
+        let (line, summary) = self.encode(src, body)?;
+        self.cache_reply(src, msg_id, &line);
+        self.write_line(src, Priority::High, line, summary);
+        Ok(())
     }
 
     pub fn reply_with_id(&self, request: Message, mut body: MessageBody) -> io::Result<()> {
-        body.msg_id = Some(self.next_msg_id());
-        body.in_reply_to = request.body.msg_id;
-        self.send(request.src, body)
+        body.msg_id = Some(self.next_msg_id().into());
+        self.reply(request, body)
+    }
+
+    pub fn reply_error(
+        &self,
+        request: Message,
+        code: ErrorCode,
+        text: impl Into<String>,
+    ) -> io::Result<()> {
+        self.reply(request, MessageBody::error(code, text))
+    }
+
+    fn cache_reply(&self, src: &str, msg_id: Option<MsgId>, line: &str) {
+        if let (true, Some(msg_id)) = (self.inner.config.dedup_requests, msg_id) {
+            self.inner
+                .requests
+                .complete(src, msg_id, line.to_owned(), self.now());
+        }
     }
 
     pub async fn rpc(
         &self,
-        dest: String,
+        dest: impl Into<NodeId>,
         mut body: MessageBody,
         retry: bool,
     ) -> io::Result<Message> {
+        let dest = dest.into();
+        // queue behind earlier rpcs to a congested destination, retries included
+        let _permit = match self.rpc_permits(&dest) {
+            Some(permits) => {
+                if permits.available_permits() == 0 {
+                    self.metrics().incr_key("rpc.queued", &dest, 1);
+                }
+                permits.acquire_owned().await.ok()
+            }
+            None => None,
+        };
+
+        // a request being served bounds the rpc by what is left of its deadline,
+        // retrying rpcs included
+        let budget =
+            current_deadline().map(|deadline| deadline.saturating_duration_since(self.now()));
+        if budget == Some(Duration::ZERO) {
+            return Err(Error::new(
+                io::ErrorKind::TimedOut,
+                "deadline passed before the rpc was sent",
+            ));
+        }
+        let timeout = match (retry, budget) {
+            (false, Some(budget)) => budget.min(self.inner.config.rpc_timeout),
+            (true, Some(budget)) => budget,
+            (_, None) => self.inner.config.rpc_timeout,
+        };
+
         let msg_id = self.next_msg_id();
-        body.msg_id = Some(msg_id);
+        body.msg_id = Some(msg_id.into());
 
         let (sender, mut receiver) = oneshot::channel::<Message>();
-        let mut interval = interval(Duration::from_millis(500));
-        self.inner.rpc.lock().await.insert(msg_id, sender);
+        let mut interval = self.interval(self.inner.config.rpc_retry_interval);
+        let timeout = self.sleep(timeout);
+        tokio::pin!(timeout);
+        self.inner.rpc.lock().unwrap().insert(msg_id, sender);
+        let _pending = PendingRpc {
+            rpcs: &self.inner.rpc,
+            msg_id,
+        };
 
         self.send(dest.to_owned(), body.to_owned())?;
         interval.tick().await;
 
         loop {
             tokio::select! {
-                _ = interval.tick() => {
-                    if retry {
-                        self.send(dest.to_owned(), body.to_owned())?;
-                    } else {
-                        return Err(Error::new(io::ErrorKind::TimedOut, "rpc timed out"));
+                _ = interval.tick(), if retry => {
+                    self.inner.health.timeout(&dest, self.now());
+                    self.resend(dest.to_owned(), body.to_owned())?;
+                },
+                _ = &mut timeout, if !retry || budget.is_some() => {
+                    // retried rpcs counted every unanswered attempt already
+                    if !retry {
+                        self.inner.health.timeout(&dest, self.now());
+                    }
+                    return Err(Error::new(io::ErrorKind::TimedOut, "rpc timed out"));
+                },
+                Ok(msg) = &mut receiver => {
+                    self.inner.health.success(&dest, self.now());
+                    return Ok(msg);
+                }
+            }
+        }
+    }
+
+    // Sends a request answered with `reply_part`s and a final reply and returns
+    // all of them in the order they were sent. Not retried; gives up once the
+    // stream stalls for longer than the rpc timeout.
+    pub async fn rpc_stream(
+        &self,
+        dest: impl Into<NodeId>,
+        mut body: MessageBody,
+    ) -> io::Result<Vec<Message>> {
+        let dest = dest.into();
+        let msg_id = self.next_msg_id();
+        body.msg_id = Some(msg_id.into());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        self.inner.streams.lock().unwrap().insert(msg_id, sender);
+        let result = match self.send(dest.to_owned(), body) {
+            Ok(()) => self.receive_stream(&dest, &mut receiver).await,
+            Err(e) => Err(e),
+        };
+        self.inner.streams.lock().unwrap().remove(&msg_id);
+        result
+    }
+
+    async fn receive_stream(
+        &self,
+        dest: &str,
+        receiver: &mut mpsc::UnboundedReceiver<Message>,
+    ) -> io::Result<Vec<Message>> {
+        let mut assembler = StreamAssembler::default();
+        loop {
+            tokio::select! {
+                Some(part) = receiver.recv() => {
+                    if let Some(parts) = assembler.push(part) {
+                        self.inner.health.success(dest, self.now());
+                        return Ok(parts);
                     }
                 },
-                msg = &mut receiver => {
-                    return Ok(msg.unwrap());
+                _ = self.sleep(self.inner.config.rpc_timeout) => {
+                    self.inner.health.timeout(dest, self.now());
+                    return Err(Error::new(io::ErrorKind::TimedOut, "streamed reply stalled"));
                 }
             }
         }
     }
 
+    fn rpc_permits(&self, dest: &str) -> Option<Arc<Semaphore>> {
+        let limit = self.inner.config.rpc_inflight_per_dest?;
+        let mut permits = self.inner.rpc_permits.lock().unwrap();
+        let permits = permits
+            .entry(dest.to_owned())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit.max(1))));
+        Some(permits.clone())
+    }
+
     pub fn spawn_rpc(
         &self,
-        dest: String,
+        dest: impl Into<NodeId>,
         body: MessageBody,
         retry: bool,
     ) -> JoinHandle<io::Result<Message>> {
         let m = self.clone();
+        let dest = dest.into();
         self.spawn(async move { m.rpc(dest, body, retry).await })
     }
 
-    pub async fn process_response(maelstrom: Self, request: Message, in_reply_to: u64) {
-        let sender = maelstrom.inner.rpc.lock().await.remove(&in_reply_to);
+    // Relays a request to the node that owns it and answers the original sender
+    // with the owner's reply, error replies included.
+    pub async fn forward(&self, request: &Message, owner: &str) -> io::Result<()> {
+        let body = self.relay(request, owner).await?;
+        self.reply_to(&request.src, request.body.msg_id.clone(), body)
+    }
+
+    // the owner's reply to a copy of the request, ready to be sent to the original sender
+    pub async fn relay(&self, request: &Message, owner: &str) -> io::Result<MessageBody> {
+        let body = MessageBody::with_type(request.body.msg_type.to_owned());
+        let response = self.rpc(owner, body, false).await?;
+        self.metrics().incr("forward.relayed", 1);
+        Ok(MessageBody::with_type(response.body.msg_type))
+    }
+
+    // Fires the rpcs concurrently, at most `rpc_concurrency` at a time, and
+    // returns their results in call order.
+    pub async fn rpc_all(
+        &self,
+        calls: impl IntoIterator<Item = (impl Into<NodeId>, MessageBody)>,
+        retry: bool,
+    ) -> Vec<io::Result<Message>> {
+        self.rpc_all_limited(calls, retry, None).await
+    }
+
+    // `rpc_all` with at most `limit` rpcs at a time, or fewer when
+    // `rpc_concurrency` is lower
+    pub async fn rpc_all_limited(
+        &self,
+        calls: impl IntoIterator<Item = (impl Into<NodeId>, MessageBody)>,
+        retry: bool,
+        limit: Option<usize>,
+    ) -> Vec<io::Result<Message>> {
+        let limit = match (limit, self.inner.config.rpc_concurrency) {
+            (Some(limit), Some(config)) => Some(limit.min(config)),
+            (limit, config) => limit.or(config),
+        };
+        let permits = limit.map(|limit| Arc::new(Semaphore::new(limit.max(1))));
+
+        let handles: Vec<_> = calls
+            .into_iter()
+            .map(|(dest, body)| {
+                let dest = dest.into();
+                let maelstrom = self.clone();
+                let permits = permits.clone();
+                self.spawn(async move {
+                    let _permit = match permits {
+                        Some(permits) => permits.acquire_owned().await.ok(),
+                        None => None,
+                    };
+                    maelstrom.rpc(dest, body, retry).await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.await.unwrap_or_else(|e| Err(Error::other(e))));
+        }
+        results
+    }
+
+    // Sends a message to a peer and keeps resending it from the outbox task until
+    // the peer replies, even if the caller is gone by then.
+    pub fn enqueue(&self, dest: impl Into<NodeId>, mut body: MessageBody) -> io::Result<()> {
+        let dest = dest.into();
+        let msg_id = self.next_msg_id();
+        body.msg_id = Some(msg_id.into());
+        self.inner
+            .outbox
+            .insert(msg_id, dest.to_owned(), body.to_owned(), self.now());
+        self.send(dest, body)
+    }
+
+    // Sends a message to a peer over its FIFO channel: the peer's app sees the
+    // messages in the order they were sent, lost ones are resent from the
+    // outbox task until acknowledged.
+    pub fn send_ordered(&self, dest: impl Into<NodeId>, body: MessageBody) -> io::Result<()> {
+        let dest = dest.into();
+        let body = self.inner.ordered.push(&dest, body, self.now());
+        self.send(dest, body)
+    }
+
+    // ordered messages to `dest` not acknowledged yet
+    pub fn ordered_backlog(&self, dest: &str) -> usize {
+        self.inner.ordered.backlog(dest)
+    }
+
+    async fn run_outbox(self) {
+        let mut interval = self.interval(self.inner.config.rpc_retry_interval);
+        loop {
+            interval.tick().await;
+            let interval = self.inner.config.rpc_retry_interval;
+            for (dest, body) in self.inner.outbox.due(interval, self.now()) {
+                self.metrics().incr("outbox.resent", 1);
+                if let Err(e) = self.resend(dest, body) {
+                    self.log(format!("Error: outbox resend failed: {e}"));
+                }
+            }
+            self.metrics()
+                .set_gauge("outbox.pending", self.inner.outbox.len() as i64);
+
+            for (dest, body) in self.inner.ordered.due(interval, self.now()) {
+                self.metrics().incr("ordered.resent", 1);
+                if let Err(e) = self.resend(dest, body) {
+                    self.log(format!("Error: ordered resend failed: {e}"));
+                }
+            }
+            self.metrics()
+                .set_gauge("ordered.backlog", self.inner.ordered.total_backlog() as i64);
+        }
+    }
+
+    // restarts the outbox task should it ever panic, pending entries are kept;
+    // aborting the supervisor aborts the running outbox task with it
+    async fn supervise_outbox(self) {
+        loop {
+            match AbortOnDropHandle::new(tokio::spawn(self.clone().run_outbox())).await {
+                Err(e) if e.is_panic() => {
+                    self.log("Error: outbox task panicked, restarting".to_owned())
+                }
+                _ => return,
+            }
+        }
+    }
+
+    pub async fn process_response(maelstrom: Self, request: Message, in_reply_to: MsgId) {
+        // ids handed out here are always u64
+        let Some(in_reply_to) = in_reply_to.as_u64() else {
+            maelstrom.protocol_violation(format!(
+                "reply from {} to unknown msg_id {in_reply_to}",
+                request.src
+            ));
+            return;
+        };
+        if maelstrom.inner.outbox.ack(in_reply_to) {
+            return;
+        }
+        let stream = maelstrom
+            .inner
+            .streams
+            .lock()
+            .unwrap()
+            .get(&in_reply_to)
+            .cloned();
+        if let Some(stream) = stream {
+            let _ = stream.send(request);
+            return;
+        }
+        let sender = maelstrom.inner.rpc.lock().unwrap().remove(&in_reply_to);
         if let Some(sender) = sender {
-            sender.send(request).unwrap();
+            // the rpc may be gone by now, e.g. aborted with its handler
+            let _ = sender.send(request);
+        } else if in_reply_to >= maelstrom.inner.next_msg_id.load(Ordering::Relaxed) {
+            // late replies to timed out or retried rpcs are expected, unknown ids are not
+            maelstrom.protocol_violation(format!(
+                "reply from {} to unknown msg_id {in_reply_to}",
+                request.src
+            ));
         }
     }
 
     pub async fn run_with_app(&self, app: Arc<dyn App + 'static>) -> io::Result<()> {
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let line = line?;
-            self.log(format!("received {line}"));
+        // catch wire format regressions early in development builds
+        if cfg!(debug_assertions) {
+            self_test()?;
+        }
+
+        // write and drain rate limited messages in the background, outside the
+        // task tracker so both outlive every task that may still send during shutdown
+        let (queue, transport) = (self.inner.write_queue.clone(), self.inner.transport.clone());
+        let writer = tokio::spawn(async move { queue.run(|line| transport.send(line)).await });
+        *self.inner.writer.lock().await = Some(writer);
+        if let Some(limiter) = self.inner.rate_limiter.clone() {
+            let queue = self.inner.write_queue.clone();
+            let drain = tokio::spawn(async move {
+                limiter
+                    .run(|priority, line| queue.push(priority, line))
+                    .await
+            });
+            *self.inner.limiter_task.lock().await = Some(drain);
+        }
 
-            let request = serde_json::from_str::<Message>(&line)?;
+        let outbox = tokio::spawn(self.clone().supervise_outbox());
+        *self.inner.outbox_task.lock().await = Some(outbox);
+
+        if let Some(period) = self.inner.config.heartbeat_interval {
+            let heartbeats = tokio::spawn(self.clone().run_heartbeats(period));
+            *self.inner.heartbeat_task.lock().await = Some(heartbeats);
+        }
+
+        while let Some(line) = self.inner.transport.recv().await? {
+            self.trace(Direction::Received, &line);
+            if self.route_envelope(&line) {
+                continue;
+            }
+
+            let mut request = match serde_json::from_str::<Message>(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.handle_malformed(&line, e)?;
+                    continue;
+                }
+            };
+            // known types only end up in the catch-all when their fields are off
+            if let MessageType::Other { r#type, .. } = &request.body.msg_type {
+                if MessageType::is_known(r#type) {
+                    self.handle_malformed(&line, format!("invalid fields for type {type}"))?;
+                    continue;
+                }
+            }
+            request.body = match decompress_body(request.body) {
+                Ok(body) => body,
+                Err(e) => {
+                    self.log(format!("warning: undecodable message ({e}): {line}"));
+                    continue;
+                }
+            };
+            // malformed lines are logged in full by `handle_malformed`
+            if self.inner.config.log_messages {
+                self.log(format!("received {request}"));
+            }
+
+            let timestamp = match (&self.inner.lamport, request.body.lamport) {
+                (Some(clock), Some(remote)) => Some(clock.observe(remote)),
+                (Some(clock), None) => Some(clock.now()),
+                _ => None,
+            };
+
+            if let Some(detector) = &self.inner.failure_detector {
+                if self.is_member(&request.src) {
+                    detector.observe(&request.src, self.now());
+                }
+            }
 
-            if let Some(in_reply_to) = request.body.in_reply_to {
+            if let Some(in_reply_to) = request.body.in_reply_to.clone() {
                 self.spawn(Self::process_response(self.clone(), request, in_reply_to));
                 continue;
             }
 
+            // messages of an ordered channel are held back until their predecessors arrived
+            if let Some(seq) = request.body.seq {
+                let src = request.src.to_owned();
+                let (ack, ready) = self.inner.ordered.receive(request, seq);
+                self.send(
+                    src,
+                    MessageBody::with_type(MessageType::OrderedAck { upto: ack }),
+                )?;
+                for request in ready {
+                    let timestamp = self.inner.lamport.as_ref().map(|clock| clock.now());
+                    let src = request.src.to_owned();
+                    self.inner.ordered.chain_handler(&src, |previous| {
+                        self.dispatch(&app, request, timestamp, previous)
+                    });
+                }
+                continue;
+            }
+
+            // keep the topology in the runtime, the message is still handed to the app
+            if let MessageType::Topology { topology } = &request.body.msg_type {
+                self.set_topology(topology);
+                self.reach_startup(Startup::Topology);
+            }
+
             match &request.body.msg_type {
+                // Maelstrom may resend init or restart the node with a new
+                // membership, every init is answered and only changes reach the app
                 MessageType::Init { node_id, node_ids } => {
+                    let reinit = !self.node_id().is_empty();
                     let node_meta = NodeMeta {
                         node_id: node_id.to_owned(),
                         node_ids: node_ids.to_owned(),
                     };
-                    self.set_node_meta(node_meta)?;
+                    // an init for another node id is refused, this node keeps serving as itself
+                    let changed = match self.set_node_meta(node_meta) {
+                        Ok(changed) => changed,
+                        Err(e) => {
+                            self.log(format!("Error: rejected init: {e}"));
+                            self.reply_error(request, ErrorCode::MalformedRequest, e.to_string())?;
+                            continue;
+                        }
+                    };
                     self.reply_with_id(request, MessageBody::with_type(MessageType::InitOk))?;
+                    self.reach_startup(Startup::Init);
+
+                    if changed && self.inner.compressor.is_some() {
+                        for dest in self.other_node_ids() {
+                            self.send(dest, Compressor::capabilities())?;
+                        }
+                    }
+                    if reinit && changed {
+                        self.metrics().incr("init.membership_changed", 1);
+                        let (app, maelstrom) = (app.clone(), self.clone());
+                        self.spawn(async move {
+                            if let Err(e) = app.reinit(maelstrom.clone()).await {
+                                maelstrom.log(format!("Error during re-init: {e}"));
+                            }
+                        });
+                    }
+                }
+                MessageType::Heartbeat => {}
+                MessageType::Capabilities { encodings } => {
+                    if let Some(compressor) = &self.inner.compressor {
+                        compressor.add_peer(&request.src, encodings);
+                    }
                 }
+                MessageType::OrderedAck { upto } => self.inner.ordered.ack(&request.src, *upto),
                 _ => {
-                    // let _ = app.handler(self.clone(), request).await;
-                    let maelstrom = self.clone();
-                    let app = app.clone();
-                    self.spawn(async move {
-                        if let Err(e) = app.handler(maelstrom.clone(), request).await {
-                            maelstrom.log(format!("Error: {e}"));
-                        }
-                    });
+                    self.dispatch(&app, request, timestamp, None);
                 }
             }
         }
 
+        // give the app a chance to flush background state before waiting on tasks
+        if let Err(e) = app.shutdown(self.clone()).await {
+            self.log(format!("Error during shutdown: {e}"));
+        }
+
         self.graceful_shutdown().await;
+
+        let summary = self.metrics().summary();
+        if !summary.is_empty() {
+            self.log(format!("metrics summary:\n{summary}"));
+        }
+        Ok(())
+    }
+
+    // Settles what the routing header alone decides, before the line is parsed
+    // in full: acks of outbox entries and retries of requests already answered.
+    // Lines that need their body, for a Lamport timestamp or an ordered
+    // channel, go the full way. Returns whether the line was handled.
+    fn route_envelope(&self, line: &str) -> bool {
+        // nothing to settle early, the header would be parsed for nothing
+        let early = !self.inner.outbox.is_empty() || self.inner.config.dedup_requests;
+        if !early || self.inner.lamport.is_some() {
+            return false;
+        }
+        let Ok(envelope) = Envelope::parse(line) else {
+            return false;
+        };
+        let header = &envelope.body;
+        let handled = match (&header.in_reply_to, &header.msg_id, header.seq) {
+            (Some(in_reply_to), _, None) => in_reply_to
+                .as_u64()
+                .is_some_and(|in_reply_to| self.inner.outbox.ack(in_reply_to)),
+            (None, Some(msg_id), None) if self.inner.config.dedup_requests => {
+                let src = &envelope.src;
+                match self.inner.requests.replied(src, msg_id.clone(), self.now()) {
+                    Some(reply) => {
+                        self.log(format!("replaying reply for {src}:{msg_id}"));
+                        self.write_line(src, Priority::High, reply, None);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        };
+        if !handled {
+            return false;
+        }
+
+        if self.inner.config.log_messages {
+            self.log(format!("received {line}"));
+        }
+        if let Some(detector) = &self.inner.failure_detector {
+            if self.is_member(&envelope.src) {
+                detector.observe(&envelope.src, self.now());
+            }
+        }
+        true
+    }
+
+    // Hands a request to the app in a task of its own, unless it is a retry
+    // that dedup answers or drops. The handler starts once `previous` finished.
+    fn dispatch(
+        &self,
+        app: &Arc<dyn App>,
+        request: Message,
+        timestamp: Option<u64>,
+        previous: Option<JoinHandle<()>>,
+    ) -> Option<JoinHandle<()>> {
+        let dedup_key = match (self.inner.config.dedup_requests, &request.body.msg_id) {
+            (true, Some(msg_id)) => Some((request.src.to_owned(), msg_id.clone())),
+            _ => None,
+        };
+
+        if let Some((src, msg_id)) = &dedup_key {
+            match self.inner.requests.begin(src, msg_id.clone(), self.now()) {
+                Lookup::New => {}
+                Lookup::InFlight => {
+                    self.log(format!("dropped duplicate of in-flight {src}:{msg_id}"));
+                    return None;
+                }
+                Lookup::Replied(line) => {
+                    self.log(format!("replaying reply for {src}:{msg_id}"));
+                    self.write_line(src, Priority::High, line, None);
+                    return None;
+                }
+            }
+        }
+
+        let maelstrom = self.clone();
+        let deadline = self
+            .inner
+            .config
+            .request_timeout
+            .map(|timeout| self.now() + timeout);
+        // client requests start a trace, peers continue the one they were sent
+        let trace_id = match &request.body.trace_id {
+            Some(trace_id) => Some(trace_id.to_owned()),
+            None if self.is_client(&request.src) => Some(new_trace_id(self.node_id())),
+            None => None,
+        };
+        let ctx = Context::new(
+            self.clone(),
+            request.src.to_owned(),
+            request.body.msg_id.clone(),
+            deadline,
+            timestamp,
+            trace_id.to_owned(),
+        );
+        let app = app.to_owned();
+        let permits = self.inner.handler_permits.clone();
+        Some(self.spawn(with_trace_id(trace_id, async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+
+            // wait for a free handler slot, the semaphore queues waiters fairly
+            let _permit = match permits {
+                Some(permits) => permits.acquire_owned().await.ok(),
+                None => None,
+            };
+
+            let result = maelstrom.run_handler(app, ctx.clone(), request).await;
+            maelstrom.complete_request(&ctx, result);
+            if let Some((src, msg_id)) = dedup_key {
+                maelstrom.inner.requests.abandon(&src, msg_id);
+            }
+        })))
+    }
+
+    // Runs the handler in a task of its own so that a panic only fails this
+    // request, it is answered with a crash error like any other failure. A
+    // handler still running at the deadline of the request is aborted and the
+    // request answered with a timeout.
+    async fn run_handler(
+        &self,
+        app: Arc<dyn App>,
+        ctx: Context,
+        request: Message,
+    ) -> io::Result<Option<MessageBody>> {
+        let deadline_passed = || {
+            self.metrics().incr("handler.deadline_exceeded", 1);
+            Err(Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "deadline of {}:{} passed",
+                    ctx.src,
+                    ctx.msg_id.clone().unwrap_or_default()
+                ),
+            ))
+        };
+        let budget = ctx
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(self.now()));
+        if budget == Some(Duration::ZERO) {
+            return deadline_passed();
+        }
+
+        let mut handler = {
+            let ctx = ctx.clone();
+            let deadline = ctx.deadline;
+            self.spawn(with_deadline(deadline, async move {
+                app.handler(ctx, request).await
+            }))
+        };
+        let expired = async {
+            match budget {
+                Some(budget) => self.sleep(budget).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let result = tokio::select! {
+            result = &mut handler => result,
+            _ = expired => {
+                handler.abort();
+                return deadline_passed();
+            }
+        };
+        match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.metrics().incr("handler.panics", 1);
+                let reason = match e.try_into_panic() {
+                    Ok(panic) => panic
+                        .downcast_ref::<&str>()
+                        .map(|reason| reason.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default(),
+                    Err(e) => e.to_string(),
+                };
+                Err(Error::other(format!(
+                    "handler for {}:{} panicked: {reason}",
+                    ctx.src,
+                    ctx.msg_id.clone().unwrap_or_default()
+                )))
+            }
+        }
+    }
+
+    // Sends the outcome of a handler as the single reply to its request, errors
+    // become error bodies unless the handler already replied.
+    fn complete_request(&self, ctx: &Context, result: io::Result<Option<MessageBody>>) {
+        let body = match result {
+            Ok(body) => body,
+            Err(e) => {
+                self.log(format!("Error: {e}"));
+                self.error_body(&e).filter(|_| !ctx.replied())
+            }
+        };
+
+        if let (Some(body), Some(msg_id)) = (body, &ctx.msg_id) {
+            if ctx.replied() {
+                self.protocol_violation(format!("second reply to {}:{msg_id}", ctx.src));
+            } else if let Err(e) = ctx.reply(body) {
+                self.log(format!("Error: {e}"));
+            }
+        }
+
+        if self.is_client(&ctx.src) && ctx.msg_id.is_some() && !ctx.replied() {
+            self.protocol_violation(format!(
+                "handler did not reply to {}:{}",
+                ctx.src,
+                ctx.msg_id.clone().unwrap_or_default()
+            ));
+        }
+    }
+
+    fn error_body(&self, e: &Error) -> Option<MessageBody> {
+        match e.kind() {
+            io::ErrorKind::Unsupported if !self.inner.config.reply_not_supported => None,
+            io::ErrorKind::Unsupported => Some(MessageBody::not_supported(e.to_string())),
+            io::ErrorKind::TimedOut => Some(MessageBody::timeout(e.to_string())),
+            // the request may or may not have taken effect
+            _ => Some(MessageBody::crash(e.to_string())),
+        }
+    }
+
+    // A single bad line must not take the node down, log it and keep going.
+    // Requests of a known type are answered with the field at fault, other
+    // lines only when `reply_to_malformed` is set; replies are never answered.
+    fn handle_malformed(&self, line: &str, error: impl fmt::Display) -> io::Result<()> {
+        let reason = serde_json::from_str::<Json>(line)
+            .ok()
+            .and_then(|message| schema::check(message.get("body")?))
+            .map_or_else(|| error.to_string(), |field| field.to_string());
+        self.log(format!("warning: malformed message ({reason}): {line}"));
+
+        let Ok(envelope) = Envelope::parse(line) else {
+            return Ok(());
+        };
+        let known = MessageType::is_known(&envelope.body.msg_type);
+        if !known && !self.inner.config.reply_to_malformed {
+            return Ok(());
+        }
+        if let (Some(msg_id), None) = (envelope.body.msg_id, envelope.body.in_reply_to) {
+            let mut body = MessageBody::malformed_request(reason);
+            body.in_reply_to = Some(msg_id);
+            self.send(envelope.src.into_owned(), body)?;
+        }
         Ok(())
     }
 
     async fn graceful_shutdown(&self) {
+        self.inner.timers.cancel();
         self.inner.task_tracker.close();
         self.inner.task_tracker.wait().await;
+
+        if let Some(outbox) = self.inner.outbox_task.lock().await.take() {
+            if !self.inner.outbox.is_empty() {
+                self.log(format!(
+                    "{} outbox messages still unacknowledged",
+                    self.inner.outbox.len()
+                ));
+            }
+            outbox.abort();
+        }
+        if let Some(heartbeats) = self.inner.heartbeat_task.lock().await.take() {
+            heartbeats.abort();
+        }
+        for task in self.inner.background.lock().unwrap().drain(..) {
+            task.abort();
+        }
+
+        if let Some(limiter) = &self.inner.rate_limiter {
+            limiter.close();
+            if let Some(drain) = self.inner.limiter_task.lock().await.take() {
+                let _ = drain.await;
+            }
+        }
+        self.inner.write_queue.close();
+        if let Some(writer) = self.inner.writer.lock().await.take() {
+            let _ = writer.await;
+        }
     }
 
+    // the task keeps the trace id of the spawning task
     pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
     where
         F: Future + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.inner.task_tracker.spawn(future)
+        let future = with_metrics(self.inner.metrics.clone(), future);
+        self.inner
+            .task_tracker
+            .spawn(with_trace_id(current_trace_id(), future))
+    }
+
+    // CPU heavy work such as merging large sets, run off the async workers and
+    // awaited at shutdown like tracked tasks
+    pub fn spawn_blocking<F, T>(&self, work: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.inner.task_tracker.spawn_blocking(work)
+    }
+
+    // Like `spawn`, but `cancel_all` stops the task at its next await point, in
+    // which case it resolves to None.
+    pub fn spawn_cancellable<F>(&self, future: F) -> JoinHandle<Option<F::Output>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let cancelled = self.inner.cancellable.lock().unwrap().clone();
+        self.spawn(async move {
+            tokio::select! {
+                output = future => Some(output),
+                _ = cancelled.cancelled() => None,
+            }
+        })
+    }
+
+    // stops every task started with `spawn_cancellable` so far, e.g. gossip
+    // loops of a node that lost leadership
+    pub fn cancel_all(&self) {
+        let cancelled = std::mem::take(&mut *self.inner.cancellable.lock().unwrap());
+        cancelled.cancel();
+    }
+
+    // sends the body once `delay` has passed, a failed send is logged
+    pub fn send_after(&self, dest: impl Into<NodeId>, body: MessageBody, delay: Duration) -> Timer {
+        let dest = dest.into();
+        let maelstrom = self.clone();
+        self.after(delay, move || {
+            if let Err(e) = maelstrom.send(dest, body) {
+                maelstrom.log(format!("Error: delayed send failed: {e}"));
+            }
+        })
+    }
+
+    // runs the callback once `delay` has passed, unless cancelled or shut down first
+    pub fn after<F>(&self, delay: Duration, callback: F) -> Timer
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shutdown = self.inner.timers.clone();
+        let clock = self.clock();
+        Timer::new(self.spawn(async move {
+            tokio::select! {
+                _ = clock.sleep(delay) => callback(),
+                _ = shutdown.cancelled() => {}
+            }
+        }))
     }
 }
 
+// Removes the reply slot of an rpc however the rpc ends: on a reply, a
+// timeout, a failed send or when its future is dropped, e.g. with a handler
+// aborted at its deadline.
+struct PendingRpc<'a> {
+    rpcs: &'a std::sync::Mutex<HashMap<u64, Sender<Message>>>,
+    msg_id: u64,
+}
+
+impl Drop for PendingRpc<'_> {
+    fn drop(&mut self) {
+        self.rpcs.lock().unwrap().remove(&self.msg_id);
+    }
+}
+
+// error for handlers declining a request, answered with a not-supported error
+// unless `reply_not_supported` is turned off
+pub fn not_supported(msg_type: &MessageType) -> Error {
+    Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} is not supported", msg_type.name()),
+    )
+}
+
 #[async_trait]
 pub trait App: Sync + Send {
-    async fn handler(&self, maelstrom: Maelstrom, request: Message) -> io::Result<()>;
+    // the returned body is sent as the reply, errors are answered with an error body;
+    // return None for messages that need no reply or were answered through the context
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>>;
+
+    // called when a repeated init changed the membership, `node_ids` already
+    // returns the new one
+    async fn reinit(&self, _maelstrom: Maelstrom) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Called once the input is closed, before waiting for spawned tasks to
+    // finish, so handlers may still be running; work that needs every request
+    // processed belongs after `run_with_app` returns.
+    async fn shutdown(&self, _maelstrom: Maelstrom) -> io::Result<()> {
+        Ok(())
+    }
 }
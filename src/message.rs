@@ -1,24 +1,111 @@
-use std::collections::{HashMap, HashSet};
+#[cfg(feature = "broadcast")]
+use std::collections::HashSet;
+use std::{borrow::Cow, collections::HashMap, fmt, io};
 
-use serde::{
-    de::{self, Visitor},
-    ser::SerializeSeq,
-    Deserialize, Serialize,
-};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value as Json;
+
+#[cfg(feature = "broadcast")]
+use crate::canonical;
+use crate::{schema, version_vector::VersionVector};
+
+// Workload specific types, each behind the cargo feature of its workload
+#[cfg(feature = "broadcast")]
+mod broadcast;
+mod id;
+#[cfg(feature = "txn")]
+mod txn;
+
+#[cfg(feature = "broadcast")]
+pub use broadcast::Payload;
+pub use id::{KvKey, LogKey, MsgId, NodeId, Offset};
+#[cfg(feature = "txn")]
+pub use txn::{Key, ListAppendOp, RegisterOp, Transaction};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
-    pub src: String,
-    pub dest: String,
+    pub src: NodeId,
+    pub dest: NodeId,
     pub body: MessageBody,
 }
 
+impl Message {
+    // `body` addressed back to the sender of `request`, in reply to its msg_id
+    pub fn reply_to(request: &Message, mut body: MessageBody) -> Self {
+        body.in_reply_to = request.body.msg_id.clone();
+        Self {
+            src: request.dest.to_owned(),
+            dest: request.src.to_owned(),
+            body,
+        }
+    }
+}
+
+// one line summary for logs, e.g. `c1→n1 broadcast msg_id=3 message=42`
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}→{} {}", self.src, self.dest, self.body)
+    }
+}
+
+// Routing header of a message borrowed from the line it was read from, for
+// paths that only forward or inspect a message. Strings without escapes are
+// borrowed and the rest of the body is skipped unparsed, so unlike `Message`
+// it costs no allocation per line.
+#[derive(Debug, Deserialize)]
+pub struct Envelope<'a> {
+    #[serde(borrow)]
+    pub src: Cow<'a, str>,
+    #[serde(borrow)]
+    pub dest: Cow<'a, str>,
+    #[serde(borrow)]
+    pub body: BodyHeader<'a>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BodyHeader<'a> {
+    // empty when missing, which a full parse rejects
+    #[serde(borrow, rename = "type", default)]
+    pub msg_type: Cow<'a, str>,
+    #[serde(default)]
+    pub msg_id: Option<MsgId>,
+    #[serde(default)]
+    pub in_reply_to: Option<MsgId>,
+    // position in an ordered channel, see `MessageBody::seq`
+    #[serde(default)]
+    pub seq: Option<u64>,
+}
+
+impl<'a> Envelope<'a> {
+    pub fn parse(line: &'a str) -> serde_json::Result<Self> {
+        serde_json::from_str(line)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageBody {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub msg_id: Option<u64>,
+    pub msg_id: Option<MsgId>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub in_reply_to: Option<u64>,
+    pub in_reply_to: Option<MsgId>,
+    // lamport timestamp, only set on messages between nodes when the clock is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lamport: Option<u64>,
+    // causal context attached by the app, see `VersionVector`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<VersionVector>,
+    // trace id of the client request that caused this message between nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    // position in the ordered channel to the destination, see `send_ordered`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    // msg_id of the previous part of a streamed reply, see `Context::reply_part`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev: Option<MsgId>,
+    // set on every part of a streamed reply but the final one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub more: Option<bool>,
     #[serde(flatten)]
     pub msg_type: MessageType,
 }
@@ -28,96 +115,364 @@ impl MessageBody {
         Self {
             msg_id: None,
             in_reply_to: None,
+            lamport: None,
+            version: None,
+            trace_id: None,
+            seq: None,
+            prev: None,
+            more: None,
             msg_type,
         }
     }
+
+    // read reply of the kv services and counter workloads
+    pub fn read_ok_value(value: impl Into<Value>) -> Self {
+        Self::with_type(MessageType::ReadOk(ReadReply::Value {
+            value: value.into(),
+        }))
+    }
+
+    // read reply of the broadcast workload
+    #[cfg(feature = "broadcast")]
+    pub fn read_ok_messages(messages: HashSet<Payload>) -> Self {
+        Self::with_type(MessageType::ReadOk(ReadReply::Messages { messages }))
+    }
+
+    pub fn error(code: ErrorCode, text: impl Into<String>) -> Self {
+        Self::with_type(MessageType::Error {
+            code,
+            text: text.into(),
+        })
+    }
+
+    pub fn timeout(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::Timeout, text)
+    }
+
+    pub fn node_not_found(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::NodeNotFound, text)
+    }
+
+    pub fn not_supported(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::NotSupported, text)
+    }
+
+    pub fn temporarily_unavailable(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::TemporarilyUnavailable, text)
+    }
+
+    pub fn malformed_request(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::MalformedRequest, text)
+    }
+
+    pub fn crash(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::Crash, text)
+    }
+
+    pub fn abort(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::Abort, text)
+    }
+
+    pub fn key_does_not_exist(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::KeyDoesNotExist, text)
+    }
+
+    pub fn key_already_exists(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::KeyAlreadyExists, text)
+    }
+
+    pub fn precondition_failed(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::PreconditionFailed, text)
+    }
+
+    pub fn txn_conflict(text: impl Into<String>) -> Self {
+        Self::error(ErrorCode::TxnConflict, text)
+    }
+
+    // Body carrying a type the app defined itself: any serde type that is
+    // tagged with `type` like `MessageType`, such as an enum with
+    // `#[serde(tag = "type")]`. Types unknown here travel as `Other`.
+    pub fn from_custom<T: Serialize>(body: &T) -> io::Result<Self> {
+        let msg_type = serde_json::from_value(serde_json::to_value(body)?)?;
+        Ok(Self::with_type(msg_type))
+    }
+
+    // the body read back as an app defined type, see `from_custom`
+    pub fn to_custom<T: DeserializeOwned>(&self) -> io::Result<T> {
+        Ok(serde_json::from_value(serde_json::to_value(
+            &self.msg_type,
+        )?)?)
+    }
+
+    // code and text of an error reply, None for any other body
+    pub fn as_error(&self) -> Option<(ErrorCode, &str)> {
+        match &self.msg_type {
+            MessageType::Error { code, text } => Some((*code, text)),
+            _ => None,
+        }
+    }
+}
+
+// longest string field shown in a summary before it is cut short
+const SUMMARY_STRING_LEN: usize = 40;
+
+// Type and ids followed by the fields of the type. Arrays and objects only
+// show their length, as in `messages[12]` or `offsets{3}`.
+impl fmt::Display for MessageBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Ok(Json::Object(fields)) = serde_json::to_value(&self.msg_type) else {
+            return f.write_str("?");
+        };
+        f.write_str(fields.get("type").and_then(Json::as_str).unwrap_or("?"))?;
+        if let Some(msg_id) = &self.msg_id {
+            write!(f, " msg_id={msg_id}")?;
+        }
+        if let Some(in_reply_to) = &self.in_reply_to {
+            write!(f, " in_reply_to={in_reply_to}")?;
+        }
+
+        for (name, value) in fields.iter().filter(|(name, _)| *name != "type") {
+            match value {
+                Json::Array(items) => write!(f, " {name}[{}]", items.len())?,
+                Json::Object(entries) => write!(f, " {name}{{{}}}", entries.len())?,
+                Json::String(text) if text.chars().count() > SUMMARY_STRING_LEN => {
+                    let text: String = text.chars().take(SUMMARY_STRING_LEN).collect();
+                    write!(f, " {name}={text:?}…")?
+                }
+                value => write!(f, " {name}={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MessageType {
+    // the `type` field on the wire, e.g. "broadcast_ok"
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.get("type")?.as_str().map(str::to_owned))
+            .unwrap_or_default()
+    }
+
+    // Whether `name` belongs to one of the variants above. A message of a known
+    // type only ends up in `Other` when its fields don't match the variant.
+    pub fn is_known(name: &str) -> bool {
+        schema::is_known(name)
+    }
+}
+
+// Error codes defined by the maelstrom protocol, sent as plain numbers. Codes
+// outside the protocol, such as app specific ones, are kept as `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "u32", into = "u32")]
+pub enum ErrorCode {
+    Timeout,
+    NodeNotFound,
+    NotSupported,
+    TemporarilyUnavailable,
+    MalformedRequest,
+    Crash,
+    Abort,
+    KeyDoesNotExist,
+    KeyAlreadyExists,
+    PreconditionFailed,
+    TxnConflict,
+    Other(u32),
+}
+
+impl ErrorCode {
+    pub fn code(self) -> u32 {
+        match self {
+            ErrorCode::Timeout => 0,
+            ErrorCode::NodeNotFound => 1,
+            ErrorCode::NotSupported => 10,
+            ErrorCode::TemporarilyUnavailable => 11,
+            ErrorCode::MalformedRequest => 12,
+            ErrorCode::Crash => 13,
+            ErrorCode::Abort => 14,
+            ErrorCode::KeyDoesNotExist => 20,
+            ErrorCode::KeyAlreadyExists => 21,
+            ErrorCode::PreconditionFailed => 22,
+            ErrorCode::TxnConflict => 30,
+            ErrorCode::Other(code) => code,
+        }
+    }
+
+    // The request certainly did not take effect. Timeouts, crashes and unknown
+    // codes leave it open.
+    pub fn is_definite(self) -> bool {
+        !matches!(
+            self,
+            ErrorCode::Timeout | ErrorCode::Crash | ErrorCode::Other(_)
+        )
+    }
+
+    // sending the same request again may succeed
+    pub fn is_retriable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Timeout
+                | ErrorCode::TemporarilyUnavailable
+                | ErrorCode::Crash
+                | ErrorCode::Abort
+                | ErrorCode::TxnConflict
+        )
+    }
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => ErrorCode::Timeout,
+            1 => ErrorCode::NodeNotFound,
+            10 => ErrorCode::NotSupported,
+            11 => ErrorCode::TemporarilyUnavailable,
+            12 => ErrorCode::MalformedRequest,
+            13 => ErrorCode::Crash,
+            14 => ErrorCode::Abort,
+            20 => ErrorCode::KeyDoesNotExist,
+            21 => ErrorCode::KeyAlreadyExists,
+            22 => ErrorCode::PreconditionFailed,
+            30 => ErrorCode::TxnConflict,
+            code => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+// the names used in the maelstrom docs, e.g. "key-does-not-exist"
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::NodeNotFound => "node-not-found",
+            ErrorCode::NotSupported => "not-supported",
+            ErrorCode::TemporarilyUnavailable => "temporarily-unavailable",
+            ErrorCode::MalformedRequest => "malformed-request",
+            ErrorCode::Crash => "crash",
+            ErrorCode::Abort => "abort",
+            ErrorCode::KeyDoesNotExist => "key-does-not-exist",
+            ErrorCode::KeyAlreadyExists => "key-already-exists",
+            ErrorCode::PreconditionFailed => "precondition-failed",
+            ErrorCode::TxnConflict => "txn-conflict",
+            ErrorCode::Other(code) => return write!(f, "error {code}"),
+        };
+        f.write_str(name)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageType {
     Init {
-        node_id: String,
-        node_ids: Vec<String>,
+        node_id: NodeId,
+        node_ids: Vec<NodeId>,
     },
     InitOk,
     Error {
-        code: u32,
+        code: ErrorCode,
         text: String,
     },
 
+    #[cfg(feature = "echo")]
     Echo {
         echo: String,
     },
+    #[cfg(feature = "echo")]
     EchoOk {
         echo: String,
     },
 
+    #[cfg(feature = "unique-ids")]
     Generate,
+    #[cfg(feature = "unique-ids")]
     GenerateOk {
         id: String,
     },
 
+    #[cfg(feature = "broadcast")]
     Broadcast {
-        message: i64,
+        message: Payload,
     },
+    #[cfg(feature = "broadcast")]
     BroadcastOk,
+    #[cfg(feature = "broadcast")]
     BroadcastMany {
-        messages: HashSet<i64>,
+        #[serde(serialize_with = "canonical::serialize_set")]
+        messages: HashSet<Payload>,
     },
+    #[cfg(feature = "broadcast")]
     BroadcastManyOk,
-    Read {
-        key: Option<String>,
-    },
-    ReadOk {
-        #[serde(skip_serializing_if = "Option::is_none")]
-        messages: Option<HashSet<i64>>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        value: Option<Value>,
-    },
+    Read(ReadRequest),
+    ReadOk(ReadReply),
+    // ungated, the runtime tracks the neighbours it names
     Topology {
-        topology: HashMap<String, Vec<String>>,
+        topology: HashMap<NodeId, Vec<NodeId>>,
     },
     TopologyOk,
 
-    Add {
-        delta: i64,
-    },
+    #[cfg(any(feature = "counter", feature = "set"))]
+    Add(AddRequest),
+    #[cfg(any(feature = "counter", feature = "set"))]
     AddOk,
+    #[cfg(feature = "set")]
+    Remove {
+        element: i64,
+    },
+    #[cfg(feature = "set")]
+    RemoveOk,
 
+    // kafka messages may be any json value
+    #[cfg(feature = "kafka")]
     Send {
-        key: String,
-        msg: i64,
+        key: LogKey,
+        msg: Json,
     },
+    #[cfg(feature = "kafka")]
     SendOk {
-        offset: i64,
+        offset: Offset,
     },
+    #[cfg(feature = "kafka")]
     Poll {
-        offsets: HashMap<String, i64>,
+        offsets: HashMap<LogKey, Offset>,
     },
+    #[cfg(feature = "kafka")]
     PollOk {
-        msgs: HashMap<String, Vec<[i64; 2]>>,
+        msgs: HashMap<LogKey, Vec<(Offset, Json)>>,
     },
+    #[cfg(feature = "kafka")]
     CommitOffsets {
-        offsets: HashMap<String, i64>,
+        offsets: HashMap<LogKey, Offset>,
     },
+    #[cfg(feature = "kafka")]
     CommitOffsetsOk,
+    #[cfg(feature = "kafka")]
     ListCommittedOffsets {
-        keys: Vec<String>,
+        keys: Vec<LogKey>,
     },
+    #[cfg(feature = "kafka")]
     ListCommittedOffsetsOk {
-        offsets: HashMap<String, i64>,
+        offsets: HashMap<LogKey, Offset>,
     },
 
+    // workload specific apps convert the operations to `RegisterOp` or `ListAppendOp`
+    #[cfg(feature = "txn")]
     Txn {
         txn: Vec<Transaction>,
     },
+    #[cfg(feature = "txn")]
     TxnOk {
         txn: Vec<Transaction>,
     },
 
     Cas {
-        key: String,
+        key: KvKey,
         from: Value,
         to: Value,
         create_if_not_exists: Option<bool>,
@@ -125,137 +480,385 @@ pub enum MessageType {
     CasOk,
 
     Write {
-        key: String,
+        key: KvKey,
         value: Value,
     },
     WriteOk,
+
+    // lin-tso timestamp request, answered with a strictly increasing ts
+    Ts,
+    TsOk {
+        ts: u64,
+    },
+
+    StateChecksum {
+        checksum: u64,
+    },
+
+    // sent between nodes after init, lists the body encodings the sender can decode
+    Capabilities {
+        encodings: Vec<String>,
+    },
+    // liveness signal for the failure detector, never answered
+    Heartbeat,
+    // a whole body, msg_id included, compressed with `encoding`
+    Compressed {
+        encoding: String,
+        payload: String,
+    },
+    // cumulative ack of an ordered channel, every message up to `upto` arrived
+    OrderedAck {
+        upto: u64,
+    },
+
+    // any other type, e.g. of a newer workload, with its fields kept as they were
+    #[serde(untagged)]
+    Other {
+        r#type: String,
+        #[serde(flatten)]
+        body: Json,
+    },
+}
+
+// Body of a `read`, which is both the client read of the broadcast and counter
+// workloads and the read of a kv service. They are told apart by the key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ReadFields", into = "ReadFields")]
+pub enum ReadRequest {
+    Client,
+    Kv { key: KvKey },
 }
 
-#[derive(Debug, Clone)]
-pub enum Transaction {
-    Read { key: u64, val: Value },
-    Write { key: u64, value: i64 },
-    Append { key: u64, value: i64 },
+#[derive(Serialize, Deserialize)]
+struct ReadFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<KvKey>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl From<ReadFields> for ReadRequest {
+    fn from(fields: ReadFields) -> Self {
+        match fields.key {
+            Some(key) => ReadRequest::Kv { key },
+            None => ReadRequest::Client,
+        }
+    }
+}
+
+impl From<ReadRequest> for ReadFields {
+    fn from(read: ReadRequest) -> Self {
+        match read {
+            ReadRequest::Client => ReadFields { key: None },
+            ReadRequest::Kv { key } => ReadFields { key: Some(key) },
+        }
+    }
+}
+
+// Body of an `add`, which is shared by the counter and set workloads. They are
+// told apart by the field.
+#[cfg(any(feature = "counter", feature = "set"))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AddRequest {
+    #[cfg(feature = "counter")]
+    Delta { delta: i64 },
+    // the set workloads add integers
+    #[cfg(feature = "set")]
+    Element { element: i64 },
+}
+
+// Body of a `read_ok`, with the field its workload expects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReadReply {
+    #[cfg(feature = "broadcast")]
+    Messages {
+        #[serde(serialize_with = "canonical::serialize_set")]
+        messages: HashSet<Payload>,
+    },
+    // kv services and counter workloads
+    Value {
+        value: Value,
+    },
+}
+
+// Any JSON value, as stored in the kv services. Numbers without a fraction
+// that fit an i64 are ints, every other number is a float.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Value {
+    #[default]
     None,
+    Bool(bool),
     Int(i64),
-    Vec(Vec<i64>),
-    Map(HashMap<String, Vec<i64>>),
+    Float(f64),
     String(String),
+    Vec(Vec<Value>),
+    Map(HashMap<String, Value>),
 }
 
 impl Value {
-    pub fn as_int(self) -> Option<i64> {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    // ints are widened
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Int(v) => Some(*v as f64),
+            Self::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
         match self {
-            Self::Int(v) => Some(v),
+            Self::String(v) => Some(v),
             _ => None,
         }
     }
 
-    pub fn as_vec(self) -> Option<Vec<i64>> {
+    pub fn as_list(&self) -> Option<&[Value]> {
         match self {
             Self::Vec(v) => Some(v),
             _ => None,
         }
     }
+
+    // lists of ints only, None if any element is something else
+    pub fn as_vec(&self) -> Option<Vec<i64>> {
+        self.as_list()?.iter().map(Value::as_int).collect()
+    }
+
+    pub fn as_map(&self) -> Option<&HashMap<String, Value>> {
+        match self {
+            Self::Map(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_map_mut(&mut self) -> Option<&mut HashMap<String, Value>> {
+        match self {
+            Self::Map(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    // The take_* accessors move the contents out and leave None behind, a
+    // value of another kind is left untouched.
+    pub fn take_string(&mut self) -> Option<String> {
+        match self {
+            Self::String(_) => std::mem::take(self).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn take_list(&mut self) -> Option<Vec<Value>> {
+        match self {
+            Self::Vec(_) => std::mem::take(self).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn take_vec(&mut self) -> Option<Vec<i64>> {
+        let ints = self.as_vec()?;
+        *self = Self::None;
+        Some(ints)
+    }
+
+    pub fn take_map(&mut self) -> Option<HashMap<String, Value>> {
+        match self {
+            Self::Map(_) => std::mem::take(self).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    // what kind of value this is, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::None => "null",
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::Vec(_) => "list",
+            Self::Map(_) => "map",
+        }
+    }
 }
 
-impl Serialize for Transaction {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let mut seq = serializer.serialize_seq(Some(3))?;
-        match &self {
-            Transaction::Read { key, val: value } => {
-                seq.serialize_element("r")?;
-                seq.serialize_element(key)?;
-                seq.serialize_element(value)?;
-            }
-            Transaction::Write { key, value } => {
-                seq.serialize_element("w")?;
-                seq.serialize_element(key)?;
-                seq.serialize_element(value)?;
-            }
-            Transaction::Append { key, value } => {
-                seq.serialize_element("append")?;
-                seq.serialize_element(key)?;
-                seq.serialize_element(value)?;
-            }
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Self::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Self::String(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Self::String(v)
+    }
+}
+
+impl From<Vec<i64>> for Value {
+    fn from(v: Vec<i64>) -> Self {
+        Self::Vec(v.into_iter().map(Value::Int).collect())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Self::Vec(v)
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(v: HashMap<String, Value>) -> Self {
+        Self::Map(v)
+    }
+}
+
+// integers beyond i64 become floats
+impl From<Json> for Value {
+    fn from(v: Json) -> Self {
+        match v {
+            Json::Null => Self::None,
+            Json::Bool(v) => Self::Bool(v),
+            Json::Number(v) => match v.as_i64() {
+                Some(v) => Self::Int(v),
+                None => Self::Float(v.as_f64().unwrap_or_default()),
+            },
+            Json::String(v) => Self::String(v),
+            Json::Array(v) => Self::Vec(v.into_iter().map(Into::into).collect()),
+            Json::Object(v) => Self::Map(v.into_iter().map(|(k, v)| (k, v.into())).collect()),
         }
-        seq.end()
-    }
-}
-
-impl<'de> Deserialize<'de> for Transaction {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        // Value::deserialize(deserializer).and_then(|value| match value {
-        //     Value::Array(data) => match &data[0] {
-        //         Value::String(t) => {
-        //             if t.eq("r") {
-        //                 let key = data[1].as_u64().unwrap();
-        //                 let value = data[2].as_i64();
-        //                 Ok(Transaction::Read { key, value })
-        //             } else {
-        //                 let key = data[1].as_u64().unwrap();
-        //                 let value = data[2].as_i64().unwrap();
-        //                 Ok(Transaction::Write { key, value })
-        //             }
-        //         }
-        //         _ => Err(serde::de::Error::custom("failed to de Transaction")),
-        //     },
-        //     _ => Err(serde::de::Error::custom("failed to de Transaction")),
-        // })
-        struct InstanceVisitor;
-
-        impl<'de> Visitor<'de> for InstanceVisitor {
-            type Value = Transaction;
-
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("expected Transaction, which is array of ['r', 4, null]")
-            }
+    }
+}
 
-            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-            where
-                A: serde::de::SeqAccess<'de>,
-            {
-                let op: String = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
-                let key = seq
-                    .next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
-
-                match op.as_str() {
-                    "r" => {
-                        let value = seq
-                            .next_element()?
-                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
-                        Ok(Transaction::Read { key, val: value })
-                    }
-                    "w" => {
-                        let value = seq
-                            .next_element()?
-                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
-                        Ok(Transaction::Write { key, value })
-                    }
-                    "append" => {
-                        let value = seq
-                            .next_element()?
-                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
-                        Ok(Transaction::Append { key, value })
-                    }
-                    _ => Err(de::Error::custom("Inavlid op")),
-                }
-            }
+// non-finite floats become null
+impl From<Value> for Json {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::None => Json::Null,
+            Value::Bool(v) => Json::Bool(v),
+            Value::Int(v) => Json::from(v),
+            Value::Float(v) => Json::from(v),
+            Value::String(v) => Json::String(v),
+            Value::Vec(v) => Json::Array(v.into_iter().map(Into::into).collect()),
+            Value::Map(v) => Json::Object(v.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+// None becomes null
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Self::None, Into::into)
+    }
+}
+
+fn unexpected(expected: &str, found: &Value) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("expected {expected}, found {}", found.kind()),
+    )
+}
+
+impl TryFrom<Value> for bool {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        value.as_bool().ok_or_else(|| unexpected("a bool", &value))
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        value.as_int().ok_or_else(|| unexpected("an int", &value))
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        value
+            .as_float()
+            .ok_or_else(|| unexpected("a number", &value))
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        match value {
+            Value::String(v) => Ok(v),
+            value => Err(unexpected("a string", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        match value {
+            Value::Vec(v) => Ok(v),
+            value => Err(unexpected("a list", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<i64> {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        value
+            .as_vec()
+            .ok_or_else(|| unexpected("a list of ints", &value))
+    }
+}
+
+impl TryFrom<Value> for HashMap<String, Value> {
+    type Error = io::Error;
+
+    fn try_from(value: Value) -> io::Result<Self> {
+        match value {
+            Value::Map(v) => Ok(v),
+            value => Err(unexpected("a map", &value)),
         }
-        deserializer.deserialize_any(InstanceVisitor)
     }
 }
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+// Message of the broadcast workload, any json value. Hashed on its canonical
+// serialization, objects serialize with sorted keys, so equal payloads land
+// in the same set slot whatever their key order on the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Payload(pub Json);
+
+impl Payload {
+    pub fn canonical(&self) -> String {
+        self.0.to_string()
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.0.as_i64()
+    }
+}
+
+impl std::hash::Hash for Payload {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state)
+    }
+}
+
+impl From<i64> for Payload {
+    fn from(v: i64) -> Self {
+        Self(v.into())
+    }
+}
+
+impl From<Json> for Payload {
+    fn from(v: Json) -> Self {
+        Self(v)
+    }
+}
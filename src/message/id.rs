@@ -0,0 +1,225 @@
+use std::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{value::RawValue, Number};
+
+// Newtypes for the strings and numbers of the protocol, so a kv key can't be
+// passed where a node id is expected. The string ids deref to `str` and can be
+// looked up by `&str` in maps and sets.
+macro_rules! string_id {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[derive(
+                Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+            )]
+            #[serde(transparent)]
+            pub struct $name(pub String);
+
+            impl Deref for $name {
+                type Target = str;
+
+                fn deref(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl Borrow<str> for $name {
+                fn borrow(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl AsRef<str> for $name {
+                fn as_ref(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str(&self.0)
+                }
+            }
+
+            impl From<String> for $name {
+                fn from(id: String) -> Self {
+                    Self(id)
+                }
+            }
+
+            impl From<&str> for $name {
+                fn from(id: &str) -> Self {
+                    Self(id.to_owned())
+                }
+            }
+
+            impl From<&String> for $name {
+                fn from(id: &String) -> Self {
+                    Self(id.to_owned())
+                }
+            }
+
+            impl From<&$name> for $name {
+                fn from(id: &$name) -> Self {
+                    id.clone()
+                }
+            }
+
+            impl From<$name> for String {
+                fn from(id: $name) -> Self {
+                    id.0
+                }
+            }
+
+            impl PartialEq<str> for $name {
+                fn eq(&self, other: &str) -> bool {
+                    self.0 == other
+                }
+            }
+
+            impl PartialEq<&str> for $name {
+                fn eq(&self, other: &&str) -> bool {
+                    self.0 == *other
+                }
+            }
+
+            impl PartialEq<$name> for str {
+                fn eq(&self, other: &$name) -> bool {
+                    self == other.0
+                }
+            }
+
+            impl PartialEq<$name> for &str {
+                fn eq(&self, other: &$name) -> bool {
+                    *self == other.0
+                }
+            }
+        )*
+    };
+}
+
+string_id! {
+    // a node, client or service such as "n1", "c4" or "lin-kv"
+    NodeId,
+    // key of a kv service entry
+    KvKey,
+    // name of a kafka log
+    LogKey,
+}
+
+// position of a message in a kafka log
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Offset(pub u64);
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for Offset {
+    fn from(offset: u64) -> Self {
+        Self(offset)
+    }
+}
+
+// A msg_id or in_reply_to as it was on the wire. Ids handed out here are
+// u64, but clients and services may use negative, fractional or huge numbers.
+// Anything that isn't a plain integer keeps its original text, so a reply
+// echoes `1e3` or `18446744073709551616` back exactly as it came in.
+#[derive(Debug, Clone)]
+pub enum MsgId {
+    U64(u64),
+    I64(i64),
+    Raw(Box<RawValue>),
+}
+
+impl MsgId {
+    // None for ids that can't have been handed out by this runtime
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            MsgId::U64(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MsgId {
+    fn default() -> Self {
+        MsgId::U64(0)
+    }
+}
+
+impl Serialize for MsgId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MsgId::U64(id) => serializer.serialize_u64(*id),
+            MsgId::I64(id) => serializer.serialize_i64(*id),
+            MsgId::Raw(raw) => raw.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MsgId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Box::<RawValue>::deserialize(deserializer)?;
+        let text = raw.get();
+        if let Ok(id) = text.parse() {
+            return Ok(MsgId::U64(id));
+        }
+        // -0 would come back as 0
+        if let (Ok(id), false) = (text.parse(), text == "-0") {
+            return Ok(MsgId::I64(id));
+        }
+        serde_json::from_str::<Number>(text).map_err(de::Error::custom)?;
+        Ok(MsgId::Raw(raw))
+    }
+}
+
+// raw ids compare by their text, so every id equals only itself
+impl PartialEq for MsgId {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MsgId::U64(a), MsgId::U64(b)) => a == b,
+            (MsgId::I64(a), MsgId::I64(b)) => a == b,
+            (MsgId::Raw(a), MsgId::Raw(b)) => a.get() == b.get(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for MsgId {}
+
+impl Hash for MsgId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            MsgId::U64(id) => (0u8, *id).hash(state),
+            MsgId::I64(id) => (1u8, *id).hash(state),
+            MsgId::Raw(raw) => (2u8, raw.get()).hash(state),
+        }
+    }
+}
+
+impl fmt::Display for MsgId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MsgId::U64(id) => write!(f, "{id}"),
+            MsgId::I64(id) => write!(f, "{id}"),
+            MsgId::Raw(raw) => f.write_str(raw.get()),
+        }
+    }
+}
+
+impl From<u64> for MsgId {
+    fn from(id: u64) -> Self {
+        MsgId::U64(id)
+    }
+}
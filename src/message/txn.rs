@@ -0,0 +1,219 @@
+use std::{fmt, io};
+
+use serde::{
+    de::{self, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Serialize,
+};
+
+use super::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transaction {
+    Read { key: Key, val: Value },
+    Write { key: Key, value: i64 },
+    Append { key: Key, value: i64 },
+}
+
+// Operation of the txn-rw-register workload, reads see an int or nothing.
+#[derive(Debug, Clone)]
+pub enum RegisterOp {
+    Read { key: Key, val: Option<i64> },
+    Write { key: Key, value: i64 },
+}
+
+// Operation of the txn-list-append workload, reads see a list or nothing.
+#[derive(Debug, Clone)]
+pub enum ListAppendOp {
+    Read { key: Key, val: Option<Vec<i64>> },
+    Append { key: Key, value: i64 },
+}
+
+impl TryFrom<Transaction> for RegisterOp {
+    type Error = io::Error;
+
+    // read results sent along are dropped
+    fn try_from(op: Transaction) -> io::Result<Self> {
+        match op {
+            Transaction::Read { key, .. } => Ok(RegisterOp::Read { key, val: None }),
+            Transaction::Write { key, value } => Ok(RegisterOp::Write { key, value }),
+            Transaction::Append { .. } => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "append is not an operation of txn-rw-register",
+            )),
+        }
+    }
+}
+
+impl From<RegisterOp> for Transaction {
+    fn from(op: RegisterOp) -> Self {
+        match op {
+            RegisterOp::Read { key, val } => Transaction::Read {
+                key,
+                val: val.into(),
+            },
+            RegisterOp::Write { key, value } => Transaction::Write { key, value },
+        }
+    }
+}
+
+impl TryFrom<Transaction> for ListAppendOp {
+    type Error = io::Error;
+
+    // read results sent along are dropped
+    fn try_from(op: Transaction) -> io::Result<Self> {
+        match op {
+            Transaction::Read { key, .. } => Ok(ListAppendOp::Read { key, val: None }),
+            Transaction::Append { key, value } => Ok(ListAppendOp::Append { key, value }),
+            Transaction::Write { .. } => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "write is not an operation of txn-list-append",
+            )),
+        }
+    }
+}
+
+impl From<ListAppendOp> for Transaction {
+    fn from(op: ListAppendOp) -> Self {
+        match op {
+            ListAppendOp::Read { key, val } => Transaction::Read {
+                key,
+                val: val.into(),
+            },
+            ListAppendOp::Append { key, value } => Transaction::Append { key, value },
+        }
+    }
+}
+
+// Key of a transaction operation. Maelstrom workloads use ints but may use
+// any string; both are sent back the way they came. Displayed without quotes,
+// which is what the kv helpers use as the key, so 1 and "1" share a kv key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Key {
+    Int(u64),
+    String(String),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Int(key) => write!(f, "{key}"),
+            Key::String(key) => f.write_str(key),
+        }
+    }
+}
+
+impl From<u64> for Key {
+    fn from(key: u64) -> Self {
+        Key::Int(key)
+    }
+}
+
+impl From<&str> for Key {
+    fn from(key: &str) -> Self {
+        Key::String(key.to_owned())
+    }
+}
+
+impl From<String> for Key {
+    fn from(key: String) -> Self {
+        Key::String(key)
+    }
+}
+
+impl Serialize for Transaction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(3))?;
+        match &self {
+            Transaction::Read { key, val: value } => {
+                seq.serialize_element("r")?;
+                seq.serialize_element(key)?;
+                seq.serialize_element(value)?;
+            }
+            Transaction::Write { key, value } => {
+                seq.serialize_element("w")?;
+                seq.serialize_element(key)?;
+                seq.serialize_element(value)?;
+            }
+            Transaction::Append { key, value } => {
+                seq.serialize_element("append")?;
+                seq.serialize_element(key)?;
+                seq.serialize_element(value)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // Value::deserialize(deserializer).and_then(|value| match value {
+        //     Value::Array(data) => match &data[0] {
+        //         Value::String(t) => {
+        //             if t.eq("r") {
+        //                 let key = data[1].as_u64().unwrap();
+        //                 let value = data[2].as_i64();
+        //                 Ok(Transaction::Read { key, value })
+        //             } else {
+        //                 let key = data[1].as_u64().unwrap();
+        //                 let value = data[2].as_i64().unwrap();
+        //                 Ok(Transaction::Write { key, value })
+        //             }
+        //         }
+        //         _ => Err(serde::de::Error::custom("failed to de Transaction")),
+        //     },
+        //     _ => Err(serde::de::Error::custom("failed to de Transaction")),
+        // })
+        struct InstanceVisitor;
+
+        impl<'de> Visitor<'de> for InstanceVisitor {
+            type Value = Transaction;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("expected Transaction, which is array of ['r', 4, null]")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let op: String = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let key = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+                match op.as_str() {
+                    "r" => {
+                        let value = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                        Ok(Transaction::Read { key, val: value })
+                    }
+                    "w" => {
+                        let value = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                        Ok(Transaction::Write { key, value })
+                    }
+                    "append" => {
+                        let value = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                        Ok(Transaction::Append { key, value })
+                    }
+                    _ => Err(de::Error::custom("Inavlid op")),
+                }
+            }
+        }
+        deserializer.deserialize_any(InstanceVisitor)
+    }
+}
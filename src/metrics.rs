@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// number of keys tracked per labeled metric, keeps cardinality bounded
+pub const TOP_K_CAPACITY: usize = 64;
+// number of keys printed per labeled metric in the summary
+pub const TOP_K_SUMMARY: usize = 10;
+
+// Approximate top-K table using the space-saving algorithm: once full, the smallest
+// entry is evicted and the new key inherits its count, so counts may overestimate.
+struct TopK {
+    entries: HashMap<String, u64>,
+    // counts have a rate, high-water marks don't
+    counts: bool,
+}
+
+impl TopK {
+    fn new(counts: bool) -> Self {
+        Self {
+            entries: HashMap::new(),
+            counts,
+        }
+    }
+
+    fn update(&mut self, key: &str, f: impl FnOnce(u64) -> u64) {
+        if let Some(value) = self.entries.get_mut(key) {
+            *value = f(*value);
+            return;
+        }
+
+        let base = if self.entries.len() < TOP_K_CAPACITY {
+            0
+        } else {
+            let (min_key, min_value) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, value)| **value)
+                .map(|(key, value)| (key.to_owned(), *value))
+                .unwrap();
+            self.entries.remove(&min_key);
+            min_value
+        };
+        self.entries.insert(key.to_owned(), f(base));
+    }
+
+    fn top(&self, k: usize) -> Vec<(String, u64)> {
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(key, value)| (key.to_owned(), *value))
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        entries.truncate(k);
+        entries
+    }
+}
+
+#[derive(Default)]
+struct Timing {
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+// Metrics registry of a node, owned by its `Maelstrom` and summarized in the
+// node log at shutdown.
+pub struct Metrics {
+    started: Instant,
+    counters: Mutex<BTreeMap<String, u64>>,
+    gauges: Mutex<BTreeMap<String, i64>>,
+    keyed: Mutex<BTreeMap<String, TopK>>,
+    timings: Mutex<BTreeMap<String, Timing>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            counters: Default::default(),
+            gauges: Default::default(),
+            keyed: Default::default(),
+            timings: Default::default(),
+        }
+    }
+
+    pub fn incr(&self, name: &str, by: u64) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_default() += by;
+    }
+
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_gauge(&self, name: &str, value: i64) {
+        self.gauges.lock().unwrap().insert(name.to_owned(), value);
+    }
+
+    // labeled counter, e.g. sends per kafka key
+    pub fn incr_key(&self, name: &str, key: &str, by: u64) {
+        self.keyed
+            .lock()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_insert_with(|| TopK::new(true))
+            .update(key, |value| value + by);
+    }
+
+    // labeled high-water mark, e.g. log length per kafka key
+    pub fn max_key(&self, name: &str, key: &str, value: u64) {
+        self.keyed
+            .lock()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_insert_with(|| TopK::new(false))
+            .update(key, |old| old.max(value));
+    }
+
+    // duration sample, summarized as count, mean and max
+    pub fn observe(&self, name: &str, duration: Duration) {
+        let mut timings = self.timings.lock().unwrap();
+        let timing = timings.entry(name.to_owned()).or_default();
+        timing.count += 1;
+        timing.total += duration;
+        timing.max = timing.max.max(duration);
+    }
+
+    pub fn top_k(&self, name: &str, k: usize) -> Vec<(String, u64)> {
+        self.keyed
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|top| top.top(k))
+            .unwrap_or_default()
+    }
+
+    pub fn summary(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let mut summary = String::new();
+
+        for (name, value) in self.counters.lock().unwrap().iter() {
+            let _ = writeln!(
+                summary,
+                "{name} = {value} ({:.2}/s)",
+                *value as f64 / elapsed
+            );
+        }
+        for (name, value) in self.gauges.lock().unwrap().iter() {
+            let _ = writeln!(summary, "{name} = {value}");
+        }
+        for (name, timing) in self.timings.lock().unwrap().iter() {
+            let _ = writeln!(
+                summary,
+                "{name} count={} mean={:.3}ms max={:.3}ms",
+                timing.count,
+                timing.total.as_secs_f64() * 1000.0 / timing.count as f64,
+                timing.max.as_secs_f64() * 1000.0
+            );
+        }
+        for (name, top) in self.keyed.lock().unwrap().iter() {
+            let _ = writeln!(summary, "{name} top {TOP_K_SUMMARY}:");
+            for (key, value) in top.top(TOP_K_SUMMARY) {
+                let _ = if top.counts {
+                    writeln!(
+                        summary,
+                        "  {key:>12} {value:>10} ({:.2}/s)",
+                        value as f64 / elapsed
+                    )
+                } else {
+                    writeln!(summary, "  {key:>12} {value:>10}")
+                };
+            }
+        }
+        summary
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+tokio::task_local! {
+    // metrics of the node the current task runs on
+    static METRICS: Arc<Metrics>;
+}
+
+// None outside the tasks of a node, e.g. in tests driving a data structure directly
+pub fn current_metrics() -> Option<Arc<Metrics>> {
+    METRICS.try_with(Clone::clone).ok()
+}
+
+// runs the future with `metrics` as the current metrics
+pub fn with_metrics<F: Future>(
+    metrics: Arc<Metrics>,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    METRICS.scope(metrics, future)
+}
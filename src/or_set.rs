@@ -0,0 +1,129 @@
+use std::{
+    collections::HashSet,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::checksum,
+    gossip_set::{Element, GossipSet, GOSSIP_INTERVAL},
+    kv::now_millis,
+    maelstrom::Maelstrom,
+    message::{Message, MessageBody, NodeId},
+};
+
+// Unique tag of a single add: the node that took it and a sequence number of
+// that node. Sequence numbers start at the wall clock time of the run shifted
+// up by 20 bits, so a restarted node doesn't reuse tags of an earlier run that
+// other nodes may have retired, unless that run added over a million elements
+// per millisecond it lived.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tag {
+    pub node: NodeId,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Tagged<T> {
+    element: T,
+    tag: Tag,
+}
+
+// Observed-remove set: elements can be removed as well as added, replicated
+// by anti-entropy to every other node.
+//
+// Every add puts the element in with a fresh tag, and a remove retires the
+// tags of the element this node has seen so far. An element is in the set
+// while any of its tags is live, so an add that a remove didn't observe, such
+// as one concurrent on another node, survives it. Adds and retired tags are
+// two grow-only `GossipSet`s, which merge in any order; retired tags are kept
+// forever.
+pub struct OrSet<T> {
+    name: String,
+    adds: Arc<GossipSet<Tagged<T>>>,
+    removes: Arc<GossipSet<Tag>>,
+    next_seq: AtomicU64,
+}
+
+impl<T: Element> OrSet<T> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_sets(name.into(), GOSSIP_INTERVAL)
+    }
+
+    // the set is empty until it runs, so its parts are simply built anew
+    pub fn with_period(self, period: Duration) -> Self {
+        Self::with_sets(self.name, period)
+    }
+
+    fn with_sets(name: String, period: Duration) -> Self {
+        Self {
+            adds: Arc::new(GossipSet::new(format!("{name}/adds")).with_period(period)),
+            removes: Arc::new(GossipSet::new(format!("{name}/removes")).with_period(period)),
+            name,
+            next_seq: AtomicU64::new((now_millis() as u64) << 20),
+        }
+    }
+
+    pub async fn add(&self, maelstrom: &Maelstrom, element: T) {
+        let tag = Tag {
+            node: maelstrom.node_id().to_owned(),
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+        };
+        self.adds.insert([Tagged { element, tag }]).await;
+    }
+
+    // Retires the tags of `element` seen so far, false if it wasn't in the set.
+    pub async fn remove(&self, element: &T) -> bool {
+        let tags: Vec<Tag> = self
+            .adds
+            .elements()
+            .await
+            .into_iter()
+            .filter(|tagged| tagged.element == *element)
+            .map(|tagged| tagged.tag)
+            .collect();
+        !self.removes.insert(tags).await.is_empty()
+    }
+
+    // elements with at least one live tag
+    pub async fn elements(&self) -> HashSet<T> {
+        let removed = self.removes.elements().await;
+        self.adds
+            .elements()
+            .await
+            .into_iter()
+            .filter(|tagged| !removed.contains(&tagged.tag))
+            .map(|tagged| tagged.element)
+            .collect()
+    }
+
+    pub async fn checksum(&self) -> u64 {
+        checksum([self.adds.checksum().await, self.removes.checksum().await])
+    }
+
+    // the ack to reply with, None if the request isn't gossip of this set
+    pub async fn on_message(&self, request: &Message) -> Option<MessageBody> {
+        match self.adds.on_message(request).await {
+            Some(ack) => Some(ack),
+            None => self.removes.on_message(request).await,
+        }
+    }
+
+    // gossips adds and removes, runs until the process exits
+    pub async fn run(self: Arc<Self>, maelstrom: Maelstrom) {
+        let adds = self.adds.clone().run(maelstrom.clone());
+        let removes = self.removes.clone().run(maelstrom);
+        tokio::join!(adds, removes);
+    }
+
+    pub async fn flush(&self, maelstrom: &Maelstrom) -> io::Result<()> {
+        self.adds.flush(maelstrom).await?;
+        self.removes.flush(maelstrom).await
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::message::{Message, MessageBody, NodeId};
+
+// Per destination FIFO channels on top of unreliable sends. Every message is
+// tagged with the next sequence number of its destination and kept until the
+// receiver acknowledges it; the receiver hands messages to the app in sequence
+// order and acknowledges cumulatively, so resending the unacknowledged tail
+// fills any hole.
+#[derive(Default)]
+pub struct OrderedQueues {
+    outgoing: Mutex<HashMap<NodeId, Outgoing>>,
+    incoming: Mutex<HashMap<NodeId, Incoming>>,
+    // handler of the latest delivered message per sender, the next one waits for it
+    handlers: Mutex<HashMap<NodeId, JoinHandle<()>>>,
+}
+
+#[derive(Default)]
+struct Outgoing {
+    last_seq: u64,
+    unacked: BTreeMap<u64, (MessageBody, Instant)>,
+}
+
+#[derive(Default)]
+struct Incoming {
+    delivered: u64,
+    // arrived ahead of a missing predecessor
+    buffered: BTreeMap<u64, Message>,
+}
+
+impl OrderedQueues {
+    // tags the body with the next sequence number for `dest` and tracks it until acknowledged
+    pub fn push(&self, dest: &str, mut body: MessageBody, now: Instant) -> MessageBody {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        let queue = outgoing.entry(dest.into()).or_default();
+        queue.last_seq += 1;
+        body.seq = Some(queue.last_seq);
+        queue.unacked.insert(queue.last_seq, (body.to_owned(), now));
+        body
+    }
+
+    // `seq` and everything before it arrived at `src`
+    pub fn ack(&self, src: &str, seq: u64) {
+        if let Some(queue) = self.outgoing.lock().unwrap().get_mut(src) {
+            queue.unacked = queue.unacked.split_off(&(seq + 1));
+        }
+    }
+
+    // unacknowledged messages last sent more than `interval` ago
+    pub fn due(&self, interval: Duration, now: Instant) -> Vec<(NodeId, MessageBody)> {
+        let mut due = vec![];
+        for (dest, queue) in self.outgoing.lock().unwrap().iter_mut() {
+            for (body, sent_at) in queue.unacked.values_mut() {
+                if now.duration_since(*sent_at) >= interval {
+                    *sent_at = now;
+                    due.push((dest.to_owned(), body.to_owned()));
+                }
+            }
+        }
+        due
+    }
+
+    // messages to `dest` not acknowledged yet
+    pub fn backlog(&self, dest: &str) -> usize {
+        self.outgoing
+            .lock()
+            .unwrap()
+            .get(dest)
+            .map_or(0, |queue| queue.unacked.len())
+    }
+
+    pub fn total_backlog(&self) -> usize {
+        self.outgoing
+            .lock()
+            .unwrap()
+            .values()
+            .map(|queue| queue.unacked.len())
+            .sum()
+    }
+
+    // Accepts a tagged message and returns the cumulative ack for its sender
+    // along with the messages that are now deliverable, in order. Duplicates
+    // are dropped.
+    pub fn receive(&self, message: Message, seq: u64) -> (u64, Vec<Message>) {
+        let mut incoming = self.incoming.lock().unwrap();
+        let queue = incoming.entry(message.src.to_owned()).or_default();
+        if seq > queue.delivered {
+            queue.buffered.insert(seq, message);
+        }
+
+        let mut ready = vec![];
+        while let Some(message) = queue.buffered.remove(&(queue.delivered + 1)) {
+            queue.delivered += 1;
+            ready.push(message);
+        }
+        (queue.delivered, ready)
+    }
+
+    // Starts the handler of the next message from `src`, given the handler of the
+    // previous one to wait for, so the app processes them in order as well.
+    pub fn chain_handler(
+        &self,
+        src: &str,
+        start: impl FnOnce(Option<JoinHandle<()>>) -> Option<JoinHandle<()>>,
+    ) {
+        let mut handlers = self.handlers.lock().unwrap();
+        let previous = handlers.remove(src);
+        if let Some(handler) = start(previous) {
+            handlers.insert(src.into(), handler);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::message::{MessageBody, NodeId};
+
+struct Entry {
+    dest: NodeId,
+    body: MessageBody,
+    sent_at: Instant,
+}
+
+// Peer messages that were sent but not acknowledged yet. Unlike a retrying
+// rpc task the entries outlive the handler that created them and are resent
+// by a single background task until the peer replies.
+#[derive(Default)]
+pub struct Outbox {
+    // keyed by msg_id, so resends go out in the original order
+    entries: Mutex<BTreeMap<u64, Entry>>,
+}
+
+impl Outbox {
+    // `body` must carry its msg_id already, `now` is the runtime's clock
+    pub fn insert(&self, msg_id: u64, dest: NodeId, body: MessageBody, now: Instant) {
+        let entry = Entry {
+            dest,
+            body,
+            sent_at: now,
+        };
+        self.entries.lock().unwrap().insert(msg_id, entry);
+    }
+
+    // true when the reply acknowledged an outbox entry
+    pub fn ack(&self, in_reply_to: u64) -> bool {
+        self.entries.lock().unwrap().remove(&in_reply_to).is_some()
+    }
+
+    // entries not acknowledged within `interval` of their last send, marked as resent
+    pub fn due(&self, interval: Duration, now: Instant) -> Vec<(NodeId, MessageBody)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|entry| now.duration_since(entry.sent_at) >= interval)
+            .map(|entry| {
+                entry.sent_at = now;
+                (entry.dest.to_owned(), entry.body.to_owned())
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
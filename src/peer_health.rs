@@ -0,0 +1,75 @@
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use tokio::sync::broadcast;
+
+use crate::failure_detector::PeerEvent;
+
+// timeouts in a row after which a destination counts as unhealthy
+pub const UNHEALTHY_AFTER: u32 = 3;
+
+// capacity of the event channel, slow subscribers miss older events
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Default)]
+pub struct PeerHealth {
+    // rpc attempts that went unanswered since the last reply
+    pub consecutive_timeouts: u32,
+    pub last_success: Option<Instant>,
+    pub last_timeout: Option<Instant>,
+}
+
+impl PeerHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_timeouts < UNHEALTHY_AFTER
+    }
+}
+
+// Passive health of rpc destinations, learned from the outcome of rpcs
+// without sending anything extra. A destination turns unhealthy after
+// `UNHEALTHY_AFTER` timeouts in a row and healthy again with its next reply,
+// both announced as peer events.
+pub struct HealthTracker {
+    peers: Mutex<HashMap<String, PeerHealth>>,
+    events: broadcast::Sender<PeerEvent>,
+}
+
+impl Default for HealthTracker {
+    fn default() -> Self {
+        Self {
+            peers: Default::default(),
+            events: broadcast::Sender::new(EVENT_CAPACITY),
+        }
+    }
+}
+
+impl HealthTracker {
+    pub fn success(&self, dest: &str, now: Instant) {
+        let mut peers = self.peers.lock().unwrap();
+        let health = peers.entry(dest.to_owned()).or_default();
+        let recovered = !health.is_healthy();
+        health.consecutive_timeouts = 0;
+        health.last_success = Some(now);
+        if recovered {
+            let _ = self.events.send(PeerEvent::Up(dest.to_owned()));
+        }
+    }
+
+    pub fn timeout(&self, dest: &str, now: Instant) {
+        let mut peers = self.peers.lock().unwrap();
+        let health = peers.entry(dest.to_owned()).or_default();
+        health.consecutive_timeouts += 1;
+        health.last_timeout = Some(now);
+        if health.consecutive_timeouts == UNHEALTHY_AFTER {
+            let _ = self.events.send(PeerEvent::Down(dest.to_owned()));
+        }
+    }
+
+    // None for destinations no rpc was made to yet
+    pub fn get(&self, dest: &str) -> Option<PeerHealth> {
+        self.peers.lock().unwrap().get(dest).cloned()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::Notify, time::sleep};
+
+// Replies and errors are written ahead of queued background traffic like
+// gossip, so client latency doesn't suffer from bursts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    // sustained messages per second
+    pub per_second: f64,
+    // messages that may be sent back to back before the rate applies
+    pub burst: u32,
+}
+
+impl RateLimit {
+    // a rate of zero would never refill, NaN fails this as well
+    pub fn is_valid(&self) -> bool {
+        self.per_second > 0.0
+    }
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst.max(1) as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst.max(1) as f64);
+        self.refilled_at = now;
+    }
+
+    // time until a token is available, zero if one is available now
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.limit.per_second)
+        }
+    }
+}
+
+#[derive(Default)]
+struct LimiterState {
+    global: Option<TokenBucket>,
+    buckets: HashMap<String, TokenBucket>,
+    // ordered by priority first, so draining visits every high queue before the low ones
+    queues: BTreeMap<(Priority, String), VecDeque<String>>,
+}
+
+// Token bucket limiter for outbound messages, applied globally and per destination.
+// Messages that exceed the limit are queued per destination and priority and
+// drained in order, high priority first.
+pub struct RateLimiter {
+    dest_limit: Option<RateLimit>,
+    dest_limits: HashMap<String, RateLimit>,
+    state: Mutex<LimiterState>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl RateLimiter {
+    pub fn new(
+        global: Option<RateLimit>,
+        dest_limit: Option<RateLimit>,
+        dest_limits: HashMap<String, RateLimit>,
+    ) -> Option<Self> {
+        if global.is_none() && dest_limit.is_none() && dest_limits.is_empty() {
+            return None;
+        }
+        // caught here at startup rather than as a panic on the first wait
+        for limit in global.iter().chain(&dest_limit).chain(dest_limits.values()) {
+            assert!(
+                limit.is_valid(),
+                "rate limit of {} per second must be positive",
+                limit.per_second
+            );
+        }
+
+        Some(Self {
+            dest_limit,
+            dest_limits,
+            state: Mutex::new(LimiterState {
+                global: global.map(TokenBucket::new),
+                ..Default::default()
+            }),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    // returns the line back if it may be written right away, otherwise queues it
+    pub fn submit(&self, dest: &str, priority: Priority, line: String) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let key = (priority, dest.to_owned());
+        let queued = state
+            .queues
+            .get(&key)
+            .is_some_and(|queue| !queue.is_empty());
+
+        if !queued && self.try_acquire(&mut state, dest, Instant::now()).is_none() {
+            return Some(line);
+        }
+
+        state.queues.entry(key).or_default().push_back(line);
+        drop(state);
+        self.notify.notify_one();
+        None
+    }
+
+    // takes a token from the global and destination buckets, or returns how long to wait
+    fn try_acquire(&self, state: &mut LimiterState, dest: &str, now: Instant) -> Option<Duration> {
+        let limit = self.dest_limits.get(dest).or(self.dest_limit.as_ref());
+        if let Some(limit) = limit {
+            state
+                .buckets
+                .entry(dest.to_owned())
+                .or_insert_with(|| TokenBucket::new(*limit));
+        }
+
+        let mut wait = Duration::ZERO;
+        if let Some(global) = state.global.as_mut() {
+            global.refill(now);
+            wait = wait.max(global.wait_time());
+        }
+        if let Some(bucket) = state.buckets.get_mut(dest) {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time());
+        }
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+
+        if let Some(global) = state.global.as_mut() {
+            global.tokens -= 1.0;
+        }
+        if let Some(bucket) = state.buckets.get_mut(dest) {
+            bucket.tokens -= 1.0;
+        }
+        None
+    }
+
+    pub fn backlog(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.queues.values().map(|queue| queue.len()).sum()
+    }
+
+    // drains queued messages as tokens become available, returns once closed and empty
+    pub async fn run(&self, write: impl Fn(Priority, String)) {
+        loop {
+            let notified = self.notify.notified();
+
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let keys: Vec<(Priority, String)> = state.queues.keys().cloned().collect();
+                let mut next_wait: Option<Duration> = None;
+
+                for key in keys {
+                    while state.queues.get(&key).is_some_and(|q| !q.is_empty()) {
+                        match self.try_acquire(&mut state, &key.1, now) {
+                            None => {
+                                let line = state.queues.get_mut(&key).unwrap().pop_front();
+                                write(key.0, line.unwrap());
+                            }
+                            Some(wait) => {
+                                next_wait = Some(next_wait.map_or(wait, |w| w.min(wait)));
+                                break;
+                            }
+                        }
+                    }
+                }
+                state.queues.retain(|_, queue| !queue.is_empty());
+                next_wait
+            };
+
+            match wait {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = sleep(wait) => {},
+                        _ = notified => {},
+                    }
+                }
+                None if self.closed.load(Ordering::Relaxed) => return,
+                None => notified.await,
+            }
+        }
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+#[cfg(feature = "broadcast")]
+use std::collections::HashSet;
+
+#[cfg(feature = "kafka")]
+use serde_json::Value as Json;
+
+#[cfg(feature = "counter")]
+use crate::message::AddRequest;
+#[cfg(feature = "broadcast")]
+use crate::message::Payload;
+#[cfg(feature = "txn")]
+use crate::message::Transaction;
+#[cfg(feature = "kafka")]
+use crate::message::{LogKey, Offset};
+use crate::message::{MessageType, NodeId, ReadRequest};
+
+// A request payload that can be split off `MessageType`, so handlers receive
+// the destructured fields of the message they handle.
+pub trait Request: Sized {
+    fn matches(msg_type: &MessageType) -> bool;
+
+    // None when the message is of a different type
+    fn from_type(msg_type: MessageType) -> Option<Self>;
+}
+
+macro_rules! requests {
+    ($($(#[$meta:meta])* $name:ident { $($field:ident: $ty:ty),* }),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone)]
+            pub struct $name {
+                $(pub $field: $ty),*
+            }
+
+            $(#[$meta])*
+            impl Request for $name {
+                fn matches(msg_type: &MessageType) -> bool {
+                    matches!(msg_type, MessageType::$name { .. })
+                }
+
+                fn from_type(msg_type: MessageType) -> Option<Self> {
+                    match msg_type {
+                        MessageType::$name { $($field),* } => Some(Self { $($field),* }),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+requests! {
+    #[cfg(feature = "echo")]
+    Echo { echo: String },
+    #[cfg(feature = "unique-ids")]
+    Generate {},
+    #[cfg(feature = "broadcast")]
+    Broadcast { message: Payload },
+    #[cfg(feature = "broadcast")]
+    BroadcastMany { messages: HashSet<Payload> },
+    Topology { topology: HashMap<NodeId, Vec<NodeId>> },
+    #[cfg(feature = "set")]
+    Remove { element: i64 },
+    #[cfg(feature = "kafka")]
+    Send { key: LogKey, msg: Json },
+    #[cfg(feature = "kafka")]
+    Poll { offsets: HashMap<LogKey, Offset> },
+    #[cfg(feature = "kafka")]
+    CommitOffsets { offsets: HashMap<LogKey, Offset> },
+    #[cfg(feature = "kafka")]
+    ListCommittedOffsets { keys: Vec<LogKey> },
+    #[cfg(feature = "txn")]
+    Txn { txn: Vec<Transaction> },
+    StateChecksum { checksum: u64 },
+}
+
+// client read of the broadcast and counter workloads, kv reads are only sent by nodes
+#[derive(Debug, Clone)]
+pub struct Read;
+
+impl Request for Read {
+    fn matches(msg_type: &MessageType) -> bool {
+        matches!(msg_type, MessageType::Read(ReadRequest::Client))
+    }
+
+    fn from_type(msg_type: MessageType) -> Option<Self> {
+        Self::matches(&msg_type).then_some(Read)
+    }
+}
+
+// add of the counter workload, set adds carry an element instead
+#[cfg(feature = "counter")]
+#[derive(Debug, Clone)]
+pub struct Add {
+    pub delta: i64,
+}
+
+#[cfg(feature = "counter")]
+impl Request for Add {
+    fn matches(msg_type: &MessageType) -> bool {
+        matches!(msg_type, MessageType::Add(AddRequest::Delta { .. }))
+    }
+
+    fn from_type(msg_type: MessageType) -> Option<Self> {
+        match msg_type {
+            MessageType::Add(AddRequest::Delta { delta }) => Some(Add { delta }),
+            _ => None,
+        }
+    }
+}
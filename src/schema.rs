@@ -0,0 +1,231 @@
+use std::{fmt, sync::OnceLock};
+
+use serde_json::{Map, Value as Json};
+
+use crate::message::{Message, MessageType};
+
+// Fields of a body type, each with a value of the kind it takes written as
+// JSON. Optional fields are listed too, so they are checked when present.
+type Shape = (&'static str, &'static [(&'static str, &'static str)]);
+
+// Every type `MessageType` parses, grouped by the cargo feature that enables
+// it. A type listed twice takes either set of fields, e.g. the `add` of the
+// counter and set workloads. Extend these lists whenever a variant is added.
+const CORE: &[Shape] = &[
+    ("init", &[("node_id", r#""n1""#), ("node_ids", r#"["n1"]"#)]),
+    ("init_ok", &[]),
+    ("error", &[("code", "30"), ("text", r#""x""#)]),
+    ("read", &[("key", r#""k""#)]),
+    ("read_ok", &[("value", "1")]),
+    ("topology", &[("topology", r#"{"n1":["n2"]}"#)]),
+    ("topology_ok", &[]),
+    (
+        "cas",
+        &[
+            ("key", r#""k""#),
+            ("from", "1"),
+            ("to", "2"),
+            ("create_if_not_exists", "true"),
+        ],
+    ),
+    ("cas_ok", &[]),
+    ("write", &[("key", r#""k""#), ("value", "1")]),
+    ("write_ok", &[]),
+    ("ts", &[]),
+    ("ts_ok", &[("ts", "1")]),
+    ("state_checksum", &[("checksum", "1")]),
+    ("capabilities", &[("encodings", r#"["gzip"]"#)]),
+    ("heartbeat", &[]),
+    (
+        "compressed",
+        &[("encoding", r#""gzip""#), ("payload", r#""x""#)],
+    ),
+    ("ordered_ack", &[("upto", "1")]),
+];
+
+#[cfg(feature = "echo")]
+const ECHO: &[Shape] = &[
+    ("echo", &[("echo", r#""x""#)]),
+    ("echo_ok", &[("echo", r#""x""#)]),
+];
+
+#[cfg(feature = "unique-ids")]
+const UNIQUE_IDS: &[Shape] = &[("generate", &[]), ("generate_ok", &[("id", r#""x""#)])];
+
+#[cfg(feature = "broadcast")]
+const BROADCAST: &[Shape] = &[
+    ("broadcast", &[("message", "1")]),
+    ("broadcast_ok", &[]),
+    ("broadcast_many", &[("messages", "[1]")]),
+    ("broadcast_many_ok", &[]),
+    ("read_ok", &[("messages", "[1]")]),
+];
+
+#[cfg(feature = "counter")]
+const COUNTER: &[Shape] = &[("add", &[("delta", "1")]), ("add_ok", &[])];
+
+#[cfg(feature = "set")]
+const SET: &[Shape] = &[
+    ("add", &[("element", "1")]),
+    ("add_ok", &[]),
+    ("remove", &[("element", "1")]),
+    ("remove_ok", &[]),
+];
+
+#[cfg(feature = "kafka")]
+const KAFKA: &[Shape] = &[
+    ("send", &[("key", r#""k""#), ("msg", "1")]),
+    ("send_ok", &[("offset", "0")]),
+    ("poll", &[("offsets", r#"{"k":0}"#)]),
+    ("poll_ok", &[("msgs", r#"{"k":[[0,1]]}"#)]),
+    ("commit_offsets", &[("offsets", r#"{"k":0}"#)]),
+    ("commit_offsets_ok", &[]),
+    ("list_committed_offsets", &[("keys", r#"["k"]"#)]),
+    ("list_committed_offsets_ok", &[("offsets", r#"{"k":0}"#)]),
+];
+
+#[cfg(feature = "txn")]
+const TXN: &[Shape] = &[
+    ("txn", &[("txn", r#"[["r",1,null]]"#)]),
+    ("txn_ok", &[("txn", r#"[["r",1,null]]"#)]),
+];
+
+// shapes of every message type compiled in
+fn shapes() -> &'static [Shape] {
+    static SHAPES: OnceLock<Vec<Shape>> = OnceLock::new();
+    SHAPES.get_or_init(|| {
+        // stays as is without workload features
+        #[allow(unused_mut)]
+        let mut shapes = CORE.to_vec();
+        #[cfg(feature = "echo")]
+        shapes.extend(ECHO);
+        #[cfg(feature = "unique-ids")]
+        shapes.extend(UNIQUE_IDS);
+        #[cfg(feature = "broadcast")]
+        shapes.extend(BROADCAST);
+        #[cfg(feature = "counter")]
+        shapes.extend(COUNTER);
+        #[cfg(feature = "set")]
+        shapes.extend(SET);
+        #[cfg(feature = "kafka")]
+        shapes.extend(KAFKA);
+        #[cfg(feature = "txn")]
+        shapes.extend(TXN);
+        shapes
+    })
+}
+
+// whether `name` is the type of one of the variants of `MessageType`
+pub fn is_known(name: &str) -> bool {
+    shapes().iter().any(|(known, _)| *known == name)
+}
+
+// The field that keeps a body of a known type from parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldError {
+    Missing {
+        field: String,
+    },
+    Invalid {
+        field: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldError::Missing { field } => write!(f, "missing field `{field}`"),
+            // right kind, wrong contents, e.g. an array of the wrong elements
+            FieldError::Invalid {
+                field,
+                expected,
+                found,
+            } if expected == found => {
+                write!(
+                    f,
+                    "invalid field `{field}`: {found} with unexpected contents"
+                )
+            }
+            FieldError::Invalid {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "invalid field `{field}`: expected {expected}, found {found}"
+            ),
+        }
+    }
+}
+
+fn kind(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(_) => "a bool",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+// A valid body of type `name` with every field of the shape closest to
+// `body`, the one it misses the fewest fields of.
+fn template(name: &str, body: &Json) -> Option<Map<String, Json>> {
+    let (_, fields) = shapes()
+        .iter()
+        .filter(|(known, _)| *known == name)
+        .min_by_key(|(_, fields)| {
+            fields
+                .iter()
+                .filter(|(field, _)| body.get(field).is_none())
+                .count()
+        })?;
+    let mut template = Map::from_iter([("type".to_owned(), Json::from(name))]);
+    for (field, value) in fields.iter() {
+        // the table is written by hand, a typo in it is a bug caught by any check
+        let value = serde_json::from_str(value).expect("field value in the schema table");
+        template.insert((*field).to_owned(), value);
+    }
+    Some(template)
+}
+
+fn parses(body: Map<String, Json>) -> bool {
+    let message = serde_json::json!({ "src": "c0", "dest": "n0", "body": body });
+    serde_json::from_value::<Message>(message)
+        .is_ok_and(|message| !matches!(message.body.msg_type, MessageType::Other { .. }))
+}
+
+// Checks the fields of `body` one at a time against a template of its type:
+// each is swapped into the sample, or removed from it when absent, and the
+// first one that breaks parsing is reported. None when the type is unknown or
+// no single field is at fault.
+pub fn check(body: &Json) -> Option<FieldError> {
+    let name = body.get("type")?.as_str()?;
+    let template = template(name, body)?;
+
+    for (field, sample) in template.iter().filter(|(field, _)| *field != "type") {
+        let mut probe = template.clone();
+        match body.get(field) {
+            Some(value) => probe.insert(field.to_owned(), value.to_owned()),
+            None => probe.remove(field),
+        };
+        if parses(probe) {
+            continue;
+        }
+
+        return Some(match body.get(field) {
+            Some(value) => FieldError::Invalid {
+                field: field.to_owned(),
+                expected: kind(sample),
+                found: kind(value),
+            },
+            None => FieldError::Missing {
+                field: field.to_owned(),
+            },
+        });
+    }
+    None
+}
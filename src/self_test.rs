@@ -0,0 +1,163 @@
+use std::io;
+
+use serde_json::Value as Json;
+
+use crate::message::{Message, MessageType};
+
+// One canonical sample per message type, as sent by Maelstrom or its services,
+// grouped by the cargo feature that enables the type. Extend these lists
+// whenever a variant is added to `MessageType`.
+const CORE: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":-3}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1.5}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":1,"code":30,"text":"txn conflict"}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":1}}"#,
+    r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":1,"key":"root"}}"#,
+    r#"{"src":"lin-kv","dest":"n1","body":{"type":"read_ok","in_reply_to":1,"value":[1,2]}}"#,
+    r#"{"src":"lin-kv","dest":"n1","body":{"type":"read_ok","in_reply_to":2,"value":{"a":[1,"x",true,1.5,null],"b":{"c":-2}}}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"]}}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"topology_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"lin-kv","body":{"type":"cas","msg_id":1,"key":"lock","from":null,"to":"n1","create_if_not_exists":true}}"#,
+    r#"{"src":"lin-kv","dest":"n1","body":{"type":"cas_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"seq-kv","body":{"type":"write","msg_id":1,"key":"n1","value":5}}"#,
+    r#"{"src":"seq-kv","dest":"n1","body":{"type":"write_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"lin-tso","body":{"type":"ts","msg_id":1}}"#,
+    r#"{"src":"lin-tso","dest":"n1","body":{"type":"ts_ok","in_reply_to":1,"ts":42}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"state_checksum","checksum":12345}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"capabilities","encodings":["gzip"]}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"heartbeat"}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"compressed","encoding":"gzip","payload":"H4sIAAAAAAACA6tWKqksSFWyUkoqyk9MSU4sLonPTcyrVNJRyi1Oj89MUbIyBDJTi4sT01OLlayiTYxiawE4PoSKNAAAAA=="}}"#,
+    r#"{"src":"n2","dest":"n1","body":{"type":"ordered_ack","upto":1}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"custom_nemesis","msg_id":1,"targets":["n2"],"delay":1.5}}"#,
+];
+
+#[cfg(feature = "echo")]
+const ECHO: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"echo","msg_id":1,"echo":"hello"}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"echo_ok","msg_id":1,"in_reply_to":1,"echo":"hello"}}"#,
+];
+
+#[cfg(feature = "unique-ids")]
+const UNIQUE_IDS: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":1}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"generate_ok","in_reply_to":1,"id":"n1-0"}}"#,
+];
+
+#[cfg(feature = "broadcast")]
+const BROADCAST: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","msg_id":1,"message":42}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","msg_id":2,"message":{"a":"x","b":[1,null]}}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"broadcast_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","msg_id":1,"messages":[42]}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","msg_id":2,"lamport":7,"messages":[42]}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","msg_id":3,"version":{"n1":2,"n2":1},"messages":[42]}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","msg_id":4,"trace_id":"n1-0","messages":[42]}}"#,
+    r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_many","seq":1,"messages":[42]}}"#,
+    r#"{"src":"n2","dest":"n1","body":{"type":"broadcast_many_ok","in_reply_to":1}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"read_ok","in_reply_to":1,"messages":[42]}}"#,
+];
+
+#[cfg(feature = "counter")]
+const COUNTER: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"add","msg_id":1,"delta":5}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"add_ok","in_reply_to":1}}"#,
+];
+
+#[cfg(feature = "set")]
+const SET: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"add","msg_id":1,"element":5}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"add_ok","in_reply_to":1}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"remove","msg_id":2,"element":5}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"remove_ok","in_reply_to":2}}"#,
+];
+
+#[cfg(feature = "kafka")]
+const KAFKA: &[&str] = &[
+    r#"{"src":"n1","dest":"c1","body":{"type":"poll_ok","msg_id":5,"in_reply_to":1,"prev":4,"more":true,"msgs":{"k1":[[0,123]]}}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"send","msg_id":1,"key":"k1","msg":123}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"send_ok","in_reply_to":1,"offset":0}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"poll","msg_id":1,"offsets":{"k1":0}}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"poll_ok","in_reply_to":1,"msgs":{"k1":[[0,123]]}}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"send","msg_id":2,"key":"k2","msg":{"a":["x",1.5,null]}}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"poll_ok","in_reply_to":2,"msgs":{"k2":[[0,{"a":["x",1.5,null]}],[1,"y"]]}}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"commit_offsets","msg_id":1,"offsets":{"k1":0}}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"commit_offsets_ok","in_reply_to":1}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"list_committed_offsets","msg_id":1,"keys":["k1"]}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"list_committed_offsets_ok","in_reply_to":1,"offsets":{"k1":0}}}"#,
+];
+
+#[cfg(feature = "txn")]
+const TXN: &[&str] = &[
+    r#"{"src":"c1","dest":"n1","body":{"type":"txn","msg_id":1,"txn":[["r",1,null],["w",1,6],["append",2,3]]}}"#,
+    r#"{"src":"n1","dest":"c1","body":{"type":"txn_ok","in_reply_to":1,"txn":[["r",1,[3]],["w",1,6]]}}"#,
+    r#"{"src":"c1","dest":"n1","body":{"type":"txn","msg_id":2,"txn":[["r","x",null],["w","y",6]]}}"#,
+];
+
+// samples of every message type compiled in
+pub fn samples() -> Vec<&'static str> {
+    // stays as is without workload features
+    #[allow(unused_mut)]
+    let mut samples = CORE.to_vec();
+    #[cfg(feature = "echo")]
+    samples.extend(ECHO);
+    #[cfg(feature = "unique-ids")]
+    samples.extend(UNIQUE_IDS);
+    #[cfg(feature = "broadcast")]
+    samples.extend(BROADCAST);
+    #[cfg(feature = "counter")]
+    samples.extend(COUNTER);
+    #[cfg(feature = "set")]
+    samples.extend(SET);
+    #[cfg(feature = "kafka")]
+    samples.extend(KAFKA);
+    #[cfg(feature = "txn")]
+    samples.extend(TXN);
+    samples
+}
+
+// null fields are equivalent to absent ones on the wire
+fn normalize(json: Json) -> Json {
+    match json {
+        Json::Object(map) => Json::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        Json::Array(values) => Json::Array(values.into_iter().map(normalize).collect()),
+        json => json,
+    }
+}
+
+// Checks that every sample deserializes and serializes back to the same JSON,
+// so a serde change that breaks wire compatibility fails at startup.
+pub fn self_test() -> io::Result<()> {
+    for sample in samples() {
+        let message = serde_json::from_str::<Message>(sample).map_err(|e| {
+            io::Error::other(format!(
+                "serde self-test: failed to deserialize {sample}: {e}"
+            ))
+        })?;
+
+        // the schema table names every type the samples cover
+        let name = message.body.msg_type.name();
+        if !matches!(message.body.msg_type, MessageType::Other { .. })
+            && !MessageType::is_known(&name)
+        {
+            return Err(io::Error::other(format!(
+                "serde self-test: type {name} is missing from the schema"
+            )));
+        }
+
+        let expected = normalize(serde_json::from_str::<Json>(sample)?);
+        let actual = normalize(serde_json::to_value(&message)?);
+        if expected != actual {
+            return Err(io::Error::other(format!(
+                "serde self-test: {sample} re-serialized as {actual}"
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::{yield_now, JoinHandle},
+};
+
+use crate::{
+    canonical,
+    clock::{Clock, SystemClock, VirtualClock},
+    maelstrom::{App, Maelstrom},
+    message::{
+        Envelope, KvKey, Message, MessageBody, MessageType, MsgId, NodeId, ReadRequest, Value,
+    },
+    transport::ChannelTransport,
+};
+
+// services answered by the simulator itself, all linearizable here
+const KV_SERVICES: &[&str] = &["lin-kv", "seq-kv", "lww-kv", "lin-tso"];
+// scheduler passes that let every message in flight be handled before time moves on
+const SETTLE_ROUNDS: usize = 64;
+
+// Faults applied to every message between two nodes. Client and service
+// traffic is always delivered.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConfig {
+    pub latency: Duration,
+    // extra random delay of up to this much, which also reorders messages
+    pub jitter: Duration,
+    // probability in [0, 1] that a message is dropped
+    pub loss: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    pub link: LinkConfig,
+    // seed of the fault injection, runs with the same seed drop the same messages
+    pub seed: u64,
+    // how long a client waits for a reply
+    pub client_timeout: Duration,
+    // Time of the nodes, links and clients, which then only passes through
+    // `fast_forward`. None runs on tokio time.
+    pub clock: Option<Arc<VirtualClock>>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            nodes: 3,
+            link: Default::default(),
+            seed: 0,
+            client_timeout: Duration::from_secs(5),
+            clock: None,
+        }
+    }
+}
+
+// splitmix64, good enough to decide which messages to drop or delay
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct SimState {
+    node_ids: Vec<NodeId>,
+    inboxes: Mutex<HashMap<String, mpsc::UnboundedSender<String>>>,
+    link: Mutex<LinkConfig>,
+    // directed (src, dest) pairs that currently can't talk
+    blocked: Mutex<HashSet<(String, String)>>,
+    rng: Mutex<Rng>,
+    kv: Mutex<HashMap<NodeId, HashMap<KvKey, Value>>>,
+    // last timestamp handed out by lin-tso
+    ts: AtomicU64,
+    // replies awaited by clients, keyed by client id and msg_id
+    pending: Mutex<HashMap<(NodeId, u64), oneshot::Sender<MessageBody>>>,
+    client_timeout: Duration,
+    next_client: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+// Runs a cluster of nodes in the current process. Nodes talk through
+// in-memory links with configurable latency, loss and partitions, and the kv
+// services are simulated, so workloads run inside `cargo test` without
+// Maelstrom.
+pub struct Simulator {
+    state: Arc<SimState>,
+    nodes: Vec<JoinHandle<io::Result<()>>>,
+    runtimes: Vec<Maelstrom>,
+    virtual_clock: Option<Arc<VirtualClock>>,
+}
+
+impl Simulator {
+    // Starts the nodes, each with the app `app` builds for it, and initializes
+    // them. Background tasks of the app are spawned on the node's runtime
+    // passed in, like a binary does before `run_with_app`.
+    pub async fn start<F>(config: SimConfig, app: F) -> io::Result<Self>
+    where
+        F: Fn(&Maelstrom) -> Arc<dyn App>,
+    {
+        let clock: Arc<dyn Clock> = match &config.clock {
+            Some(clock) => clock.clone(),
+            None => Arc::new(SystemClock),
+        };
+        let node_ids: Vec<NodeId> = (1..=config.nodes)
+            .map(|i| NodeId(format!("n{i}")))
+            .collect();
+        let state = Arc::new(SimState {
+            node_ids: node_ids.to_owned(),
+            inboxes: Default::default(),
+            link: Mutex::new(config.link),
+            blocked: Default::default(),
+            rng: Mutex::new(Rng(config.seed)),
+            kv: Default::default(),
+            ts: AtomicU64::new(0),
+            pending: Default::default(),
+            client_timeout: config.client_timeout,
+            next_client: AtomicU64::new(0),
+            clock: clock.clone(),
+        });
+
+        let mut nodes = Vec::with_capacity(node_ids.len());
+        let mut runtimes = Vec::with_capacity(node_ids.len());
+        for node_id in &node_ids {
+            let (transport, handle) = ChannelTransport::new();
+            state
+                .inboxes
+                .lock()
+                .unwrap()
+                .insert(node_id.to_string(), handle.inbound);
+
+            let maelstrom = Maelstrom::builder()
+                .log_messages(false)
+                .transport(Arc::new(transport))
+                .clock(clock.clone())
+                .build();
+            let app = app(&maelstrom);
+            runtimes.push(maelstrom.clone());
+            nodes.push(tokio::spawn(
+                async move { maelstrom.run_with_app(app).await },
+            ));
+            tokio::spawn(route(state.clone(), handle.outbound));
+        }
+
+        let simulator = Self {
+            state,
+            nodes,
+            runtimes,
+            virtual_clock: config.clock,
+        };
+        let client = simulator.client();
+        for node_id in &node_ids {
+            client
+                .rpc(
+                    node_id,
+                    MessageType::Init {
+                        node_id: node_id.to_owned(),
+                        node_ids: node_ids.to_owned(),
+                    },
+                )
+                .await?;
+        }
+        Ok(simulator)
+    }
+
+    pub fn node_ids(&self) -> &[NodeId] {
+        &self.state.node_ids
+    }
+
+    // the runtime of a node, e.g. to drive kv clients or rpcs from it in tests
+    pub fn node(&self, node_id: &str) -> &Maelstrom {
+        let index = self.node_ids().iter().position(|id| id == node_id);
+        &self.runtimes[index.expect("unknown node")]
+    }
+
+    // a new client with its own id, like the c1, c2, ... of a maelstrom run
+    pub fn client(&self) -> SimClient {
+        let id = self.state.next_client.fetch_add(1, Ordering::Relaxed);
+        SimClient {
+            id: NodeId(format!("c{id}")),
+            state: self.state.clone(),
+            next_msg_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn set_link(&self, link: LinkConfig) {
+        *self.state.link.lock().unwrap() = link;
+    }
+
+    // cuts every link between nodes of different groups, nodes not listed
+    // keep talking to everyone
+    pub fn partition(&self, groups: &[&[&str]]) {
+        let mut blocked = self.state.blocked.lock().unwrap();
+        for (i, group) in groups.iter().enumerate() {
+            for other in &groups[i + 1..] {
+                for a in group.iter() {
+                    for b in other.iter() {
+                        blocked.insert((a.to_string(), b.to_string()));
+                        blocked.insert((b.to_string(), a.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn heal(&self) {
+        self.state.blocked.lock().unwrap().clear();
+    }
+
+    // Moves virtual time forward by `horizon`, from one pending sleep to the
+    // next, and lets every node handle what is in flight before each jump. So
+    // hours of timers, retries and gossip rounds pass in as long as their work
+    // takes. Only for a simulator started with a `SimConfig::clock`, under a
+    // current thread runtime such as the default of `#[tokio::test]`.
+    pub async fn fast_forward(&self, horizon: Duration) -> io::Result<()> {
+        let Some(clock) = &self.virtual_clock else {
+            return Err(io::Error::other("fast forward needs a virtual clock"));
+        };
+        let end = clock.now() + horizon;
+        loop {
+            settle().await;
+            let now = clock.now();
+            match clock.next_wake() {
+                Some(wake) if wake <= end => clock.advance(wake.saturating_duration_since(now)),
+                _ => {
+                    clock.advance(end.saturating_duration_since(now));
+                    break;
+                }
+            }
+        }
+        settle().await;
+        Ok(())
+    }
+
+    // Closes the input of every node and waits for them to shut down. Under
+    // virtual time it keeps passing, tasks a node waits for at shutdown such
+    // as rpcs to unreachable peers only time out as it does.
+    pub async fn shutdown(self) -> io::Result<()> {
+        self.state.inboxes.lock().unwrap().clear();
+        for mut node in self.nodes {
+            let result = match &self.virtual_clock {
+                Some(clock) => loop {
+                    tokio::select! {
+                        result = &mut node => break result,
+                        _ = settle() => advance_to_next_wake(clock),
+                    }
+                },
+                None => node.await,
+            };
+            result.map_err(io::Error::other)??;
+        }
+        Ok(())
+    }
+}
+
+// A simulated client, sends requests to nodes and waits for their replies.
+pub struct SimClient {
+    id: NodeId,
+    state: Arc<SimState>,
+    next_msg_id: AtomicU64,
+}
+
+impl SimClient {
+    pub fn id(&self) -> &NodeId {
+        &self.id
+    }
+
+    pub async fn rpc(&self, node_id: &str, msg_type: MessageType) -> io::Result<MessageBody> {
+        let msg_id = self.next_msg_id.fetch_add(1, Ordering::Relaxed);
+        let mut body = MessageBody::with_type(msg_type);
+        body.msg_id = Some(msg_id.into());
+
+        let (sender, receiver) = oneshot::channel();
+        self.state
+            .pending
+            .lock()
+            .unwrap()
+            .insert((self.id.to_owned(), msg_id), sender);
+        deliver(
+            &self.state,
+            Message {
+                src: self.id.to_owned(),
+                dest: node_id.into(),
+                body,
+            },
+        )?;
+
+        let reply = tokio::select! {
+            reply = receiver => reply.ok(),
+            _ = self.state.clock.sleep(self.state.client_timeout) => None,
+        };
+        match reply {
+            Some(body) => Ok(body),
+            None => {
+                self.state
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .remove(&(self.id.to_owned(), msg_id));
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} got no reply from {node_id}", self.id),
+                ))
+            }
+        }
+    }
+}
+
+fn advance_to_next_wake(clock: &VirtualClock) {
+    if let Some(wake) = clock.next_wake() {
+        clock.advance(wake.saturating_duration_since(clock.now()));
+    }
+}
+
+// Yields until the tasks woken so far ran, and the ones they woke in turn, a
+// message travelling from node to node takes a few passes.
+async fn settle() {
+    for _ in 0..SETTLE_ROUNDS {
+        yield_now().await;
+    }
+}
+
+fn deliver(state: &SimState, message: Message) -> io::Result<()> {
+    let line = canonical::to_string(&message)?;
+    deliver_line(state, &message.dest, line);
+    Ok(())
+}
+
+fn deliver_line(state: &SimState, dest: &str, line: String) {
+    if let Some(inbox) = state.inboxes.lock().unwrap().get(dest) {
+        let _ = inbox.send(line);
+    }
+}
+
+// forwards everything a node writes to clients, services or other nodes
+async fn route(state: Arc<SimState>, mut outbound: mpsc::UnboundedReceiver<String>) {
+    while let Some(line) = outbound.recv().await {
+        let Ok(envelope) = Envelope::parse(&line) else {
+            continue;
+        };
+
+        // traffic between nodes is forwarded as is, only service requests
+        // and client replies are parsed in full
+        if state.node_ids.iter().any(|id| *id == *envelope.dest) {
+            if let Some(delay) = link_delay(&state, &envelope.src, &envelope.dest) {
+                let state = state.clone();
+                let dest = envelope.dest.into_owned();
+                tokio::spawn(async move {
+                    state.clock.sleep(delay).await;
+                    deliver_line(&state, &dest, line);
+                });
+            }
+            continue;
+        }
+
+        let Ok(message) = serde_json::from_str::<Message>(&line) else {
+            continue;
+        };
+        if KV_SERVICES.contains(&&*message.dest) {
+            let reply = kv_reply(&state, &message);
+            let _ = deliver(&state, reply);
+        } else if let Some(in_reply_to) = message.body.in_reply_to.as_ref().and_then(MsgId::as_u64)
+        {
+            let key = (message.dest.to_owned(), in_reply_to);
+            if let Some(sender) = state.pending.lock().unwrap().remove(&key) {
+                let _ = sender.send(message.body);
+            }
+        }
+    }
+}
+
+// None when the message is lost or the link is partitioned
+fn link_delay(state: &SimState, src: &str, dest: &str) -> Option<Duration> {
+    let pair = (src.to_owned(), dest.to_owned());
+    if state.blocked.lock().unwrap().contains(&pair) {
+        return None;
+    }
+
+    let link = state.link.lock().unwrap().to_owned();
+    let mut rng = state.rng.lock().unwrap();
+    if rng.next_f64() < link.loss {
+        return None;
+    }
+    Some(link.latency + link.jitter.mul_f64(rng.next_f64()))
+}
+
+fn kv_reply(state: &SimState, request: &Message) -> Message {
+    let mut kv = state.kv.lock().unwrap();
+    let store = kv.entry(request.dest.to_owned()).or_default();
+
+    let body = match &request.body.msg_type {
+        MessageType::Read(ReadRequest::Kv { key }) => match store.get(key) {
+            Some(value) => MessageBody::read_ok_value(value.to_owned()),
+            None => MessageBody::key_does_not_exist("key does not exist"),
+        },
+        MessageType::Write { key, value } => {
+            store.insert(key.to_owned(), value.to_owned());
+            MessageBody::with_type(MessageType::WriteOk)
+        }
+        MessageType::Cas {
+            key,
+            from,
+            to,
+            create_if_not_exists,
+        } => match store.get(key) {
+            Some(current) if current == from => {
+                store.insert(key.to_owned(), to.to_owned());
+                MessageBody::with_type(MessageType::CasOk)
+            }
+            Some(current) => {
+                MessageBody::precondition_failed(format!("expected {from:?}, found {current:?}"))
+            }
+            None if create_if_not_exists.unwrap_or_default() => {
+                store.insert(key.to_owned(), to.to_owned());
+                MessageBody::with_type(MessageType::CasOk)
+            }
+            None => MessageBody::key_does_not_exist("key does not exist"),
+        },
+        MessageType::Ts => MessageBody::with_type(MessageType::TsOk {
+            ts: state.ts.fetch_add(1, Ordering::Relaxed) + 1,
+        }),
+        other => MessageBody::not_supported(format!(
+            "{} is not supported by {}",
+            other.name(),
+            request.dest
+        )),
+    };
+    Message::reply_to(request, body)
+}
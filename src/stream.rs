@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use crate::message::{Message, MsgId};
+
+// Collects the parts of a streamed reply, which may arrive in any order. Every
+// part names the msg_id of the one before it, so the stream is complete once
+// the chain from the first part reaches the final one.
+#[derive(Default)]
+pub struct StreamAssembler {
+    // parts keyed by the msg_id of their predecessor, None for the first part
+    by_prev: HashMap<Option<MsgId>, Message>,
+}
+
+impl StreamAssembler {
+    // adds a part and returns the whole stream in order once it is complete
+    pub fn push(&mut self, part: Message) -> Option<Vec<Message>> {
+        self.by_prev.insert(part.body.prev.clone(), part);
+
+        let mut prev = None;
+        let mut len = 0;
+        loop {
+            let part = self.by_prev.get(&prev)?;
+            len += 1;
+            if part.body.more != Some(true) {
+                break;
+            }
+            prev = Some(part.body.msg_id.clone()?);
+        }
+
+        let mut parts = Vec::with_capacity(len);
+        let mut prev = None;
+        while let Some(part) = self.by_prev.remove(&prev) {
+            prev = part.body.msg_id.clone();
+            let last = part.body.more != Some(true);
+            parts.push(part);
+            if last {
+                break;
+            }
+        }
+        Some(parts)
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::Arc,
+    time::Instant,
+};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::metrics::{current_metrics, Metrics};
+
+// tokio Mutex that records how long each call site waited for the lock and how
+// long it held it, as `lock.wait <file>:<line>` / `lock.hold <file>:<line>` timings
+// in the metrics of the node the locking task runs on.
+#[derive(Default)]
+pub struct TrackedMutex<T> {
+    inner: Mutex<T>,
+}
+
+pub struct TrackedMutexGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    site: String,
+    acquired_at: Instant,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl<T> TrackedMutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> impl Future<Output = TrackedMutexGuard<'_, T>> {
+        let location = Location::caller();
+        async move {
+            let started_at = Instant::now();
+            let guard = self.inner.lock().await;
+            let acquired_at = Instant::now();
+
+            let site = format!("{}:{}", location.file(), location.line());
+            let metrics = current_metrics();
+            if let Some(metrics) = &metrics {
+                metrics.observe(&format!("lock.wait {site}"), acquired_at - started_at);
+            }
+
+            TrackedMutexGuard {
+                guard,
+                site,
+                acquired_at,
+                metrics,
+            }
+        }
+    }
+}
+
+impl<T> Deref for TrackedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for TrackedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for TrackedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(
+                &format!("lock.hold {}", self.site),
+                self.acquired_at.elapsed(),
+            );
+        }
+    }
+}
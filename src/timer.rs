@@ -0,0 +1,23 @@
+use tokio::task::JoinHandle;
+
+// Handle of a timer scheduled with `send_after` or `after`. Dropping it leaves
+// the timer running, `cancel` stops it if it hasn't fired yet.
+#[derive(Debug)]
+pub struct Timer {
+    task: JoinHandle<()>,
+}
+
+impl Timer {
+    pub fn new(task: JoinHandle<()>) -> Self {
+        Self { task }
+    }
+
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    // true once the timer fired or was cancelled
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
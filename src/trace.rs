@@ -0,0 +1,117 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, LineWriter, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::message::MsgId;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+#[derive(Debug, Serialize)]
+struct TraceEntry {
+    // milliseconds since the unix epoch
+    time: u128,
+    direction: Direction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<MsgId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to: Option<MsgId>,
+    message: Json,
+}
+
+// Records every received and sent message as one JSON object per line in
+// `<prefix>-<node_id>.jsonl`. Messages seen before init told us the node id
+// are held back until the file can be named.
+pub struct Tracer {
+    prefix: String,
+    state: Mutex<TraceState>,
+}
+
+#[derive(Default)]
+struct TraceState {
+    file: Option<LineWriter<File>>,
+    pending: Vec<String>,
+}
+
+impl Tracer {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            state: Default::default(),
+        }
+    }
+
+    pub fn record(&self, node_id: &str, direction: Direction, line: &str) -> io::Result<()> {
+        let message = serde_json::from_str::<Json>(line).unwrap_or_else(|_| line.into());
+        let body = message.get("body");
+        let entry = TraceEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            direction,
+            msg_id: body.and_then(|body| MsgId::deserialize(body.get("msg_id")?).ok()),
+            in_reply_to: body.and_then(|body| MsgId::deserialize(body.get("in_reply_to")?).ok()),
+            message,
+        };
+        let entry = serde_json::to_string(&entry)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.file.is_none() {
+            if node_id.is_empty() {
+                state.pending.push(entry);
+                return Ok(());
+            }
+            let mut file =
+                LineWriter::new(File::create(format!("{}-{node_id}.jsonl", self.prefix))?);
+            for pending in state.pending.drain(..) {
+                writeln!(file, "{pending}")?;
+            }
+            state.file = Some(file);
+        }
+
+        let file = state.file.as_mut().unwrap();
+        writeln!(file, "{entry}")
+    }
+}
+
+tokio::task_local! {
+    // trace id of the client request the current task works for
+    static TRACE_ID: Option<String>;
+}
+
+static NEXT_TRACE_ID: AtomicU64 = AtomicU64::new(0);
+
+// unique per node, e.g. "n1-42"
+pub fn new_trace_id(node_id: &str) -> String {
+    format!(
+        "{node_id}-{}",
+        NEXT_TRACE_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+pub fn current_trace_id() -> Option<String> {
+    TRACE_ID.try_with(Clone::clone).ok().flatten()
+}
+
+// runs the future with `trace_id` as the current trace id
+pub fn with_trace_id<F: Future>(
+    trace_id: Option<String>,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    TRACE_ID.scope(trace_id, future)
+}
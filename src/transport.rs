@@ -0,0 +1,94 @@
+use std::io;
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines, Stdin},
+    sync::{mpsc, Mutex},
+};
+
+// Line based link between the runtime and Maelstrom, one JSON message per line.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    // next inbound line, None once the input is closed
+    async fn recv(&self) -> io::Result<Option<String>>;
+
+    fn send(&self, line: String);
+}
+
+// stdin / stdout, what Maelstrom talks to
+pub struct StdioTransport {
+    lines: Mutex<Lines<BufReader<Stdin>>>,
+}
+
+impl Default for StdioTransport {
+    fn default() -> Self {
+        Self {
+            lines: Mutex::new(BufReader::new(tokio::io::stdin()).lines()),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for StdioTransport {
+    async fn recv(&self) -> io::Result<Option<String>> {
+        self.lines.lock().await.next_line().await
+    }
+
+    fn send(&self, line: String) {
+        println!("{line}");
+    }
+}
+
+// In-memory transport to drive a node from the same process, e.g. in tests.
+// Dropping the `inbound` sender of the handle closes the input, which shuts the
+// node down like the end of stdin does.
+pub struct ChannelTransport {
+    inbound: Mutex<mpsc::UnboundedReceiver<String>>,
+    outbound: mpsc::UnboundedSender<String>,
+}
+
+// the other end of a `ChannelTransport`, standing in for Maelstrom
+pub struct TransportHandle {
+    pub inbound: mpsc::UnboundedSender<String>,
+    pub outbound: mpsc::UnboundedReceiver<String>,
+}
+
+impl ChannelTransport {
+    pub fn new() -> (Self, TransportHandle) {
+        let (inbound_sender, inbound) = mpsc::unbounded_channel();
+        let (outbound, outbound_receiver) = mpsc::unbounded_channel();
+        let transport = Self {
+            inbound: Mutex::new(inbound),
+            outbound,
+        };
+        let handle = TransportHandle {
+            inbound: inbound_sender,
+            outbound: outbound_receiver,
+        };
+        (transport, handle)
+    }
+}
+
+#[async_trait]
+impl Transport for ChannelTransport {
+    async fn recv(&self) -> io::Result<Option<String>> {
+        Ok(self.inbound.lock().await.recv().await)
+    }
+
+    // lines sent after the handle is gone are dropped
+    fn send(&self, line: String) {
+        let _ = self.outbound.send(line);
+    }
+}
+
+impl TransportHandle {
+    // delivers a line to the node as if Maelstrom had sent it
+    pub fn send(&self, line: impl Into<String>) {
+        let _ = self.inbound.send(line.into());
+    }
+
+    // next line written by the node, None once the node has shut down
+    pub async fn recv(&mut self) -> Option<String> {
+        self.outbound.recv().await
+    }
+}
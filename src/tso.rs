@@ -0,0 +1,80 @@
+use std::{io, ops::Range, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    maelstrom::Maelstrom,
+    message::{MessageBody, MessageType},
+};
+
+const LIN_TSO: &str = "lin-tso";
+
+// Client of Maelstrom's lin-tso timestamp oracle. Timestamps strictly increase
+// across every node of the cluster, so unlike wall clocks they can order
+// snapshots or last-write-wins updates between nodes.
+//
+// A `batched` client turns every oracle timestamp into a block of `block_size`
+// local ones and hands those out without further rpcs. They stay unique and
+// increase on every node, but a timestamp from a block only orders after the
+// timestamps of blocks fetched before that block, not after everything handed
+// out before the call.
+#[derive(Clone)]
+pub struct Tso {
+    maelstrom: Maelstrom,
+    block_size: u64,
+    // rest of the current block, held across the fetch of the next one so
+    // concurrent callers wait for a single rpc
+    block: Arc<Mutex<Range<u64>>>,
+}
+
+impl Tso {
+    pub fn new(maelstrom: Maelstrom) -> Self {
+        Self::batched(maelstrom, 1)
+    }
+
+    pub fn batched(maelstrom: Maelstrom, block_size: u64) -> Self {
+        Self {
+            maelstrom,
+            block_size: block_size.max(1),
+            block: Arc::new(Mutex::new(0..0)),
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    // a timestamp larger than any this client handed out before, and than any
+    // handed out by others before the current block was fetched
+    pub async fn ts(&self) -> io::Result<u64> {
+        if self.block_size == 1 {
+            return self.fetch().await;
+        }
+        let mut block = self.block.lock().await;
+        if block.is_empty() {
+            let ts = self.fetch().await?;
+            let start = ts.checked_mul(self.block_size);
+            let end = start.and_then(|start| start.checked_add(self.block_size));
+            let (Some(start), Some(end)) = (start, end) else {
+                return Err(io::Error::other("timestamp too large to split into blocks"));
+            };
+            self.maelstrom.metrics().incr("tso.blocks", 1);
+            *block = start..end;
+        }
+        // not empty at this point
+        Ok(block.next().unwrap())
+    }
+
+    async fn fetch(&self) -> io::Result<u64> {
+        let body = MessageBody::with_type(MessageType::Ts);
+        // a lost request only burns a timestamp, so it is safe to retry
+        let response = self.maelstrom.rpc(LIN_TSO.to_owned(), body, true).await?;
+        match response.body.msg_type {
+            MessageType::TsOk { ts } => Ok(ts),
+            MessageType::Error { code, text } => Err(io::Error::other(format!(
+                "timestamp request failed ({code}): {text}"
+            ))),
+            _ => Err(io::Error::other("unexpected reply to timestamp request")),
+        }
+    }
+}
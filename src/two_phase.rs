@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use tokio::task::JoinSet;
+
+use crate::{
+    maelstrom::Maelstrom,
+    message::{Message, MessageBody, NodeId},
+};
+
+// how long a participant stays prepared before asking the coordinator for the outcome
+pub const DEFAULT_IN_DOUBT_TIMEOUT: Duration = Duration::from_secs(1);
+// how long `execute` waits for the participants to acknowledge the outcome
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+// How long the outcome is resent to a participant. Shutdown waits for the
+// delivery, and participants that miss it ask for the outcome themselves.
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+// how long an undelivered outcome is kept for participants in doubt to ask for
+const DECISION_RETENTION: Duration = Duration::from_secs(60);
+// how long a participant remembers a finished transaction to answer resent
+// messages of it, well past the delivery timeout
+const DONE_RETENTION: Duration = Duration::from_secs(30);
+const RECOVERY_INTERVAL: Duration = Duration::from_millis(250);
+
+pub type TxnId = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Committed,
+    Aborted,
+}
+
+// Messages between coordinator and participants, sent as custom bodies (see
+// `MessageBody::from_custom`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TwoPhaseBody {
+    Prepare { txn_id: TxnId, writes: Json },
+    PrepareOk { txn_id: TxnId, vote: bool },
+    Commit { txn_id: TxnId },
+    CommitOk,
+    Abort { txn_id: TxnId },
+    AbortOk,
+    // asked by a participant in doubt, the outcome is None while undecided
+    TxnStatus { txn_id: TxnId },
+    TxnStatusOk { outcome: Option<Outcome> },
+}
+
+impl TwoPhaseBody {
+    fn parse(body: &MessageBody) -> Option<Self> {
+        body.to_custom().ok()
+    }
+
+    fn to_body(&self) -> MessageBody {
+        // serializing a tagged enum into a body can't fail
+        MessageBody::from_custom(self).expect("two phase body")
+    }
+}
+
+// The part of a node a transaction writes to. `prepare` validates the writes
+// and holds whatever they need, e.g. locks on the keys, until `commit` applies
+// them or `abort` lets go. Each is called at most once per transaction.
+#[async_trait]
+pub trait Resource: Send + Sync {
+    // false votes to abort the transaction
+    async fn prepare(&self, txn_id: &TxnId, writes: &Json) -> io::Result<bool>;
+
+    async fn commit(&self, txn_id: &TxnId, writes: &Json) -> io::Result<()>;
+
+    async fn abort(&self, txn_id: &TxnId) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    // None while the votes are being collected
+    outcome: Option<Outcome>,
+    // set once the delivery gave up on some participant
+    undelivered_since: Option<Instant>,
+}
+
+type Decisions = Arc<Mutex<HashMap<TxnId, Decision>>>;
+
+// Runs transactions that write on several nodes. The outcome is decided once
+// every participant voted, or a vote timed out which aborts, and is kept until
+// every participant acknowledged it, or for `DECISION_RETENTION` after the
+// delivery gave up, so those in doubt can ask for it later. Transactions it
+// doesn't know about are presumed aborted.
+pub struct Coordinator {
+    maelstrom: Maelstrom,
+    // dropped once every participant acknowledged the outcome
+    decisions: Decisions,
+    next_txn: AtomicU64,
+    ack_timeout: Duration,
+    delivery_timeout: Duration,
+}
+
+impl Coordinator {
+    pub fn new(maelstrom: Maelstrom) -> Self {
+        Self {
+            maelstrom,
+            decisions: Default::default(),
+            next_txn: AtomicU64::new(1),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            delivery_timeout: DEFAULT_DELIVERY_TIMEOUT,
+        }
+    }
+
+    pub fn with_ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    pub fn with_delivery_timeout(mut self, timeout: Duration) -> Self {
+        self.delivery_timeout = timeout;
+        self
+    }
+
+    // Writes each participant's share atomically: all of them commit or none
+    // does. Prepares are single rpcs, bounded by the rpc timeout.
+    pub async fn execute(&self, writes: HashMap<NodeId, Json>) -> io::Result<Outcome> {
+        let n = self.next_txn.fetch_add(1, Ordering::Relaxed);
+        let txn_id = format!("{}-{n}", self.maelstrom.node_id());
+        let now = self.maelstrom.now();
+        {
+            let mut decisions = self.decisions.lock().unwrap();
+            decisions.retain(|_, decision| {
+                decision
+                    .undelivered_since
+                    .is_none_or(|since| now.duration_since(since) < DECISION_RETENTION)
+            });
+            let pending = Decision {
+                outcome: None,
+                undelivered_since: None,
+            };
+            decisions.insert(txn_id.to_owned(), pending);
+        }
+
+        let participants: Vec<NodeId> = writes.keys().cloned().collect();
+        let prepares = writes.into_iter().map(|(node_id, writes)| {
+            let prepare = TwoPhaseBody::Prepare {
+                txn_id: txn_id.to_owned(),
+                writes,
+            };
+            (node_id, prepare.to_body())
+        });
+        let votes = self.maelstrom.rpc_all(prepares, false).await;
+        let all_yes = votes.into_iter().all(|vote| {
+            let vote = vote.ok().and_then(|reply| TwoPhaseBody::parse(&reply.body));
+            matches!(vote, Some(TwoPhaseBody::PrepareOk { vote: true, .. }))
+        });
+
+        let outcome = if all_yes {
+            Outcome::Committed
+        } else {
+            Outcome::Aborted
+        };
+        if let Some(decision) = self.decisions.lock().unwrap().get_mut(&txn_id) {
+            decision.outcome = Some(outcome);
+        }
+        self.maelstrom.metrics().incr(
+            match outcome {
+                Outcome::Committed => "2pc.committed",
+                Outcome::Aborted => "2pc.aborted",
+            },
+            1,
+        );
+
+        // the outcome is resent until acknowledged or the delivery timeout, also
+        // after `execute` gave up waiting
+        let decision = match outcome {
+            Outcome::Committed => TwoPhaseBody::Commit {
+                txn_id: txn_id.to_owned(),
+            },
+            Outcome::Aborted => TwoPhaseBody::Abort {
+                txn_id: txn_id.to_owned(),
+            },
+        }
+        .to_body();
+        let deliveries = self.maelstrom.spawn(Self::deliver(
+            self.maelstrom.clone(),
+            self.decisions.clone(),
+            txn_id,
+            participants,
+            decision,
+            self.delivery_timeout,
+        ));
+        tokio::select! {
+            _ = deliveries => {}
+            _ = self.maelstrom.sleep(self.ack_timeout) => {}
+        }
+        Ok(outcome)
+    }
+
+    // Resends the decision to each participant until it acknowledges or
+    // `timeout` passes, and forgets the decision once all of them did. A
+    // participant that missed it asks with a status request, which finds the
+    // decision still kept for a while.
+    async fn deliver(
+        maelstrom: Maelstrom,
+        decisions: Decisions,
+        txn_id: TxnId,
+        participants: Vec<NodeId>,
+        decision: MessageBody,
+        timeout: Duration,
+    ) {
+        let mut deliveries = JoinSet::new();
+        for node_id in participants {
+            let (maelstrom, decision) = (maelstrom.clone(), decision.to_owned());
+            deliveries.spawn(async move {
+                tokio::select! {
+                    reply = maelstrom.rpc(node_id, decision, true) => reply.is_ok(),
+                    _ = maelstrom.sleep(timeout) => false,
+                }
+            });
+        }
+        let mut acked = true;
+        while let Some(delivered) = deliveries.join_next().await {
+            acked &= delivered.unwrap_or(false);
+        }
+        let mut decisions = decisions.lock().unwrap();
+        if acked {
+            decisions.remove(&txn_id);
+        } else if let Some(decision) = decisions.get_mut(&txn_id) {
+            maelstrom.metrics().incr("2pc.undelivered", 1);
+            decision.undelivered_since = Some(maelstrom.now());
+        }
+    }
+
+    // Unknown transactions were never started here, or every participant
+    // acknowledged them already, and aren't remembered.
+    fn status(&self, txn_id: &TxnId) -> Option<Outcome> {
+        let decisions = self.decisions.lock().unwrap();
+        decisions
+            .get(txn_id)
+            .map_or(Some(Outcome::Aborted), |decision| decision.outcome)
+    }
+
+    // the reply to a status request, None for any other message
+    pub fn handle(&self, request: &Message) -> Option<MessageBody> {
+        let TwoPhaseBody::TxnStatus { txn_id } = TwoPhaseBody::parse(&request.body)? else {
+            return None;
+        };
+        let outcome = self.status(&txn_id);
+        Some(TwoPhaseBody::TxnStatusOk { outcome }.to_body())
+    }
+}
+
+enum Participation {
+    Prepared {
+        coordinator: NodeId,
+        writes: Json,
+        since: Instant,
+    },
+    Done {
+        outcome: Outcome,
+        since: Instant,
+    },
+}
+
+// the state of one transaction, None until it's prepared
+type Slot = Arc<tokio::sync::Mutex<Option<Participation>>>;
+
+// Applies the coordinator's decisions to a `Resource`. Prepared transactions
+// that hear nothing for `in_doubt_timeout` ask the coordinator for the outcome,
+// see `run`.
+pub struct Participant<R> {
+    maelstrom: Maelstrom,
+    resource: R,
+    // Each transaction's lock is held across its resource calls, so retried
+    // messages of a transaction wait for the first one while other
+    // transactions go ahead. Finished ones are dropped after `DONE_RETENTION`.
+    txns: Mutex<HashMap<TxnId, Slot>>,
+    in_doubt_timeout: Duration,
+}
+
+impl<R: Resource> Participant<R> {
+    pub fn new(maelstrom: Maelstrom, resource: R) -> Self {
+        Self {
+            maelstrom,
+            resource,
+            txns: Default::default(),
+            in_doubt_timeout: DEFAULT_IN_DOUBT_TIMEOUT,
+        }
+    }
+
+    pub fn with_in_doubt_timeout(mut self, timeout: Duration) -> Self {
+        self.in_doubt_timeout = timeout;
+        self
+    }
+
+    pub fn resource(&self) -> &R {
+        &self.resource
+    }
+
+    // the reply to a prepare, commit or abort, None for any other message
+    pub async fn handle(&self, request: &Message) -> Option<io::Result<MessageBody>> {
+        let reply = match TwoPhaseBody::parse(&request.body)? {
+            TwoPhaseBody::Prepare { txn_id, writes } => {
+                self.prepare(&request.src, txn_id, writes).await
+            }
+            TwoPhaseBody::Commit { txn_id } => self
+                .finish(&txn_id, Outcome::Committed)
+                .await
+                .map(|()| TwoPhaseBody::CommitOk),
+            TwoPhaseBody::Abort { txn_id } => self
+                .finish(&txn_id, Outcome::Aborted)
+                .await
+                .map(|()| TwoPhaseBody::AbortOk),
+            _ => return None,
+        };
+        Some(reply.map(|reply| reply.to_body()))
+    }
+
+    fn slot(&self, txn_id: &TxnId) -> Slot {
+        let mut txns = self.txns.lock().unwrap();
+        txns.entry(txn_id.to_owned()).or_default().clone()
+    }
+
+    async fn prepare(
+        &self,
+        coordinator: &NodeId,
+        txn_id: TxnId,
+        writes: Json,
+    ) -> io::Result<TwoPhaseBody> {
+        let slot = self.slot(&txn_id);
+        let mut state = slot.lock().await;
+        let vote = match &*state {
+            // a retried prepare gets the same vote
+            Some(
+                Participation::Prepared { .. }
+                | Participation::Done {
+                    outcome: Outcome::Committed,
+                    ..
+                },
+            ) => true,
+            Some(Participation::Done {
+                outcome: Outcome::Aborted,
+                ..
+            }) => false,
+            None => {
+                let vote = self.resource.prepare(&txn_id, &writes).await?;
+                let since = self.maelstrom.now();
+                *state = Some(if vote {
+                    Participation::Prepared {
+                        coordinator: coordinator.to_owned(),
+                        writes,
+                        since,
+                    }
+                } else {
+                    Participation::Done {
+                        outcome: Outcome::Aborted,
+                        since,
+                    }
+                });
+                vote
+            }
+        };
+        Ok(TwoPhaseBody::PrepareOk { txn_id, vote })
+    }
+
+    // applies the outcome once, later deliveries of it are no-ops
+    async fn finish(&self, txn_id: &TxnId, outcome: Outcome) -> io::Result<()> {
+        // an abort may overtake the prepare, which then votes no, a commit can't
+        let slot = match outcome {
+            Outcome::Committed => self.txns.lock().unwrap().get(txn_id).cloned(),
+            Outcome::Aborted => Some(self.slot(txn_id)),
+        };
+        let Some(slot) = slot else {
+            return self.never_prepared(txn_id);
+        };
+        let mut state = slot.lock().await;
+        match (&*state, outcome) {
+            (Some(Participation::Prepared { writes, .. }), Outcome::Committed) => {
+                self.resource.commit(txn_id, writes).await?;
+            }
+            (Some(Participation::Prepared { .. }), Outcome::Aborted) => {
+                self.resource.abort(txn_id).await?;
+            }
+            (Some(Participation::Done { .. }), _) => return Ok(()),
+            (None, Outcome::Committed) => return self.never_prepared(txn_id),
+            (None, Outcome::Aborted) => {}
+        }
+        *state = Some(Participation::Done {
+            outcome,
+            since: self.maelstrom.now(),
+        });
+        Ok(())
+    }
+
+    fn never_prepared(&self, txn_id: &TxnId) -> io::Result<()> {
+        self.maelstrom.protocol_violation(format!(
+            "commit of transaction {txn_id} that was never prepared"
+        ));
+        Ok(())
+    }
+
+    // Transactions prepared longer than the in-doubt timeout, with their
+    // coordinator. Drops the ones finished longer than `DONE_RETENTION` ago.
+    // Transactions busy with a resource call are skipped.
+    fn in_doubt(&self) -> Vec<(TxnId, NodeId)> {
+        let now = self.maelstrom.now();
+        let mut txns = self.txns.lock().unwrap();
+        txns.retain(|_, slot| {
+            let Ok(state) = slot.try_lock() else {
+                return true;
+            };
+            !matches!(&*state, Some(Participation::Done { since, .. })
+                if now.duration_since(*since) >= DONE_RETENTION)
+        });
+        txns.iter()
+            .filter_map(|(txn_id, slot)| match &*slot.try_lock().ok()? {
+                Some(Participation::Prepared {
+                    coordinator, since, ..
+                }) if now.duration_since(*since) >= self.in_doubt_timeout => {
+                    Some((txn_id.to_owned(), coordinator.to_owned()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Resolves transactions in doubt by asking their coordinator, runs until the
+    // process exits.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = self.maelstrom.interval(RECOVERY_INTERVAL);
+        loop {
+            interval.tick().await;
+            for (txn_id, coordinator) in self.in_doubt() {
+                let request = TwoPhaseBody::TxnStatus {
+                    txn_id: txn_id.to_owned(),
+                };
+                let Ok(reply) = self
+                    .maelstrom
+                    .rpc(coordinator, request.to_body(), false)
+                    .await
+                else {
+                    continue;
+                };
+                let Some(TwoPhaseBody::TxnStatusOk {
+                    outcome: Some(outcome),
+                }) = TwoPhaseBody::parse(&reply.body)
+                else {
+                    continue;
+                };
+                self.maelstrom.metrics().incr("2pc.recovered", 1);
+                if let Err(e) = self.finish(&txn_id, outcome).await {
+                    self.maelstrom
+                        .log(format!("Error: resolving transaction {txn_id} failed: {e}"));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{collections::HashMap, env, fmt::Write, fs, io, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::message::{Key, Transaction};
+
+// env var holding the output path prefix, recording is disabled when unset
+pub const TXN_GRAPH_ENV: &str = "TXN_GRAPH";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TxnRecord {
+    pub id: usize,
+    pub reads: Vec<Key>,
+    pub writes: Vec<Key>,
+    pub conflict: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TxnEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: &'static str,
+    pub key: Key,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphExport<'a> {
+    node_id: &'a str,
+    txns: &'a [TxnRecord],
+    edges: Vec<TxnEdge>,
+}
+
+// Records the keys touched by each transaction processed on this node, in local
+// commit order, and exports the resulting dependency graph as JSON and DOT.
+#[derive(Default)]
+pub struct TxnGraph {
+    path: Option<String>,
+    records: Mutex<Vec<TxnRecord>>,
+}
+
+impl TxnGraph {
+    // `path` is the output prefix of `export`, None disables recording
+    pub fn new(path: Option<String>) -> Self {
+        Self {
+            path,
+            records: Default::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(env::var(TXN_GRAPH_ENV).ok())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // `conflict` marks a transaction that was aborted because its CAS lost a race
+    pub fn record<T: Clone + Into<Transaction>>(&self, txn: &[T], conflict: bool) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut reads = vec![];
+        let mut writes = vec![];
+        for t in txn.iter().cloned().map(Into::into) {
+            match t {
+                Transaction::Read { key, .. } => reads.push(key),
+                Transaction::Write { key, .. } | Transaction::Append { key, .. } => {
+                    writes.push(key)
+                }
+            }
+        }
+
+        let mut records = self.records.lock().unwrap();
+        let id = records.len();
+        records.push(TxnRecord {
+            id,
+            reads,
+            writes,
+            conflict,
+        });
+    }
+
+    pub fn edges(&self) -> Vec<TxnEdge> {
+        let records = self.records.lock().unwrap();
+        let mut last_writer: HashMap<Key, usize> = HashMap::new();
+        let mut readers: HashMap<Key, Vec<usize>> = HashMap::new();
+        let mut edges = vec![];
+
+        for record in records.iter() {
+            for key in record.reads.iter() {
+                if let Some(writer) = last_writer.get(key) {
+                    edges.push(TxnEdge {
+                        from: *writer,
+                        to: record.id,
+                        kind: "wr",
+                        key: key.to_owned(),
+                    });
+                }
+                if !record.conflict {
+                    readers.entry(key.to_owned()).or_default().push(record.id);
+                }
+            }
+
+            // aborted transactions never installed their writes
+            if record.conflict {
+                continue;
+            }
+
+            for key in record.writes.iter() {
+                if let Some(writer) = last_writer.get(key) {
+                    edges.push(TxnEdge {
+                        from: *writer,
+                        to: record.id,
+                        kind: "ww",
+                        key: key.to_owned(),
+                    });
+                }
+                for reader in readers.remove(key).unwrap_or_default() {
+                    if reader != record.id {
+                        edges.push(TxnEdge {
+                            from: reader,
+                            to: record.id,
+                            kind: "rw",
+                            key: key.to_owned(),
+                        });
+                    }
+                }
+                last_writer.insert(key.to_owned(), record.id);
+            }
+        }
+
+        edges
+    }
+
+    pub fn to_dot(&self, node_id: &str) -> String {
+        let edges = self.edges();
+        let records = self.records.lock().unwrap();
+
+        let mut dot = format!("digraph \"txns-{}\" {{\n", escape(node_id));
+        for record in records.iter() {
+            let color = if record.conflict { "red" } else { "black" };
+            let label = format!(
+                "t{} r[{}] w[{}]",
+                record.id,
+                join(&record.reads),
+                join(&record.writes)
+            );
+            let _ = writeln!(
+                dot,
+                "  t{} [label=\"{}\", color={color}];",
+                record.id,
+                escape(&label)
+            );
+        }
+        for edge in edges {
+            let label = format!("{} {}", edge.kind, edge.key);
+            let _ = writeln!(
+                dot,
+                "  t{} -> t{} [label=\"{}\"];",
+                edge.from,
+                edge.to,
+                escape(&label)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self, node_id: &str) -> io::Result<String> {
+        let edges = self.edges();
+        let records = self.records.lock().unwrap();
+        let export = GraphExport {
+            node_id,
+            txns: &records,
+            edges,
+        };
+        Ok(serde_json::to_string(&export)?)
+    }
+
+    // write `<prefix>-<node_id>.json` and `<prefix>-<node_id>.dot`
+    pub fn export(&self, node_id: &str) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        fs::write(format!("{path}-{node_id}.json"), self.to_json(node_id)?)?;
+        fs::write(format!("{path}-{node_id}.dot"), self.to_dot(node_id))?;
+        Ok(())
+    }
+}
+
+fn join(keys: &[Key]) -> String {
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    keys.join(",")
+}
+
+// quotes and backslashes would end or break a quoted DOT string
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
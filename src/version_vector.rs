@@ -0,0 +1,129 @@
+use std::{
+    cmp::Ordering,
+    collections::{btree_map, BTreeMap},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::MessageBody;
+
+// How two version vectors relate causally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    // the left side happened before the right side
+    Before,
+    After,
+    Concurrent,
+}
+
+// Version vector keyed by node id. Serialized as a `{"n1": 3}` map that omits
+// nodes still at zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, u64>", into = "BTreeMap<String, u64>")]
+pub struct VersionVector {
+    counters: BTreeMap<String, u64>,
+}
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, node_id: &str) -> u64 {
+        self.counters.get(node_id).copied().unwrap_or_default()
+    }
+
+    // records a local event of `node_id` and returns its new counter
+    pub fn increment(&mut self, node_id: &str) -> u64 {
+        let counter = self.counters.entry(node_id.to_owned()).or_default();
+        *counter += 1;
+        *counter
+    }
+
+    // pointwise maximum, the result dominates both sides
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (node_id, &counter) in &other.counters {
+            let current = self.counters.entry(node_id.to_owned()).or_default();
+            *current = (*current).max(counter);
+        }
+    }
+
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let mut less = false;
+        let mut greater = false;
+        for node_id in self.counters.keys().chain(other.counters.keys()) {
+            match self.get(node_id).cmp(&other.get(node_id)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+
+        match (less, greater) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    pub fn happened_before(&self, other: &VersionVector) -> bool {
+        self.compare(other) == Causality::Before
+    }
+
+    pub fn is_concurrent(&self, other: &VersionVector) -> bool {
+        self.compare(other) == Causality::Concurrent
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<'_, String, u64> {
+        self.counters.iter()
+    }
+}
+
+// concurrent vectors are incomparable
+impl PartialOrd for VersionVector {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.compare(other) {
+            Causality::Equal => Some(Ordering::Equal),
+            Causality::Before => Some(Ordering::Less),
+            Causality::After => Some(Ordering::Greater),
+            Causality::Concurrent => None,
+        }
+    }
+}
+
+impl FromIterator<(String, u64)> for VersionVector {
+    fn from_iter<I: IntoIterator<Item = (String, u64)>>(iter: I) -> Self {
+        Self {
+            counters: iter
+                .into_iter()
+                .filter(|(_, counter)| *counter > 0)
+                .collect(),
+        }
+    }
+}
+
+impl From<BTreeMap<String, u64>> for VersionVector {
+    fn from(counters: BTreeMap<String, u64>) -> Self {
+        counters.into_iter().collect()
+    }
+}
+
+impl From<VersionVector> for BTreeMap<String, u64> {
+    fn from(version: VersionVector) -> Self {
+        version.counters
+    }
+}
+
+impl MessageBody {
+    // attaches a version vector to the body, sent as its `version` field
+    pub fn with_version(mut self, version: VersionVector) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn version(&self) -> Option<&VersionVector> {
+        self.version.as_ref()
+    }
+}
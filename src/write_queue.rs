@@ -0,0 +1,73 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use crate::rate_limit::Priority;
+
+#[derive(Default)]
+struct Queues {
+    high: VecDeque<String>,
+    low: VecDeque<String>,
+}
+
+// Lines waiting for the single writer task. Whatever sends them only queues,
+// the writer takes every high priority line before the next low one, so a
+// reply isn't stuck behind a burst of gossip whether or not a rate limit is
+// set. Lines of the same priority keep their order.
+#[derive(Default)]
+pub struct WriteQueue {
+    queues: Mutex<Queues>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl WriteQueue {
+    pub fn push(&self, priority: Priority, line: String) {
+        let mut queues = self.queues.lock().unwrap();
+        match priority {
+            Priority::High => queues.high.push_back(line),
+            Priority::Low => queues.low.push_back(line),
+        }
+        drop(queues);
+        self.notify.notify_one();
+    }
+
+    fn pop(&self) -> Option<String> {
+        let mut queues = self.queues.lock().unwrap();
+        queues.high.pop_front().or_else(|| queues.low.pop_front())
+    }
+
+    pub fn len(&self) -> usize {
+        let queues = self.queues.lock().unwrap();
+        queues.high.len() + queues.low.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // writes queued lines as they come, returns once closed and empty
+    pub async fn run(&self, write: impl Fn(String)) {
+        loop {
+            let notified = self.notify.notified();
+            while let Some(line) = self.pop() {
+                write(line);
+            }
+            if self.closed.load(Ordering::Relaxed) && self.is_empty() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+}
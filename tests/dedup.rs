@@ -0,0 +1,153 @@
+// Retried client requests crossing their replies, driven over a
+// `ChannelTransport` with request dedup enabled, and the outbound dedup.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    dedup::OutboundDedup,
+    maelstrom::{not_supported, App, Context, Maelstrom},
+    message::{AddRequest, Message, MessageBody, MessageType},
+    transport::{ChannelTransport, TransportHandle},
+};
+use serde_json::Value as Json;
+use tokio::{sync::Semaphore, task::JoinHandle, time::timeout};
+
+// counts the adds it applied, each one waits for a permit first
+struct CountingApp {
+    applied: AtomicU64,
+    permits: Semaphore,
+}
+
+#[async_trait]
+impl App for CountingApp {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        let MessageType::Add(AddRequest::Delta { delta }) = request.body.msg_type else {
+            return Err(not_supported(&request.body.msg_type));
+        };
+        self.permits
+            .acquire()
+            .await
+            .map_err(io::Error::other)?
+            .forget();
+        self.applied.fetch_add(delta as u64, Ordering::SeqCst);
+        Ok(Some(MessageBody::with_type(MessageType::AddOk)))
+    }
+}
+
+struct Node {
+    app: Arc<CountingApp>,
+    handle: TransportHandle,
+    task: JoinHandle<io::Result<()>>,
+}
+
+async fn start(permits: usize) -> Node {
+    let (transport, mut handle) = ChannelTransport::new();
+    let maelstrom = Maelstrom::builder()
+        .log_messages(false)
+        .dedup_requests(Some(Duration::from_secs(30)))
+        .transport(Arc::new(transport))
+        .build();
+    let app = Arc::new(CountingApp {
+        applied: AtomicU64::new(0),
+        permits: Semaphore::new(permits),
+    });
+    let task = tokio::spawn({
+        let app = app.clone();
+        async move { maelstrom.run_with_app(app).await }
+    });
+
+    handle.send(r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#);
+    let init_ok = recv(&mut handle).await.unwrap();
+    assert_eq!(init_ok["body"]["type"], "init_ok");
+    Node { app, handle, task }
+}
+
+// next line the node wrote, None if nothing arrives for a while
+async fn recv(handle: &mut TransportHandle) -> Option<Json> {
+    let line = timeout(Duration::from_millis(500), handle.recv())
+        .await
+        .ok()??;
+    Some(serde_json::from_str(&line).unwrap())
+}
+
+const ADD: &str = r#"{"src":"c1","dest":"n1","body":{"type":"add","msg_id":2,"delta":5}}"#;
+
+#[tokio::test]
+async fn retry_after_the_reply_is_answered_with_the_same_reply() {
+    let mut node = start(usize::MAX >> 4).await;
+
+    node.handle.send(ADD);
+    let first = recv(&mut node.handle).await.unwrap();
+    assert_eq!(first["body"]["type"], "add_ok");
+    assert_eq!(first["body"]["in_reply_to"], 2);
+
+    // the client timed out just before the add_ok arrived and retried
+    node.handle.send(ADD);
+    let replayed = recv(&mut node.handle).await.unwrap();
+    assert_eq!(replayed, first);
+    assert_eq!(node.app.applied.load(Ordering::SeqCst), 5);
+
+    drop(node.handle);
+    node.task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn retry_while_the_handler_runs_is_applied_once() {
+    let mut node = start(0).await;
+
+    node.handle.send(ADD);
+    node.handle.send(ADD);
+    // nothing is answered while the handler waits
+    assert_eq!(recv(&mut node.handle).await, None);
+
+    node.app.permits.add_permits(2);
+    let reply = recv(&mut node.handle).await.unwrap();
+    assert_eq!(reply["body"]["type"], "add_ok");
+    assert_eq!(recv(&mut node.handle).await, None);
+    assert_eq!(node.app.applied.load(Ordering::SeqCst), 5);
+
+    // a later retry still gets the reply of the single application
+    node.handle.send(ADD);
+    assert_eq!(recv(&mut node.handle).await.unwrap(), reply);
+    assert_eq!(node.app.applied.load(Ordering::SeqCst), 5);
+
+    drop(node.handle);
+    node.task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn replies_echo_the_msg_id_text() {
+    let mut node = start(usize::MAX >> 4).await;
+
+    for id in ["1e3", "18446744073709551616"] {
+        node.handle
+            .send(ADD.replace(r#""msg_id":2"#, &format!(r#""msg_id":{id}"#)));
+        let line = timeout(Duration::from_millis(500), node.handle.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(line.contains(&format!(r#""in_reply_to":{id}"#)), "{line}");
+    }
+
+    drop(node.handle);
+    node.task.await.unwrap().unwrap();
+}
+
+#[test]
+fn outbound_dedup_drops_only_identical_lines_within_the_window() {
+    let dedup = OutboundDedup::new(Duration::from_secs(1));
+    let start = Instant::now();
+
+    assert!(dedup.admit(ADD, start));
+    assert!(!dedup.admit(ADD, start + Duration::from_millis(500)));
+    assert!(dedup.admit(&ADD.replace("5", "6"), start + Duration::from_millis(500)));
+    assert!(dedup.admit(ADD, start + Duration::from_secs(1)));
+}
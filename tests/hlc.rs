@@ -0,0 +1,50 @@
+// Timestamps of the hybrid logical clock under virtual time.
+
+use std::time::Duration;
+
+use maelstrom_client::{
+    clock::VirtualClock,
+    hlc::{HybridClock, LOGICAL_BITS},
+};
+
+#[test]
+fn timestamps_increase_while_time_stands_still() {
+    let hlc = HybridClock::new(VirtualClock::new());
+    let first = hlc.now();
+    assert_eq!(hlc.now(), first + 1);
+    assert_eq!(hlc.now(), first + 2);
+}
+
+#[test]
+fn the_wall_part_follows_the_runtime_clock() {
+    let clock = VirtualClock::new();
+    let hlc = HybridClock::new(clock.clone());
+    let before = hlc.now();
+    hlc.now();
+
+    clock.advance(Duration::from_secs(1));
+    let after = hlc.now();
+    assert_eq!(
+        HybridClock::millis(after) - HybridClock::millis(before),
+        1000
+    );
+    // the logical counter starts over in a new millisecond
+    assert_eq!(after % (1 << LOGICAL_BITS), 0);
+}
+
+#[test]
+fn observed_timestamps_are_passed() {
+    let clock = VirtualClock::new();
+    let hlc = HybridClock::new(clock.clone());
+    // a node whose clock runs a minute ahead
+    let remote = hlc.now() + (60_000 << LOGICAL_BITS);
+
+    assert_eq!(hlc.observe(remote), remote + 1);
+    assert_eq!(hlc.now(), remote + 2);
+    // an older timestamp doesn't move it back
+    assert_eq!(hlc.observe(remote - 5), remote + 3);
+
+    // the wall clock takes over once it catches up
+    clock.advance(Duration::from_secs(61));
+    assert!(HybridClock::millis(hlc.now()) > HybridClock::millis(remote));
+}
@@ -0,0 +1,177 @@
+// Kv clients of simulated nodes against the simulator's kv services.
+
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    kv::{Kv, KvError, TxnStore, Versioned},
+    maelstrom::{not_supported, App, Context, Maelstrom},
+    message::{Message, MessageBody},
+    simulator::{SimConfig, Simulator},
+};
+use serde_json::json;
+
+// nodes that only act as kv clients
+struct Idle;
+
+#[async_trait]
+impl App for Idle {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        Err(not_supported(&request.body.msg_type))
+    }
+}
+
+async fn start(nodes: usize) -> Simulator {
+    let config = SimConfig {
+        nodes,
+        ..Default::default()
+    };
+    let idle = |_: &Maelstrom| -> Arc<dyn App> { Arc::new(Idle) };
+    Simulator::start(config, idle).await.unwrap()
+}
+
+#[tokio::test]
+async fn versioned_writes_swap_only_the_version_number() {
+    let sim = start(2).await;
+    let (a, b) = (
+        Kv::lin(sim.node("n1").clone()),
+        Kv::lin(sim.node("n2").clone()),
+    );
+
+    assert_eq!(a.read_versioned::<Vec<i64>>("log").await.unwrap(), None);
+    assert_eq!(a.cas_versioned("log", 0, &vec![1]).await.unwrap(), 1);
+
+    // both read version 1, the second writer loses
+    assert_eq!(a.cas_versioned("log", 1, &vec![1, 2]).await.unwrap(), 2);
+    let lost = b.cas_versioned("log", 1, &vec![1, 3]).await;
+    assert!(
+        matches!(lost, Err(KvError::PreconditionFailed(_))),
+        "{lost:?}"
+    );
+
+    let expected = Versioned {
+        version: 2,
+        value: vec![1, 2],
+    };
+    assert_eq!(b.read_versioned("log").await.unwrap(), Some(expected));
+    // the key itself holds nothing but the number
+    assert_eq!(b.read::<u64>("log").await.unwrap(), 2);
+
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_claimed_version_is_finished_by_the_next_writer() {
+    let sim = start(1).await;
+    let kv = Kv::lin(sim.node("n1").clone());
+
+    assert_eq!(kv.cas_versioned("log", 0, &vec![1]).await.unwrap(), 1);
+    // a writer claimed version 2 and died before bumping the number
+    kv.write("log@2", &vec![1, 2]).await.unwrap();
+
+    let lost = kv.cas_versioned("log", 1, &vec![1, 3]).await;
+    assert!(
+        matches!(lost, Err(KvError::PreconditionFailed(_))),
+        "{lost:?}"
+    );
+    let current = kv.read_versioned::<Vec<i64>>("log").await.unwrap().unwrap();
+    assert_eq!((current.version, current.value), (2, vec![1, 2]));
+
+    sim.shutdown().await.unwrap();
+}
+
+fn txn_store(sim: &Simulator, node_id: &str) -> TxnStore {
+    TxnStore::new(Kv::lin(sim.node(node_id).clone()).scoped("txn"))
+}
+
+#[tokio::test]
+async fn the_second_of_two_conflicting_txns_aborts() {
+    let sim = start(2).await;
+    let (a, b) = (txn_store(&sim, "n1"), txn_store(&sim, "n2"));
+
+    let (first, second) = (a.begin().await.unwrap(), b.begin().await.unwrap());
+    assert_eq!(first.read::<i64>("x").await.unwrap(), None);
+    assert_eq!(second.read::<i64>("x").await.unwrap(), None);
+    first.write("x", &1).unwrap();
+    second.write("x", &2).unwrap();
+    first.commit().await.unwrap();
+    let lost = second.commit().await;
+    assert!(
+        matches!(lost, Err(KvError::PreconditionFailed(_))),
+        "{lost:?}"
+    );
+
+    // a txn begun before the commit can't read what it wrote
+    let stale = b.begin().await.unwrap();
+    let late = a.begin().await.unwrap();
+    late.write("y", &1).unwrap();
+    late.commit().await.unwrap();
+    let x = b.begin().await.unwrap().read::<i64>("x").await.unwrap();
+    assert_eq!(x, Some(1));
+    let conflict = stale.read::<i64>("y").await;
+    assert!(
+        matches!(conflict, Err(KvError::PreconditionFailed(_))),
+        "{conflict:?}"
+    );
+
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_commit_whose_writer_died_is_finished_by_the_next_txn() {
+    let sim = start(2).await;
+    let (a, b) = (txn_store(&sim, "n1"), txn_store(&sim, "n2"));
+
+    let txn = a.begin().await.unwrap();
+    txn.write("x", &1).unwrap();
+    txn.commit().await.unwrap();
+    // n1 swapped in the record of version 2 and died before copying its writes
+    let record = json!({"version": 2, "writes": {"x": 2, "y": 3}});
+    let base = json!({"version": 1});
+    Kv::lin(sim.node("n1").clone())
+        .scoped("txn")
+        .cas("commit", &Some(base), &Some(record), false)
+        .await
+        .unwrap();
+
+    let txn = b.begin().await.unwrap();
+    assert_eq!(txn.version(), 2);
+    assert_eq!(txn.read::<i64>("y").await.unwrap(), Some(3));
+    txn.write("x", &4).unwrap();
+    txn.commit().await.unwrap();
+
+    let txn = a.begin().await.unwrap();
+    assert_eq!(txn.version(), 3);
+    assert_eq!(txn.read::<i64>("x").await.unwrap(), Some(4));
+    assert_eq!(txn.read::<i64>("y").await.unwrap(), Some(3));
+
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn readers_finish_a_pending_commit() {
+    let sim = start(2).await;
+    let store = Kv::lin(sim.node("n1").clone()).scoped("txn");
+    let record = json!({"version": 1, "writes": {"x": 1}});
+    store.write("commit", &record).await.unwrap();
+
+    let txn = txn_store(&sim, "n2").begin().await.unwrap();
+    assert_eq!(txn.version(), 1);
+    assert_eq!(txn.read::<i64>("x").await.unwrap(), Some(1));
+    // the read-only txn copied the writes and cleared them from the record
+    let finished = json!({"version": 1});
+    assert_eq!(
+        store.read::<serde_json::Value>("commit").await.unwrap(),
+        finished
+    );
+    assert_eq!(
+        store
+            .scoped("data")
+            .read::<serde_json::Value>("x")
+            .await
+            .unwrap(),
+        json!({"version": 1, "value": 1})
+    );
+
+    sim.shutdown().await.unwrap();
+}
@@ -0,0 +1,156 @@
+// Last-write-wins registers replicated between simulated nodes.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    clock::VirtualClock,
+    hlc::{HybridClock, LOGICAL_BITS},
+    lww::{LwwMap, Timestamps},
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::{KvKey, Message, MessageBody},
+    simulator::{SimConfig, Simulator},
+};
+use serde_json::json;
+
+struct LwwApp {
+    map: Arc<LwwMap>,
+}
+
+#[async_trait]
+impl App for LwwApp {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        match self.map.on_message(&request).await {
+            Some(ack) => Ok(Some(ack)),
+            None => Err(not_supported(&request.body.msg_type)),
+        }
+    }
+}
+
+struct Cluster {
+    sim: Simulator,
+    clock: Arc<VirtualClock>,
+    maps: Vec<Arc<LwwMap>>,
+}
+
+impl Cluster {
+    async fn start() -> Self {
+        let clock = VirtualClock::new();
+        let config = SimConfig {
+            nodes: 2,
+            clock: Some(clock.clone()),
+            ..Default::default()
+        };
+        let maps = Arc::new(Mutex::new(vec![]));
+        let start = {
+            let maps = maps.clone();
+            move |maelstrom: &Maelstrom| -> Arc<dyn App> {
+                let hlc = HybridClock::new(maelstrom.clock());
+                let map = Arc::new(LwwMap::new(Timestamps::Hybrid(hlc)));
+                let gossip = map.clone();
+                maelstrom.spawn_after(Startup::Init, move |maelstrom| gossip.run(maelstrom));
+                maps.lock().unwrap().push(map.clone());
+                Arc::new(LwwApp { map })
+            }
+        };
+        let sim = Simulator::start(config, start).await.unwrap();
+        let maps = maps.lock().unwrap().clone();
+        Self { sim, clock, maps }
+    }
+
+    async fn write(&self, i: usize, value: i64) {
+        let node = self.sim.node(&format!("n{}", i + 1));
+        self.maps[i].write(node, "x", value.into()).await.unwrap();
+    }
+
+    async fn read(&self, i: usize) -> Option<i64> {
+        self.maps[i].read(&KvKey::from("x")).await?.as_int()
+    }
+}
+
+#[tokio::test]
+async fn the_later_write_wins_on_every_node() {
+    let cluster = Cluster::start().await;
+    cluster.write(1, 2).await;
+    cluster.clock.advance(Duration::from_millis(10));
+    cluster.write(0, 1).await;
+
+    cluster
+        .sim
+        .fast_forward(Duration::from_secs(2))
+        .await
+        .unwrap();
+    assert_eq!(cluster.read(0).await, Some(1));
+    assert_eq!(cluster.read(1).await, Some(1));
+
+    cluster.sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn partitioned_nodes_converge_after_the_heal() {
+    let cluster = Cluster::start().await;
+    cluster.sim.partition(&[&["n1"], &["n2"]]);
+    cluster.write(0, 1).await;
+    cluster.clock.advance(Duration::from_millis(10));
+    cluster.write(1, 2).await;
+
+    cluster
+        .sim
+        .fast_forward(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(cluster.read(0).await, Some(1));
+    assert_eq!(cluster.read(1).await, Some(2));
+
+    cluster.sim.heal();
+    cluster
+        .sim
+        .fast_forward(Duration::from_secs(2))
+        .await
+        .unwrap();
+    assert_eq!(cluster.read(0).await, Some(2));
+    assert_eq!(
+        cluster.maps[0].checksum().await,
+        cluster.maps[1].checksum().await
+    );
+
+    cluster.sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_write_after_gossip_from_a_clock_ahead_still_wins() {
+    let cluster = Cluster::start().await;
+    // n2's clock runs a minute ahead of n1's
+    let ahead = cluster.maps[0]
+        .write(cluster.sim.node("n1"), "x", 0.into())
+        .await
+        .unwrap()
+        .ts
+        + (60_000 << LOGICAL_BITS);
+    let gossip: Message = serde_json::from_value(json!({
+        "src": "n2",
+        "dest": "n1",
+        "body": {
+            "type": "lww_gossip",
+            "msg_id": 1,
+            "entries": [{"key": "x", "stamp": {"ts": ahead, "node": "n2"}, "value": 2}],
+        },
+    }))
+    .unwrap();
+    assert!(cluster.maps[0].on_message(&gossip).await.is_some());
+    assert_eq!(cluster.read(0).await, Some(2));
+
+    // n1 saw the write, so its own next one orders after it
+    let stamp = cluster.maps[0]
+        .write(cluster.sim.node("n1"), "x", 1.into())
+        .await
+        .unwrap();
+    assert!(stamp.ts > ahead);
+    assert_eq!(cluster.read(0).await, Some(1));
+
+    cluster.sim.shutdown().await.unwrap();
+}
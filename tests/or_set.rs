@@ -0,0 +1,123 @@
+// Adds, removes and merges of the observed-remove set.
+
+use std::{io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    maelstrom::{not_supported, App, Context, Maelstrom},
+    message::{Message, MessageBody},
+    or_set::OrSet,
+    simulator::{SimConfig, Simulator},
+};
+use serde_json::{json, Value as Json};
+
+// gossip of the set "s" as another node sends it
+fn gossip(set: &str, elements: Json) -> Message {
+    serde_json::from_value(json!({
+        "src": "n2",
+        "dest": "n1",
+        "body": {"type": "set_gossip", "msg_id": 1, "set": set, "elements": elements},
+    }))
+    .unwrap()
+}
+
+fn add(element: i64, node: &str, seq: u64) -> Message {
+    let tagged = json!([{"element": element, "tag": {"node": node, "seq": seq}}]);
+    gossip("s/adds", tagged)
+}
+
+fn remove(node: &str, seq: u64) -> Message {
+    gossip("s/removes", json!([{"node": node, "seq": seq}]))
+}
+
+async fn elements(set: &OrSet<i64>) -> Vec<i64> {
+    let mut elements: Vec<i64> = set.elements().await.into_iter().collect();
+    elements.sort_unstable();
+    elements
+}
+
+struct OrSetApp {
+    set: Arc<OrSet<i64>>,
+}
+
+#[async_trait]
+impl App for OrSetApp {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        match self.set.on_message(&request).await {
+            Some(ack) => Ok(Some(ack)),
+            None => Err(not_supported(&request.body.msg_type)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn an_add_the_remove_didnt_see_survives_it() {
+    let set = OrSet::new("s");
+    for message in [add(1, "n2", 1), add(2, "n2", 2)] {
+        assert!(set.on_message(&message).await.is_some());
+    }
+
+    assert!(set.remove(&1).await);
+    assert!(!set.remove(&3).await);
+    assert_eq!(elements(&set).await, [2]);
+
+    // added on n3 concurrently with the remove
+    set.on_message(&add(1, "n3", 1)).await;
+    assert_eq!(elements(&set).await, [1, 2]);
+}
+
+#[tokio::test]
+async fn merges_in_either_order_agree() {
+    let events = [add(1, "n2", 1), remove("n2", 1), add(2, "n3", 1)];
+    let (forward, backward) = (OrSet::new("s"), OrSet::new("s"));
+    for message in &events {
+        forward.on_message(message).await;
+    }
+    // the remove arrives before the add it retires
+    for message in events.iter().rev() {
+        backward.on_message(message).await;
+    }
+
+    assert_eq!(elements(&forward).await, [2]);
+    assert_eq!(elements(&backward).await, [2]);
+    assert_eq!(forward.checksum().await, backward.checksum().await);
+}
+
+#[tokio::test]
+async fn a_restarted_node_takes_fresh_tags() {
+    // the set of a run of n1 before the current one
+    let earlier = OrSet::new("s");
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    let sets = Arc::new(std::sync::Mutex::new(vec![]));
+    let start = {
+        let sets = sets.clone();
+        move |_: &Maelstrom| -> Arc<dyn App> {
+            let set = Arc::new(OrSet::new("s"));
+            sets.lock().unwrap().push(set.clone());
+            Arc::new(OrSetApp { set })
+        }
+    };
+    let config = SimConfig {
+        nodes: 2,
+        ..Default::default()
+    };
+    let sim = Simulator::start(config, start).await.unwrap();
+    let (n1, n2) = {
+        let sets = sets.lock().unwrap();
+        (sets[0].clone(), sets[1].clone())
+    };
+
+    // the earlier run added and removed 1, n2 saw both
+    earlier.add(sim.node("n1"), 1).await;
+    earlier.remove(&1).await;
+    earlier.flush(sim.node("n1")).await.unwrap();
+    // the current run adds 1 again
+    n1.add(sim.node("n1"), 1).await;
+    n1.flush(sim.node("n1")).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(elements(&n2).await, [1]);
+
+    sim.shutdown().await.unwrap();
+}
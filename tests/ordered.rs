@@ -0,0 +1,108 @@
+// Sequencing, acknowledgements and resends of the per destination FIFO channels.
+
+use std::time::{Duration, Instant};
+
+use maelstrom_client::{
+    message::{Message, MessageBody, MessageType},
+    ordered::OrderedQueues,
+};
+
+fn body() -> MessageBody {
+    MessageBody::with_type(MessageType::TopologyOk)
+}
+
+fn message(seq: u64) -> Message {
+    let mut body = body();
+    body.seq = Some(seq);
+    Message {
+        src: "n1".into(),
+        dest: "n2".into(),
+        body,
+    }
+}
+
+fn seqs(messages: &[Message]) -> Vec<u64> {
+    messages
+        .iter()
+        .filter_map(|message| message.body.seq)
+        .collect()
+}
+
+#[test]
+fn pushed_messages_are_numbered_per_destination() {
+    let queues = OrderedQueues::default();
+    let now = Instant::now();
+    assert_eq!(queues.push("n2", body(), now).seq, Some(1));
+    assert_eq!(queues.push("n2", body(), now).seq, Some(2));
+    assert_eq!(queues.push("n3", body(), now).seq, Some(1));
+    assert_eq!((queues.backlog("n2"), queues.total_backlog()), (2, 3));
+}
+
+#[test]
+fn out_of_order_messages_wait_for_the_hole() {
+    let queues = OrderedQueues::default();
+
+    let (ack, ready) = queues.receive(message(2), 2);
+    assert_eq!((ack, seqs(&ready)), (0, vec![]));
+    let (ack, ready) = queues.receive(message(3), 3);
+    assert_eq!((ack, seqs(&ready)), (0, vec![]));
+
+    // the missing first one releases everything buffered behind it
+    let (ack, ready) = queues.receive(message(1), 1);
+    assert_eq!((ack, seqs(&ready)), (3, vec![1, 2, 3]));
+}
+
+#[test]
+fn duplicates_are_dropped() {
+    let queues = OrderedQueues::default();
+    queues.receive(message(1), 1);
+    queues.receive(message(3), 3);
+
+    let (ack, ready) = queues.receive(message(1), 1);
+    assert_eq!((ack, seqs(&ready)), (1, vec![]));
+    // a duplicate of a buffered one is still delivered once
+    queues.receive(message(3), 3);
+    let (ack, ready) = queues.receive(message(2), 2);
+    assert_eq!((ack, seqs(&ready)), (3, vec![2, 3]));
+}
+
+#[test]
+fn acks_are_cumulative() {
+    let queues = OrderedQueues::default();
+    let now = Instant::now();
+    for _ in 0..4 {
+        queues.push("n2", body(), now);
+    }
+
+    queues.ack("n2", 3);
+    assert_eq!(queues.backlog("n2"), 1);
+    // a late ack of an older message changes nothing
+    queues.ack("n2", 1);
+    assert_eq!(queues.backlog("n2"), 1);
+    queues.ack("n2", 4);
+    assert_eq!(queues.backlog("n2"), 0);
+}
+
+#[test]
+fn due_resends_only_the_unacked_tail() {
+    let queues = OrderedQueues::default();
+    let interval = Duration::from_millis(100);
+    let sent = Instant::now();
+    for _ in 0..3 {
+        queues.push("n2", body(), sent);
+    }
+    queues.ack("n2", 1);
+
+    assert!(queues.due(interval, sent + interval / 2).is_empty());
+    let due: Vec<_> = queues
+        .due(interval, sent + interval)
+        .into_iter()
+        .map(|(dest, body)| (dest.to_string(), body.seq))
+        .collect();
+    assert_eq!(
+        due,
+        [("n2".to_owned(), Some(2)), ("n2".to_owned(), Some(3))]
+    );
+    // resent just now, not due again until another interval passed
+    assert!(queues.due(interval, sent + interval).is_empty());
+}
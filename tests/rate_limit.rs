@@ -0,0 +1,106 @@
+// Token buckets and queues of the outbound rate limiter.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use maelstrom_client::rate_limit::{Priority, RateLimit, RateLimiter};
+
+fn global(per_second: f64, burst: u32) -> RateLimiter {
+    let limit = RateLimit { per_second, burst };
+    RateLimiter::new(Some(limit), None, HashMap::new()).unwrap()
+}
+
+// drains the limiter until it is closed and empty, returns what it wrote
+async fn drain(limiter: &RateLimiter) -> Vec<(Priority, String)> {
+    let written = Mutex::new(vec![]);
+    limiter
+        .run(|priority, line| written.lock().unwrap().push((priority, line)))
+        .await;
+    written.into_inner().unwrap()
+}
+
+#[test]
+fn a_burst_goes_out_at_once() {
+    let limiter = global(1.0, 3);
+    for i in 0..3 {
+        let line = format!("m{i}");
+        assert_eq!(
+            limiter.submit("n2", Priority::Low, line.to_owned()),
+            Some(line)
+        );
+    }
+    assert_eq!(limiter.submit("n2", Priority::Low, "m3".to_owned()), None);
+    assert_eq!(limiter.backlog(), 1);
+}
+
+#[test]
+fn no_limit_means_no_limiter() {
+    assert!(RateLimiter::new(None, None, HashMap::new()).is_none());
+}
+
+#[tokio::test]
+async fn queued_lines_wait_for_the_refill() {
+    let limiter = global(50.0, 1);
+    assert!(limiter
+        .submit("n2", Priority::Low, "m0".to_owned())
+        .is_some());
+    for i in 1..4 {
+        assert_eq!(limiter.submit("n2", Priority::Low, format!("m{i}")), None);
+    }
+
+    let started = Instant::now();
+    limiter.close();
+    let written = drain(&limiter).await;
+    // a token every 20ms
+    assert!(
+        started.elapsed() >= Duration::from_millis(50),
+        "{:?}",
+        started.elapsed()
+    );
+    let lines: Vec<String> = written.into_iter().map(|(_, line)| line).collect();
+    assert_eq!(lines, ["m1", "m2", "m3"]);
+}
+
+#[tokio::test]
+async fn high_priority_drains_before_low() {
+    let limiter = global(1000.0, 1);
+    assert!(limiter
+        .submit("n2", Priority::Low, "spent".to_owned())
+        .is_some());
+    assert_eq!(
+        limiter.submit("n2", Priority::Low, "gossip".to_owned()),
+        None
+    );
+    assert_eq!(
+        limiter.submit("c1", Priority::High, "reply".to_owned()),
+        None
+    );
+
+    limiter.close();
+    let written = drain(&limiter).await;
+    assert_eq!(
+        written,
+        [
+            (Priority::High, "reply".to_owned()),
+            (Priority::Low, "gossip".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn close_waits_for_the_queue_to_drain() {
+    let limiter = global(200.0, 1);
+    assert!(limiter
+        .submit("n2", Priority::Low, "m0".to_owned())
+        .is_some());
+    for i in 1..6 {
+        assert_eq!(limiter.submit("n2", Priority::Low, format!("m{i}")), None);
+    }
+
+    limiter.close();
+    assert_eq!(drain(&limiter).await.len(), 5);
+    assert_eq!(limiter.backlog(), 0);
+}
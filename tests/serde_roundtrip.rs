@@ -0,0 +1,159 @@
+// Round trips of the hand written and untagged serde impls in `message`.
+
+use std::collections::HashMap;
+
+use maelstrom_client::{
+    message::{Key, Message, MessageBody, MessageType, Transaction, Value},
+    schema,
+    self_test::{samples, self_test},
+};
+use proptest::prelude::*;
+
+// non-finite floats serialize as null, so they can't come back
+fn arb_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::None),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::Int),
+        (-1e12..1e12f64).prop_map(Value::Float),
+        ".*".prop_map(Value::String),
+    ];
+    leaf.prop_recursive(3, 32, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Vec),
+            prop::collection::hash_map(".*", inner, 0..4).prop_map(Value::Map),
+        ]
+    })
+}
+
+fn arb_key() -> impl Strategy<Value = Key> {
+    prop_oneof![any::<u64>().prop_map(Key::Int), ".*".prop_map(Key::String)]
+}
+
+fn arb_transaction() -> impl Strategy<Value = Transaction> {
+    prop_oneof![
+        (arb_key(), arb_value()).prop_map(|(key, val)| Transaction::Read { key, val }),
+        (arb_key(), any::<i64>()).prop_map(|(key, value)| Transaction::Write { key, value }),
+        (arb_key(), any::<i64>()).prop_map(|(key, value)| Transaction::Append { key, value }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn value_round_trips(value in arb_value()) {
+        let json = serde_json::to_string(&value).unwrap();
+        prop_assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn key_round_trips(key in arb_key()) {
+        let json = serde_json::to_string(&key).unwrap();
+        prop_assert_eq!(serde_json::from_str::<Key>(&json).unwrap(), key);
+    }
+
+    #[test]
+    fn transaction_round_trips(txn in prop::collection::vec(arb_transaction(), 0..8)) {
+        let json = serde_json::to_string(&txn).unwrap();
+        prop_assert_eq!(serde_json::from_str::<Vec<Transaction>>(&json).unwrap(), txn);
+    }
+}
+
+#[test]
+fn samples_round_trip() {
+    assert!(!samples().is_empty());
+    self_test().unwrap();
+}
+
+// txn bodies as sent by Maelstrom's txn-rw-register and txn-list-append workloads
+#[test]
+fn maelstrom_txn_payloads() {
+    let payloads = [
+        r#"{"src":"c4","dest":"n0","body":{"type":"txn","msg_id":3,"txn":[["r",9,null],["w",8,1],["r",8,null]]}}"#,
+        r#"{"src":"c7","dest":"n1","body":{"type":"txn","msg_id":12,"txn":[["append",3,2],["r",3,null],["append",4,1]]}}"#,
+        r#"{"src":"n1","dest":"c7","body":{"type":"txn_ok","in_reply_to":12,"txn":[["append",3,2],["r",3,[1,2]],["append",4,1]]}}"#,
+    ];
+    for payload in payloads {
+        let message: Message = serde_json::from_str(payload).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), expected);
+    }
+
+    let message: Message = serde_json::from_str(payloads[2]).unwrap();
+    let MessageType::TxnOk { txn } = message.body.msg_type else {
+        panic!("expected txn_ok, got {:?}", message.body.msg_type);
+    };
+    assert_eq!(
+        txn[1],
+        Transaction::Read {
+            key: Key::Int(3),
+            val: vec![1, 2].into(),
+        }
+    );
+}
+
+#[test]
+fn malformed_transactions_are_rejected() {
+    for txn in [
+        r#"[["x",1,2]]"#,
+        r#"[["r",1]]"#,
+        r#"[["w",1,"a"]]"#,
+        r#"[[]]"#,
+    ] {
+        assert!(
+            serde_json::from_str::<Vec<Transaction>>(txn).is_err(),
+            "{txn} deserialized"
+        );
+    }
+}
+
+#[test]
+fn map_values_keep_nesting() {
+    let json = r#"{"a":[1,"x",true,1.5,null],"b":{"c":-2}}"#;
+    let value: Value = serde_json::from_str(json).unwrap();
+    let map = value.as_map().unwrap();
+    assert_eq!(map["a"].as_list().unwrap().len(), 5);
+    assert_eq!(
+        map["b"],
+        Value::Map(HashMap::from([("c".to_owned(), Value::Int(-2))]))
+    );
+}
+
+// `add` is shared by the counter and set workloads, the field at fault is the
+// one of the workload the body is closest to
+#[test]
+fn add_fields_are_checked_against_their_workload() {
+    let delta = serde_json::json!({"type": "add", "msg_id": 1, "delta": "x"});
+    let error = schema::check(&delta).unwrap().to_string();
+    assert_eq!(
+        error,
+        "invalid field `delta`: expected a number, found a string"
+    );
+
+    let element = serde_json::json!({"type": "add", "msg_id": 1, "element": [5]});
+    let error = schema::check(&element).unwrap().to_string();
+    assert_eq!(
+        error,
+        "invalid field `element`: expected a number, found an array"
+    );
+
+    let valid = serde_json::json!({"type": "add", "msg_id": 1, "delta": 5});
+    assert_eq!(schema::check(&valid), None);
+}
+
+// ids are echoed in replies, so they come back in the exact text they came in
+#[test]
+fn msg_ids_keep_their_text() {
+    for id in ["7", "-3", "-0", "1.5", "1e3", "18446744073709551616", "1.0"] {
+        let line = format!(r#"{{"src":"c1","dest":"n1","body":{{"type":"read","msg_id":{id}}}}}"#);
+        let message: Message = serde_json::from_str(&line).unwrap();
+        let reply = Message::reply_to(&message, MessageBody::with_type(MessageType::TopologyOk));
+        let json = serde_json::to_string(&reply.body).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"in_reply_to":{id},"type":"topology_ok"}}"#)
+        );
+    }
+
+    let line = r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":"7"}}"#;
+    assert!(serde_json::from_str::<Message>(line).is_err());
+}
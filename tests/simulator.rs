@@ -0,0 +1,319 @@
+// Whole clusters run in-process by the simulator, with faulty links.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    broadcast::{
+        strategy::{Batched, NewestFirst, Overlay},
+        BroadcastApp,
+    },
+    checksum::{checksum, exchange_checksums, ChecksumExchange, StateChecksum},
+    clock::VirtualClock,
+    gossip_set::GossipSet,
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::{
+        AddRequest, Message, MessageBody, MessageType, NodeId, Payload, ReadReply, ReadRequest,
+        Value,
+    },
+    simulator::{LinkConfig, SimConfig, Simulator},
+};
+use serde_json::json;
+
+// a g-set node: adds go to the local replica, anti-entropy spreads them
+struct SetApp {
+    set: Arc<GossipSet<i64>>,
+}
+
+impl SetApp {
+    fn start(maelstrom: &Maelstrom) -> Arc<dyn App> {
+        let set = Arc::new(GossipSet::new("s"));
+        let gossip = set.clone();
+        maelstrom.spawn_after(Startup::Init, move |maelstrom| gossip.run(maelstrom));
+        Arc::new(SetApp { set })
+    }
+}
+
+#[async_trait]
+impl App for SetApp {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        if let Some(ack) = self.set.on_message(&request).await {
+            return Ok(Some(ack));
+        }
+        let body = match &request.body.msg_type {
+            MessageType::Add(AddRequest::Element { element }) => {
+                self.set.insert([*element]).await;
+                MessageBody::with_type(MessageType::AddOk)
+            }
+            MessageType::Read(ReadRequest::Client) => {
+                let mut elements: Vec<i64> = self.set.elements().await.into_iter().collect();
+                elements.sort_unstable();
+                MessageBody::read_ok_value(elements)
+            }
+            _ => return Err(not_supported(&request.body.msg_type)),
+        };
+        Ok(Some(body))
+    }
+}
+
+async fn read(sim: &Simulator, node_id: &str) -> Value {
+    let reply = sim
+        .client()
+        .rpc(node_id, MessageType::Read(ReadRequest::Client))
+        .await
+        .unwrap();
+    match reply.msg_type {
+        MessageType::ReadOk(ReadReply::Value { value }) => value,
+        other => panic!("unexpected reply {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn set_converges_after_hours_of_partition_in_virtual_time() {
+    let clock = VirtualClock::new();
+    let config = SimConfig {
+        nodes: 3,
+        link: LinkConfig {
+            latency: Duration::from_millis(5),
+            jitter: Duration::from_millis(20),
+            loss: 0.3,
+        },
+        clock: Some(clock.clone()),
+        ..Default::default()
+    };
+    let sim = Simulator::start(config, SetApp::start).await.unwrap();
+    let client = sim.client();
+
+    sim.partition(&[&["n1"], &["n2", "n3"]]);
+    for element in 0..30 {
+        let node_id = &sim.node_ids()[element as usize % 3];
+        let add = MessageType::Add(AddRequest::Element { element });
+        client.rpc(node_id, add).await.unwrap();
+    }
+
+    // the majority side agrees while n1 is cut off
+    sim.fast_forward(Duration::from_secs(3600)).await.unwrap();
+    assert_eq!(read(&sim, "n2").await, read(&sim, "n3").await);
+    assert_ne!(read(&sim, "n1").await, read(&sim, "n2").await);
+    let partitioned = clock.sleepers();
+
+    sim.heal();
+    sim.fast_forward(Duration::from_secs(3600)).await.unwrap();
+    let expected = Value::Vec((0..30).map(Value::Int).collect());
+    for node_id in ["n1", "n2", "n3"] {
+        assert_eq!(read(&sim, node_id).await, expected, "{node_id}");
+    }
+
+    // timers don't pile up over the hours, e.g. rpcs to unreachable peers
+    // give up and are not leaked
+    let healed = clock.sleepers();
+    assert!(partitioned < 100, "{partitioned} pending sleeps");
+    assert!(healed < 100, "{healed} pending sleeps");
+
+    sim.shutdown().await.unwrap();
+}
+
+fn broadcast_app(maelstrom: &Maelstrom) -> Arc<dyn App> {
+    let strategy = Batched::new(Overlay::Topology, Box::new(NewestFirst));
+    let app = Arc::new(BroadcastApp::new(Box::new(strategy)));
+    let gossip = app.clone();
+    maelstrom.spawn_after(Startup::Topology, move |maelstrom| {
+        gossip.run_gossip(maelstrom)
+    });
+    app
+}
+
+async fn read_messages(sim: &Simulator, node_id: &str) -> HashSet<Payload> {
+    let reply = sim
+        .client()
+        .rpc(node_id, MessageType::Read(ReadRequest::Client))
+        .await
+        .unwrap();
+    match reply.msg_type {
+        MessageType::ReadOk(ReadReply::Messages { messages }) => messages,
+        other => panic!("unexpected reply {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn broadcast_converges_after_loss_and_a_partition() {
+    let config = SimConfig {
+        nodes: 3,
+        link: LinkConfig {
+            latency: Duration::from_millis(1),
+            jitter: Duration::from_millis(5),
+            loss: 0.25,
+        },
+        seed: 7,
+        ..Default::default()
+    };
+    let sim = Simulator::start(config, broadcast_app).await.unwrap();
+    let client = sim.client();
+
+    // a line n1 - n2 - n3, so n2 relays between the ends
+    let topology: HashMap<NodeId, Vec<NodeId>> = HashMap::from([
+        ("n1".into(), vec!["n2".into()]),
+        ("n2".into(), vec!["n1".into(), "n3".into()]),
+        ("n3".into(), vec!["n2".into()]),
+    ]);
+    for node_id in sim.node_ids() {
+        let message = MessageType::Topology {
+            topology: topology.clone(),
+        };
+        client.rpc(node_id, message).await.unwrap();
+    }
+
+    sim.partition(&[&["n1"], &["n2", "n3"]]);
+    for i in 0..12 {
+        let node_id = &sim.node_ids()[i % 3];
+        let message = MessageType::Broadcast {
+            message: Payload(json!(i)),
+        };
+        client.rpc(node_id, message).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(read_messages(&sim, "n1").await.len() < 12);
+
+    sim.heal();
+    let expected: HashSet<Payload> = (0..12).map(|i| Payload(json!(i))).collect();
+    let mut converged = false;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut all = true;
+        for node_id in ["n1", "n2", "n3"] {
+            all &= read_messages(&sim, node_id).await == expected;
+        }
+        if all {
+            converged = true;
+            break;
+        }
+    }
+    assert!(converged, "nodes didn't converge within 10s of healing");
+
+    sim.shutdown().await.unwrap();
+}
+
+// a set that isn't replicated, so nodes only agree if clients add the same
+struct LocalSetApp {
+    set: Mutex<HashSet<i64>>,
+    checksums: ChecksumExchange,
+}
+
+#[async_trait]
+impl StateChecksum for LocalSetApp {
+    async fn state_checksum(&self) -> u64 {
+        checksum(self.set.lock().unwrap().iter())
+    }
+
+    fn checksums(&self) -> &ChecksumExchange {
+        &self.checksums
+    }
+}
+
+#[async_trait]
+impl App for LocalSetApp {
+    async fn handler(&self, ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        match &request.body.msg_type {
+            MessageType::Add(AddRequest::Element { element }) => {
+                self.set.lock().unwrap().insert(*element);
+                Ok(Some(MessageBody::with_type(MessageType::AddOk)))
+            }
+            MessageType::StateChecksum { checksum: theirs } => {
+                let ours = self.state_checksum().await;
+                self.checksums.compare(&ctx, &request.src, *theirs, ours);
+                Ok(None)
+            }
+            _ => Err(not_supported(&request.body.msg_type)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn checksums_are_compared_while_the_nodes_run() {
+    let clock = VirtualClock::new();
+    let config = SimConfig {
+        nodes: 2,
+        clock: Some(clock.clone()),
+        ..Default::default()
+    };
+    let apps = Arc::new(Mutex::new(vec![]));
+    let start = {
+        let apps = apps.clone();
+        move |maelstrom: &Maelstrom| -> Arc<dyn App> {
+            let app = Arc::new(LocalSetApp {
+                set: Default::default(),
+                checksums: Default::default(),
+            });
+            apps.lock().unwrap().push(app.clone());
+            let checksums = app.clone();
+            maelstrom.spawn_after(Startup::Init, move |maelstrom| {
+                exchange_checksums(checksums, maelstrom)
+            });
+            app
+        }
+    };
+    let sim = Simulator::start(config, start).await.unwrap();
+    let client = sim.client();
+    let mismatched = |i: usize| apps.lock().unwrap()[i].checksums.mismatched();
+
+    let add = MessageType::Add(AddRequest::Element { element: 1 });
+    client.rpc("n1", add.clone()).await.unwrap();
+    sim.fast_forward(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(mismatched(0), ["n2"]);
+    assert_eq!(mismatched(1), ["n1"]);
+
+    // once both settle on the same state the mismatch is gone on both sides
+    client.rpc("n2", add).await.unwrap();
+    sim.fast_forward(Duration::from_secs(5)).await.unwrap();
+    assert!(mismatched(0).is_empty());
+    assert!(mismatched(1).is_empty());
+
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn every_node_counts_its_own_metrics() {
+    let config = SimConfig {
+        nodes: 2,
+        ..Default::default()
+    };
+    let start = |_: &Maelstrom| -> Arc<dyn App> {
+        Arc::new(LocalSetApp {
+            set: Default::default(),
+            checksums: Default::default(),
+        })
+    };
+    let sim = Simulator::start(config, start).await.unwrap();
+
+    let body = MessageBody::with_type(MessageType::StateChecksum { checksum: 0 });
+    sim.node("n1").send("n2", body).unwrap();
+    let sent = |node_id| sim.node(node_id).metrics().counter("net.peer_messages");
+    assert_eq!((sent("n1"), sent("n2")), (1, 0));
+
+    sim.shutdown().await.unwrap();
+}
+
+// the msgs-per-op line of each broadcast node divides by its own client ops
+#[tokio::test]
+async fn broadcast_nodes_count_their_own_client_ops() {
+    let sim = Simulator::start(SimConfig::default(), broadcast_app)
+        .await
+        .unwrap();
+    let client = sim.client();
+    for (i, node_id) in ["n1", "n1", "n1", "n2"].into_iter().enumerate() {
+        let message = MessageType::Broadcast {
+            message: Payload(json!(i)),
+        };
+        client.rpc(node_id, message).await.unwrap();
+    }
+
+    let ops = |node_id| sim.node(node_id).metrics().counter("broadcast.client_ops");
+    assert_eq!((ops("n1"), ops("n2"), ops("n3")), (3, 1, 0));
+
+    sim.shutdown().await.unwrap();
+}
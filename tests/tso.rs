@@ -0,0 +1,94 @@
+// Timestamps of lin-tso, one rpc per timestamp or split into local blocks.
+
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    maelstrom::{not_supported, App, Context, Maelstrom},
+    message::{Message, MessageBody},
+    simulator::{SimConfig, Simulator},
+    tso::Tso,
+};
+
+struct Idle;
+
+#[async_trait]
+impl App for Idle {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        Err(not_supported(&request.body.msg_type))
+    }
+}
+
+async fn start() -> Simulator {
+    let config = SimConfig {
+        nodes: 2,
+        ..Default::default()
+    };
+    let idle = |_: &Maelstrom| -> Arc<dyn App> { Arc::new(Idle) };
+    Simulator::start(config, idle).await.unwrap()
+}
+
+fn blocks(sim: &Simulator, node_id: &str) -> u64 {
+    sim.node(node_id).metrics().counter("tso.blocks")
+}
+
+#[tokio::test]
+async fn every_timestamp_is_an_rpc_by_default() {
+    let sim = start().await;
+    let tso = Tso::new(sim.node("n1").clone());
+    assert_eq!(tso.block_size(), 1);
+    assert_eq!(tso.ts().await.unwrap(), 1);
+    assert_eq!(tso.ts().await.unwrap(), 2);
+    assert_eq!(blocks(&sim, "n1"), 0);
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_batched_client_hands_out_a_block_per_rpc() {
+    let sim = start().await;
+    let tso = Tso::batched(sim.node("n1").clone(), 4);
+    let mut timestamps = vec![];
+    for _ in 0..8 {
+        timestamps.push(tso.ts().await.unwrap());
+    }
+    // oracle timestamps 1 and 2, each times the block size
+    assert_eq!(timestamps, (4..12).collect::<Vec<_>>());
+    assert_eq!(blocks(&sim, "n1"), 2);
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_a_single_fetch() {
+    let sim = start().await;
+    let tso = Tso::batched(sim.node("n1").clone(), 4);
+    let calls: Vec<_> = (0..4)
+        .map(|_| {
+            let tso = tso.clone();
+            tokio::spawn(async move { tso.ts().await.unwrap() })
+        })
+        .collect();
+    let mut timestamps = vec![];
+    for call in calls {
+        timestamps.push(call.await.unwrap());
+    }
+    timestamps.sort_unstable();
+    assert_eq!(timestamps, [4, 5, 6, 7]);
+    assert_eq!(blocks(&sim, "n1"), 1);
+    sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_block_fetched_later_orders_after_earlier_blocks() {
+    let sim = start().await;
+    let (n1, n2) = (
+        Tso::batched(sim.node("n1").clone(), 10),
+        Tso::batched(sim.node("n2").clone(), 10),
+    );
+    let first = n1.ts().await.unwrap();
+    let second = n2.ts().await.unwrap();
+    // after the whole block of n1, not only the timestamp it handed out
+    assert!(second >= first + 10, "{first} {second}");
+    // while n1 still serves its own block locally
+    assert_eq!(n1.ts().await.unwrap(), first + 1);
+    sim.shutdown().await.unwrap();
+}
@@ -0,0 +1,200 @@
+// Two-phase commit between simulated nodes, n1 coordinates and the others
+// take part.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use maelstrom_client::{
+    clock::VirtualClock,
+    maelstrom::{not_supported, App, Context, Maelstrom, Startup},
+    message::{Message, MessageBody, NodeId},
+    simulator::{LinkConfig, SimConfig, Simulator},
+    two_phase::{Coordinator, Outcome, Participant, Resource, TxnId},
+};
+use serde_json::{json, Value as Json};
+
+// records what it was asked to do, votes no on writes of "no"
+#[derive(Default)]
+struct Store {
+    prepared: Mutex<Vec<TxnId>>,
+    committed: Mutex<Vec<Json>>,
+    aborted: Mutex<Vec<TxnId>>,
+}
+
+#[async_trait]
+impl Resource for Store {
+    async fn prepare(&self, txn_id: &TxnId, writes: &Json) -> io::Result<bool> {
+        self.prepared.lock().unwrap().push(txn_id.to_owned());
+        Ok(*writes != json!("no"))
+    }
+
+    async fn commit(&self, _txn_id: &TxnId, writes: &Json) -> io::Result<()> {
+        self.committed.lock().unwrap().push(writes.to_owned());
+        Ok(())
+    }
+
+    async fn abort(&self, txn_id: &TxnId) -> io::Result<()> {
+        self.aborted.lock().unwrap().push(txn_id.to_owned());
+        Ok(())
+    }
+}
+
+struct TxnNode {
+    coordinator: Arc<Coordinator>,
+    participant: Arc<Participant<Store>>,
+}
+
+#[async_trait]
+impl App for TxnNode {
+    async fn handler(&self, _ctx: Context, request: Message) -> io::Result<Option<MessageBody>> {
+        if let Some(reply) = self.coordinator.handle(&request) {
+            return Ok(Some(reply));
+        }
+        match self.participant.handle(&request).await {
+            Some(reply) => reply.map(Some),
+            None => Err(not_supported(&request.body.msg_type)),
+        }
+    }
+}
+
+struct Cluster {
+    sim: Simulator,
+    nodes: Arc<Mutex<Vec<Arc<TxnNode>>>>,
+}
+
+impl Cluster {
+    async fn start(link: LinkConfig, delivery_timeout: Duration) -> Self {
+        let config = SimConfig {
+            nodes: 3,
+            link,
+            clock: Some(VirtualClock::new()),
+            ..Default::default()
+        };
+        let nodes = Arc::new(Mutex::new(vec![]));
+        let start = {
+            let nodes = nodes.clone();
+            move |maelstrom: &Maelstrom| -> Arc<dyn App> {
+                let coordinator =
+                    Coordinator::new(maelstrom.clone()).with_delivery_timeout(delivery_timeout);
+                let participant = Arc::new(Participant::new(maelstrom.clone(), Store::default()));
+                let recovery = participant.clone();
+                maelstrom.spawn_after(Startup::Init, move |_| recovery.run());
+                let node = Arc::new(TxnNode {
+                    coordinator: Arc::new(coordinator),
+                    participant,
+                });
+                nodes.lock().unwrap().push(node.clone());
+                node
+            }
+        };
+        let sim = Simulator::start(config, start).await.unwrap();
+        Self { sim, nodes }
+    }
+
+    // starts a transaction on n1, it runs as virtual time passes
+    fn execute(&self, writes: &[(&str, Json)]) -> tokio::task::JoinHandle<io::Result<Outcome>> {
+        let coordinator = self.nodes.lock().unwrap()[0].coordinator.clone();
+        let writes: HashMap<NodeId, Json> = writes
+            .iter()
+            .map(|(node_id, writes)| (NodeId(node_id.to_string()), writes.to_owned()))
+            .collect();
+        tokio::spawn(async move { coordinator.execute(writes).await })
+    }
+
+    fn store(&self, i: usize) -> Arc<Participant<Store>> {
+        self.nodes.lock().unwrap()[i].participant.clone()
+    }
+
+    fn prepared(&self, i: usize) -> usize {
+        self.store(i).resource().prepared.lock().unwrap().len()
+    }
+
+    fn committed(&self, i: usize) -> Vec<Json> {
+        self.store(i)
+            .resource()
+            .committed
+            .lock()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn aborted(&self, i: usize) -> usize {
+        self.store(i).resource().aborted.lock().unwrap().len()
+    }
+}
+
+#[tokio::test]
+async fn every_participant_commits() {
+    let cluster = Cluster::start(Default::default(), Duration::from_secs(5)).await;
+    let sim = &cluster.sim;
+
+    let txn = cluster.execute(&[("n2", json!([1])), ("n3", json!([2]))]);
+    sim.fast_forward(Duration::from_secs(2)).await.unwrap();
+    assert_eq!(txn.await.unwrap().unwrap(), Outcome::Committed);
+
+    assert_eq!(cluster.committed(1), [json!([1])]);
+    assert_eq!(cluster.committed(2), [json!([2])]);
+    cluster.sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_no_vote_aborts_everywhere() {
+    let cluster = Cluster::start(Default::default(), Duration::from_secs(5)).await;
+    let sim = &cluster.sim;
+
+    let txn = cluster.execute(&[("n2", json!([1])), ("n3", json!("no"))]);
+    sim.fast_forward(Duration::from_secs(2)).await.unwrap();
+    assert_eq!(txn.await.unwrap().unwrap(), Outcome::Aborted);
+
+    assert!(cluster.committed(1).is_empty());
+    assert!(cluster.committed(2).is_empty());
+    // n3 voted no and has nothing to undo
+    assert_eq!(cluster.aborted(1), 1);
+    assert_eq!(cluster.aborted(2), 0);
+    cluster.sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_vote_that_times_out_aborts() {
+    let cluster = Cluster::start(Default::default(), Duration::from_secs(5)).await;
+    let sim = &cluster.sim;
+    sim.partition(&[&["n1", "n2"], &["n3"]]);
+
+    let txn = cluster.execute(&[("n2", json!([1])), ("n3", json!([2]))]);
+    sim.fast_forward(Duration::from_secs(2)).await.unwrap();
+    assert_eq!(txn.await.unwrap().unwrap(), Outcome::Aborted);
+
+    assert!(cluster.committed(1).is_empty());
+    assert_eq!(cluster.aborted(1), 1);
+    assert_eq!(cluster.prepared(2), 0);
+    cluster.sim.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_participant_in_doubt_asks_for_the_outcome() {
+    let link = LinkConfig {
+        latency: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let cluster = Cluster::start(link, Duration::from_millis(100)).await;
+    let sim = &cluster.sim;
+
+    let txn = cluster.execute(&[("n2", json!([1]))]);
+    // n2 prepares and votes, then loses n1 before the commit reaches it
+    sim.fast_forward(Duration::from_millis(10)).await.unwrap();
+    sim.partition(&[&["n1"], &["n2"]]);
+    sim.fast_forward(Duration::from_secs(2)).await.unwrap();
+    assert_eq!(txn.await.unwrap().unwrap(), Outcome::Committed);
+    assert!(cluster.committed(1).is_empty());
+
+    // the coordinator gave up delivering, n2 asks with a status request
+    sim.heal();
+    sim.fast_forward(Duration::from_secs(2)).await.unwrap();
+    assert_eq!(cluster.committed(1), [json!([1])]);
+    cluster.sim.shutdown().await.unwrap();
+}
@@ -0,0 +1,114 @@
+// Dependency edges derived by `TxnGraph` and their JSON and DOT exports.
+
+use maelstrom_client::{
+    message::{Key, Transaction, Value},
+    txn_graph::TxnGraph,
+};
+use serde_json::json;
+
+fn read(key: u64) -> Transaction {
+    Transaction::Read {
+        key: Key::Int(key),
+        val: Value::None,
+    }
+}
+
+fn append(key: u64) -> Transaction {
+    Transaction::Append {
+        key: Key::Int(key),
+        value: 1,
+    }
+}
+
+// t0 appends to 1, t1 reads it and appends to 2, t2 overwrites 1 after t1
+// read it, t3 aborts on a conflict and t4 appends to 2 again
+fn graph() -> TxnGraph {
+    let graph = TxnGraph::new(Some("unused".to_owned()));
+    graph.record(&[append(1)], false);
+    graph.record(&[read(1), append(2)], false);
+    graph.record(&[append(1)], false);
+    graph.record(&[read(2), append(1)], true);
+    graph.record(&[append(2)], false);
+    graph
+}
+
+#[test]
+fn edges_follow_local_commit_order() {
+    let edges: Vec<_> = graph()
+        .edges()
+        .into_iter()
+        .map(|edge| (edge.from, edge.to, edge.kind, edge.key))
+        .collect();
+    assert_eq!(
+        edges,
+        [
+            (0, 1, "wr", Key::Int(1)),
+            (0, 2, "ww", Key::Int(1)),
+            (1, 2, "rw", Key::Int(1)),
+            // the aborted t3 read but installed nothing, so no ww from it and
+            // no rw to t4
+            (1, 3, "wr", Key::Int(2)),
+            (1, 4, "ww", Key::Int(2)),
+        ]
+    );
+}
+
+#[test]
+fn dot_marks_conflicts_and_labels_edges() {
+    let dot = graph().to_dot("n1");
+    assert!(dot.starts_with("digraph \"txns-n1\" {\n"), "{dot}");
+    assert!(dot.contains("  t3 [label=\"t3 r[2] w[1]\", color=red];\n"));
+    assert!(dot.contains("  t0 [label=\"t0 r[] w[1]\", color=black];\n"));
+    assert!(dot.contains("  t1 -> t2 [label=\"rw 1\"];\n"));
+    assert_eq!(dot.matches(" -> ").count(), 5);
+    assert!(dot.ends_with("}\n"));
+}
+
+#[test]
+fn dot_joins_keys_and_escapes_labels() {
+    let graph = TxnGraph::new(Some("unused".to_owned()));
+    let quoted = Key::String("a\"b\\".to_owned());
+    let txn = [
+        Transaction::Append {
+            key: quoted.to_owned(),
+            value: 1,
+        },
+        append(2),
+    ];
+    graph.record(&txn, false);
+    graph.record(&txn, false);
+
+    let dot = graph.to_dot("n1");
+    assert!(
+        dot.contains("  t0 [label=\"t0 r[] w[a\\\"b\\\\,2]\", color=black];\n"),
+        "{dot}"
+    );
+    assert!(
+        dot.contains("  t0 -> t1 [label=\"ww a\\\"b\\\\\"];\n"),
+        "{dot}"
+    );
+}
+
+#[test]
+fn json_holds_the_transactions_and_edges() {
+    let export: serde_json::Value = serde_json::from_str(&graph().to_json("n1").unwrap()).unwrap();
+    assert_eq!(export["node_id"], "n1");
+    assert_eq!(export["txns"].as_array().unwrap().len(), 5);
+    assert_eq!(
+        export["txns"][3],
+        json!({"id": 3, "reads": [2], "writes": [1], "conflict": true})
+    );
+    assert_eq!(
+        export["edges"][2],
+        json!({"from": 1, "to": 2, "kind": "rw", "key": 1})
+    );
+}
+
+#[test]
+fn disabled_graph_records_nothing() {
+    let graph = TxnGraph::new(None);
+    graph.record(&[append(1)], false);
+    graph.record(&[read(1)], false);
+    assert!(graph.edges().is_empty());
+    assert_eq!(graph.to_dot("n1"), "digraph \"txns-n1\" {\n}\n");
+}